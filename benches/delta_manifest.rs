@@ -0,0 +1,111 @@
+//! Benchmarks delta manifest generation on synthetic build manifests.
+//!
+//! Run with `cargo bench --bench delta_manifest`.
+
+use std::time::{Duration, Instant};
+
+use freecarnival::manifest::{
+    delta_manifest, BuildManifest, BuildManifestRecord, BuildManifestWriter, ChangeTag,
+};
+
+const ITERATIONS: u32 = 5;
+
+fn record(file_name: String, sha: String, size_in_bytes: usize) -> BuildManifestRecord {
+    BuildManifestRecord {
+        size_in_bytes,
+        chunks: 1,
+        sha,
+        flags: 0,
+        file_name,
+        tag: None,
+        copy_source: None,
+    }
+}
+
+fn write_manifest(records: &[BuildManifestRecord]) -> Vec<u8> {
+    let mut writer = BuildManifestWriter::new(vec![]).unwrap();
+    for record in records {
+        writer.write(record).unwrap();
+    }
+    writer.into_inner().unwrap()
+}
+
+/// Generates an old build with `files` files, and a new build where 5% of the files were
+/// modified, 2% moved, 1% removed and 1% added. Both are returned as CSV, like they're
+/// downloaded.
+fn generate_manifests(files: usize) -> (Vec<u8>, Vec<u8>) {
+    let old: Vec<BuildManifestRecord> = (0..files)
+        .map(|i| {
+            record(
+                format!("Game\\Content\\Folder{}\\File{}.pak", i % 100, i),
+                format!("{:064x}", i),
+                1024 + i,
+            )
+        })
+        .collect();
+
+    let mut new = Vec::with_capacity(files);
+    for (i, entry) in old.iter().enumerate() {
+        match i % 100 {
+            0..=4 => new.push(BuildManifestRecord {
+                sha: format!("{:064x}", files + i),
+                ..entry.clone()
+            }),
+            5 | 6 => new.push(BuildManifestRecord {
+                file_name: format!("Game\\Moved\\File{}.pak", i),
+                ..entry.clone()
+            }),
+            7 => {}
+            8 => {
+                new.push(entry.clone());
+                new.push(record(
+                    format!("Game\\New\\File{}.pak", i),
+                    format!("{:064x}", 2 * files + i),
+                    2048,
+                ));
+            }
+            _ => new.push(entry.clone()),
+        }
+    }
+
+    (write_manifest(&old), write_manifest(&new))
+}
+
+fn bench(files: usize) {
+    let (old, new) = generate_manifests(files);
+    let mut total = Duration::ZERO;
+    let mut delta = vec![];
+
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        delta = delta_manifest(&old, &new).unwrap();
+        total += start.elapsed();
+    }
+
+    let mut changes = [0usize; 4];
+    for record in BuildManifest::new(&delta[..]).unwrap() {
+        let record = record.unwrap();
+        match (record.tag, record.copy_source) {
+            (_, Some(_)) => changes[2] += 1,
+            (Some(ChangeTag::Added), None) => changes[0] += 1,
+            (Some(ChangeTag::Modified), None) => changes[1] += 1,
+            (Some(ChangeTag::Removed), None) | (None, None) => changes[3] += 1,
+        }
+    }
+
+    println!(
+        "{:>7} files: {:>10.2?}/iter ({} added, {} modified, {} copied, {} removed)",
+        files,
+        total / ITERATIONS,
+        changes[0],
+        changes[1],
+        changes[2],
+        changes[3]
+    );
+}
+
+fn main() {
+    for files in [1_000, 10_000, 100_000, 250_000] {
+        bench(files);
+    }
+}
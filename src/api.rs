@@ -1,24 +1,82 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use reqwest_cookie_store::CookieStoreMutex;
 
-use crate::constants::DEFAULT_HEADERS;
+use crate::{
+    config::SettingsConfig,
+    constants::{DEFAULT_BASE_URL, DEFAULT_CONTENT_URL, DEFAULT_DEV_URL, DEFAULT_HEADERS},
+};
 
 pub(crate) mod auth;
 pub(crate) mod product;
 
-pub(crate) trait GalaClient {
-    fn with_gala(cookie_store: &Arc<CookieStoreMutex>) -> Self;
+/// URLs of the IndieGala services requests are sent to
+#[derive(Debug, Clone)]
+pub struct Endpoints {
+    /// The IndieGala website, used to login and sync the library
+    pub base_url: String,
+    /// The content CDN builds are downloaded from
+    pub content_url: String,
+    /// The developer API, used to fetch game details
+    pub dev_url: String,
 }
 
-impl GalaClient for reqwest::Client {
-    fn with_gala(cookie_store: &Arc<CookieStoreMutex>) -> Self {
-        reqwest::Client::builder()
-            .default_headers(DEFAULT_HEADERS.to_owned())
-            .cookie_provider(cookie_store.clone())
-            .user_agent("galaClient")
-            .use_rustls_tls()
-            .build()
-            .unwrap()
+impl Default for Endpoints {
+    /// The official IndieGala services
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            content_url: DEFAULT_CONTENT_URL.to_string(),
+            dev_url: DEFAULT_DEV_URL.to_string(),
+        }
+    }
+}
+
+impl Endpoints {
+    /// Uses the endpoints set in settings, and falls back to the official IndieGala services
+    pub fn from_settings(settings: &SettingsConfig) -> Self {
+        let resolve = |setting: &Option<String>, default: String| {
+            setting
+                .as_ref()
+                .map(|url| url.trim_end_matches('/').to_owned())
+                .unwrap_or(default)
+        };
+        let defaults = Self::default();
+
+        Self {
+            base_url: resolve(&settings.base_url, defaults.base_url),
+            content_url: resolve(&settings.content_url, defaults.content_url),
+            dev_url: resolve(&settings.dev_url, defaults.dev_url),
+        }
+    }
+}
+
+/// HTTP client along with the endpoints it talks to
+#[derive(Clone)]
+pub(crate) struct ApiClient {
+    pub(crate) http: reqwest::Client,
+    pub(crate) endpoints: Arc<Endpoints>,
+    /// How long to wait for a server to respond before giving up
+    pub(crate) timeout: Duration,
+}
+
+impl ApiClient {
+    pub(crate) fn new(
+        cookie_store: &Arc<CookieStoreMutex>,
+        endpoints: Endpoints,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .default_headers(DEFAULT_HEADERS.to_owned())
+                .cookie_provider(cookie_store.clone())
+                .connect_timeout(timeout)
+                .user_agent("galaClient")
+                .use_rustls_tls()
+                .build()
+                .unwrap(),
+            endpoints: Arc::new(endpoints),
+            timeout,
+        }
     }
 }
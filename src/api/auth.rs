@@ -1,17 +1,21 @@
+use log::{debug, warn};
+
 use crate::{
+    api::ApiClient,
     config::{LibraryConfig, UserConfig},
-    constants::BASE_URL,
     shared::models::api::{LoginResult, SyncResult, UserInfo, UserInfoShowcaseContent},
 };
 
 pub(crate) async fn login(
-    client: &reqwest::Client,
+    client: &ApiClient,
     username: &String,
     password: &String,
 ) -> Result<Option<LoginResult>, reqwest::Error> {
     let params = [("usre", username), ("usrp", password)];
     let res = client
-        .post(format!("{}/login_new/gcl", *BASE_URL))
+        .http
+        .post(format!("{}/login_new/gcl", client.endpoints.base_url))
+        .timeout(client.timeout)
         .form(&params)
         .send()
         .await?;
@@ -23,9 +27,11 @@ pub(crate) async fn login(
     }
 }
 
-pub(crate) async fn sync(client: &reqwest::Client) -> Result<Option<SyncResult>, reqwest::Error> {
+pub(crate) async fn sync(client: &ApiClient) -> Result<Option<SyncResult>, reqwest::Error> {
     let res = client
-        .get(format!("{}/login_new/user_info", *BASE_URL))
+        .http
+        .get(format!("{}/login_new/user_info", client.endpoints.base_url))
+        .timeout(client.timeout)
         .send()
         .await?;
 
@@ -42,7 +48,7 @@ pub(crate) async fn sync(client: &reqwest::Client) -> Result<Option<SyncResult>,
                     None => vec![],
                 },
                 Err(err) => {
-                    println!("Failed to parse user library: {err:?}");
+                    warn!("Failed to parse user library: {err:?}");
                     vec![]
                 }
             };
@@ -57,7 +63,7 @@ pub(crate) async fn sync(client: &reqwest::Client) -> Result<Option<SyncResult>,
             }))
         }
         Err(_) => {
-            println!("Failed to sync data. Are you logged in?");
+            debug!("Failed to sync data. Are you logged in?");
             Ok(None)
         }
     }
@@ -1,66 +1,73 @@
-use bytes::Bytes;
+use std::future::Future;
+
+use bytes::{Bytes, BytesMut};
+use log::warn;
 
 use crate::{
-    constants::{CONTENT_URL, DEV_URL},
+    api::ApiClient,
+    error::Error,
     shared::models::api::{BuildOs, GameDetails, GameDetailsResponse, Product, ProductVersion},
 };
 
 pub(crate) async fn get_build_manifest(
-    client: &reqwest::Client,
+    client: &ApiClient,
     product: &Product,
     build_version: &ProductVersion,
-) -> Result<Bytes, reqwest::Error> {
-    let res = client
-        .get(format!(
-            "{}/DevShowCaseSourceVolume/dev_fold_{}/{}/{}/{}_manifest.csv",
-            *CONTENT_URL,
-            product.namespace,
-            product.id_key_name,
-            build_version.os,
-            build_version.version,
-        ))
-        .send()
-        .await?;
-    let body = res.bytes().await?;
-    Ok(body)
+) -> Result<Bytes, Error> {
+    let url = format!(
+        "{}/{}/{}",
+        client.endpoints.content_url,
+        build_path(product, &build_version.os),
+        manifest_file_name(build_version, "manifest"),
+    );
+    get_content(client, url).await
 }
 
 pub(crate) async fn get_build_manifest_chunks(
-    client: &reqwest::Client,
+    client: &ApiClient,
     product: &Product,
     build_version: &ProductVersion,
-) -> Result<Bytes, reqwest::Error> {
-    let res = client
-        .get(format!(
-            "{}/DevShowCaseSourceVolume/dev_fold_{}/{}/{}/{}_manifest_chunks.csv",
-            *CONTENT_URL,
-            product.namespace,
-            product.id_key_name,
-            build_version.os,
-            build_version.version,
-        ))
-        .send()
-        .await?;
-    let body = res.bytes().await?;
-    Ok(body)
+) -> Result<Bytes, Error> {
+    let url = format!(
+        "{}/{}/{}",
+        client.endpoints.content_url,
+        build_path(product, &build_version.os),
+        manifest_file_name(build_version, "manifest_chunks"),
+    );
+    get_content(client, url).await
 }
 
 pub(crate) async fn download_chunk(
-    client: &reqwest::Client,
+    client: &ApiClient,
     product: &Product,
     os: &BuildOs,
     chunk_sha: &String,
-) -> Result<Bytes, reqwest::Error> {
-    let res = client
-        .get(get_chunk_url(product, os, chunk_sha))
-        .send()
-        .await?;
-    let bytes = res.bytes().await?;
-    Ok(bytes)
+) -> Result<Bytes, Error> {
+    let url = get_chunk_url(client, product, os, chunk_sha);
+    get_content(client, url).await
+}
+
+/// Downloads a file from the content CDN, giving up if the server stops responding for longer
+/// than the client's timeout
+async fn get_content(client: &ApiClient, url: String) -> Result<Bytes, Error> {
+    let res = with_timeout(client, client.http.get(url).send()).await??;
+    let mut res = res.error_for_status()?;
+
+    let mut bytes = BytesMut::with_capacity(res.content_length().unwrap_or_default() as usize);
+    while let Some(part) = with_timeout(client, res.chunk()).await?? {
+        bytes.extend_from_slice(&part);
+    }
+    Ok(bytes.freeze())
+}
+
+async fn with_timeout<F: Future>(client: &ApiClient, future: F) -> Result<F::Output, Error> {
+    tokio::time::timeout(client.timeout, future)
+        .await
+        .map_err(|_| Error::TimedOut)
 }
 
 pub(crate) async fn get_game_details(
-    client: &reqwest::Client,
+    client: &ApiClient,
     product: &Product,
 ) -> Result<Option<GameDetails>, reqwest::Error> {
     let query = &[
@@ -68,7 +75,9 @@ pub(crate) async fn get_game_details(
         ("prod_name", &product.slugged_name),
     ];
     let res = client
-        .get(format!("{}/get_product_info", *DEV_URL))
+        .http
+        .get(format!("{}/get_product_info", client.endpoints.dev_url))
+        .timeout(client.timeout)
         .query(query)
         .send()
         .await?;
@@ -77,14 +86,14 @@ pub(crate) async fn get_game_details(
     match serde_json::from_str::<GameDetailsResponse>(&body) {
         Ok(data) => {
             if data.status != "success" {
-                println!("Server failed to deliver game details");
+                warn!("Server failed to deliver game details: {}", data.message);
                 return Ok(None);
             }
 
             Ok(Some(data.product_data))
         }
         Err(_) => {
-            println!(
+            warn!(
                 "Failed to get game details for {}. Are you logged in?",
                 product.name
             );
@@ -93,9 +102,31 @@ pub(crate) async fn get_game_details(
     }
 }
 
-fn get_chunk_url(product: &Product, os: &BuildOs, chunk_sha: &String) -> String {
+/// Directory every build is stored under on the content CDN
+pub(crate) const CONTENT_ROOT: &str = "DevShowCaseSourceVolume";
+
+/// Path of a build's manifests and chunks, relative to the root of the content CDN
+pub(crate) fn build_path(product: &Product, os: &BuildOs) -> String {
+    format!(
+        "{}/dev_fold_{}/{}/{}",
+        CONTENT_ROOT, product.namespace, product.id_key_name, os,
+    )
+}
+
+pub(crate) fn manifest_file_name(build_version: &ProductVersion, suffix: &str) -> String {
+    format!("{}_{}.csv", build_version.version, suffix)
+}
+
+fn get_chunk_url(
+    client: &ApiClient,
+    product: &Product,
+    os: &BuildOs,
+    chunk_sha: &String,
+) -> String {
     format!(
-        "{}/DevShowCaseSourceVolume/dev_fold_{}/{}/{}/{}",
-        *CONTENT_URL, product.namespace, product.id_key_name, os, chunk_sha,
+        "{}/{}/{}",
+        client.endpoints.content_url,
+        build_path(product, os),
+        chunk_sha
     )
 }
@@ -0,0 +1,69 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api,
+    shared::models::api::{BuildOs, Product, ProductVersion},
+};
+
+const INDEX_FILE_NAME: &str = "archive.json";
+
+/// Describes the builds stored in a local archive created with the `download` command.
+///
+/// Archives mirror the layout of the content CDN, so builds are installed from them by reading
+/// the same manifests and chunks from disk instead of downloading them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ArchiveIndex {
+    /// Archived games. Only the archived builds are listed in their versions.
+    pub products: Vec<Product>,
+}
+
+impl ArchiveIndex {
+    /// Loads the index of the archive at `path`. Returns an empty index if there's no archive
+    /// there yet.
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let index_path = path.join(INDEX_FILE_NAME);
+        if !index_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read(index_path)?;
+        Ok(serde_json::from_slice(&contents)?)
+    }
+
+    pub fn store(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(path)?;
+        let contents = serde_json::to_vec_pretty(self)?;
+        std::fs::write(path.join(INDEX_FILE_NAME), contents)
+    }
+
+    /// Adds a build to the index, replacing it if it was already archived
+    pub(crate) fn insert(&mut self, product: &Product, build_version: &ProductVersion) {
+        let index = match self
+            .products
+            .iter()
+            .position(|p| p.slugged_name == product.slugged_name)
+        {
+            Some(index) => index,
+            None => {
+                self.products.push(Product {
+                    version: vec![],
+                    ..product.clone()
+                });
+                self.products.len() - 1
+            }
+        };
+
+        let archived = &mut self.products[index];
+        archived
+            .version
+            .retain(|v| v.version != build_version.version || v.os != build_version.os);
+        archived.version.push(build_version.clone());
+    }
+}
+
+/// Directory holding the manifests and chunks of a product's builds for `os`
+pub(crate) fn build_dir(archive_path: &Path, product: &Product, os: &BuildOs) -> PathBuf {
+    archive_path.join(api::product::build_path(product, os))
+}
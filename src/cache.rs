@@ -0,0 +1,212 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::SystemTime,
+};
+
+use bytes::Bytes;
+use directories::ProjectDirs;
+use log::warn;
+use tokio::sync::Mutex;
+
+use crate::{
+    config::SettingsConfig,
+    constants::{DEFAULT_CHUNK_CACHE_SIZE, PROJECT_NAME},
+    helpers::{chunk_hash, is_valid_chunk_sha, verify_chunk},
+};
+
+/// On-disk cache of downloaded chunks, shared between games and builds.
+///
+/// Chunks are stored by their SHA, so the same chunk is only ever downloaded once while it's
+/// cached. Only verified chunks are inserted, and every chunk is verified again when read. The
+/// least recently used chunks are evicted once the cache grows over its size limit.
+pub struct ChunkCache {
+    path: PathBuf,
+    max_size: u64,
+    size: AtomicU64,
+    pruning: Mutex<()>,
+    /// Used to give every temporary file a unique name
+    tmp_count: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+pub struct CacheStats {
+    pub chunks: u64,
+    pub size: u64,
+}
+
+impl CacheStats {
+    fn of(chunks: &[(PathBuf, u64, SystemTime)]) -> Self {
+        Self {
+            chunks: chunks.len() as u64,
+            size: chunks.iter().map(|(_, size, _)| size).sum(),
+        }
+    }
+}
+
+impl ChunkCache {
+    /// Opens the chunk cache if it's enabled in settings
+    pub(crate) fn from_settings(settings: &SettingsConfig) -> Option<Self> {
+        if !settings.chunk_cache {
+            return None;
+        }
+
+        let path = Self::default_path();
+        if let Err(err) = std::fs::create_dir_all(&path) {
+            warn!(
+                "Failed to create chunk cache. Caching is disabled: {:?}",
+                err
+            );
+            return None;
+        }
+        let size = Self::stats(&path).map(|s| s.size).unwrap_or_default();
+
+        Some(Self {
+            path,
+            max_size: settings
+                .chunk_cache_max_size
+                .unwrap_or(*DEFAULT_CHUNK_CACHE_SIZE),
+            size: AtomicU64::new(size),
+            pruning: Mutex::new(()),
+            tmp_count: AtomicU64::new(0),
+        })
+    }
+
+    pub fn default_path() -> PathBuf {
+        let project = ProjectDirs::from("rs", "", *PROJECT_NAME).unwrap();
+        project.cache_dir().join("chunks")
+    }
+
+    /// Reads a chunk from the cache. Chunks that fail verification are removed.
+    pub(crate) async fn get(&self, sha: &str) -> Option<Bytes> {
+        let (path, hash) = (self.chunk_path(sha)?, chunk_hash(sha)?);
+        let chunk = Bytes::from(tokio::fs::read(&path).await.ok()?);
+
+        if !verify_chunk(&chunk, hash) {
+            warn!("Cached chunk {} is corrupted. Downloading it again...", sha);
+            self.remove(&path).await;
+            return None;
+        }
+
+        // Reading a chunk marks it as recently used
+        let _ = tokio::task::spawn_blocking(move || {
+            std::fs::File::options()
+                .write(true)
+                .open(&path)?
+                .set_modified(SystemTime::now())
+        })
+        .await;
+
+        Some(chunk)
+    }
+
+    /// Stores a chunk, evicting old chunks if the cache grows over its size limit. The chunk
+    /// must already be verified.
+    pub(crate) async fn insert(&self, sha: &str, chunk: &Bytes) {
+        let path = match self.chunk_path(sha) {
+            Some(path) => path,
+            None => return,
+        };
+        if tokio::fs::try_exists(&path).await.unwrap_or(true) {
+            return;
+        }
+
+        // Write to a temporary file first, so a partially written chunk is never read. Installs
+        // running at the same time can insert the same chunk, so each one gets its own file.
+        let tmp_path = self.path.join(format!(
+            "{}.{}-{}.tmp",
+            sha,
+            std::process::id(),
+            self.tmp_count.fetch_add(1, Ordering::Relaxed)
+        ));
+        if tokio::fs::write(&tmp_path, chunk).await.is_err()
+            || tokio::fs::rename(&tmp_path, &path).await.is_err()
+        {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+            return;
+        }
+
+        let size = self.size.fetch_add(chunk.len() as u64, Ordering::SeqCst) + chunk.len() as u64;
+        if size > self.max_size {
+            if let Ok(_guard) = self.pruning.try_lock() {
+                let path = self.path.clone();
+                let max_size = self.max_size;
+                if let Ok(Ok(stats)) =
+                    tokio::task::spawn_blocking(move || Self::prune(&path, max_size)).await
+                {
+                    self.size.store(stats.size, Ordering::SeqCst);
+                }
+            }
+        }
+    }
+
+    async fn remove(&self, path: &Path) {
+        if let Ok(metadata) = tokio::fs::metadata(path).await {
+            if tokio::fs::remove_file(path).await.is_ok() {
+                self.size.fetch_sub(metadata.len(), Ordering::SeqCst);
+            }
+        }
+    }
+
+    fn chunk_path(&self, sha: &str) -> Option<PathBuf> {
+        // Chunk SHAs end up in file names, so make sure they can't point outside of the cache
+        if !is_valid_chunk_sha(sha) {
+            return None;
+        }
+
+        Some(self.path.join(sha))
+    }
+
+    /// Lists cached chunks with their size and last time they were used
+    fn chunks(path: &Path) -> std::io::Result<Vec<(PathBuf, u64, SystemTime)>> {
+        let mut chunks = vec![];
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                chunks.push((entry.path(), metadata.len(), metadata.modified()?));
+            }
+        }
+
+        Ok(chunks)
+    }
+
+    pub fn stats(path: &Path) -> std::io::Result<CacheStats> {
+        if !path.exists() {
+            return Ok(CacheStats::default());
+        }
+
+        Ok(CacheStats::of(&Self::chunks(path)?))
+    }
+
+    /// Removes the least recently used chunks until the cache fits in `max_size`. Returns the
+    /// stats of the cache after pruning.
+    pub fn prune(path: &Path, max_size: u64) -> std::io::Result<CacheStats> {
+        if !path.exists() {
+            return Ok(CacheStats::default());
+        }
+
+        let mut chunks = Self::chunks(path)?;
+        chunks.sort_by_key(|(_, _, modified)| *modified);
+        let mut stats = CacheStats::of(&chunks);
+
+        for (chunk_path, size, _) in chunks {
+            if stats.size <= max_size {
+                break;
+            }
+            std::fs::remove_file(chunk_path)?;
+            stats.chunks -= 1;
+            stats.size -= size;
+        }
+
+        Ok(stats)
+    }
+
+    pub fn clear(path: &Path) -> std::io::Result<()> {
+        if path.exists() {
+            std::fs::remove_dir_all(path)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,8 +1,15 @@
-use std::path::PathBuf;
+use std::{net::SocketAddr, path::PathBuf};
 
+use chrono::NaiveTime;
 use clap::{Args, Parser, Subcommand, ValueEnum};
 
-use crate::{constants::*, shared::models::api::BuildOs};
+use freecarnival::{
+    config::SettingsConfig,
+    constants::*,
+    models::BuildOs,
+    search::{LibraryQuery, LibrarySort},
+    Endpoints, InstallOptions,
+};
 
 /// Native cross-platform indieGala client
 #[derive(Parser, Debug)]
@@ -15,6 +22,16 @@ use crate::{constants::*, shared::models::api::BuildOs};
 pub(crate) struct Cli {
     #[command(subcommand)]
     pub(crate) command: Commands,
+    /// Control socket of the background daemon. Commands that a running daemon can handle are
+    /// forwarded to it.
+    #[cfg(unix)]
+    #[arg(long, global = true, env = "FREECARNIVAL_SOCKET")]
+    pub(crate) socket: Option<PathBuf>,
+    #[command(flatten)]
+    pub(crate) network: NetworkOpts,
+    /// Print debug messages
+    #[arg(long, global = true)]
+    pub(crate) verbose: bool,
 }
 
 impl Cli {
@@ -26,8 +43,31 @@ impl Cli {
                 email: _,
                 password: _,
             } | Commands::Logout
-                | Commands::Uninstall { slug: _, keep: _ }
-                | Commands::Verify { slug: _ }
+                | Commands::Uninstall {
+                    slug: _,
+                    keep: _,
+                    label: _
+                }
+                | Commands::Verify { slug: _, label: _ }
+                | Commands::Hold {
+                    slug: _,
+                    version: _,
+                    label: _
+                }
+                | Commands::Unhold { slug: _, label: _ }
+                | Commands::Queue {
+                    command: QueueCommands::List
+                        | QueueCommands::Pause { slug: _, label: _ }
+                        | QueueCommands::Remove { slug: _, label: _ }
+                }
+                | Commands::Install {
+                    from_archive: Some(_),
+                    ..
+                }
+                | Commands::Serve { root: _, bind: _ }
+                | Commands::Settings { command: _ }
+                | Commands::Cache { command: _ }
+                | Commands::Manifest { command: _ }
         )
     }
 }
@@ -44,12 +84,25 @@ pub(crate) enum Commands {
     /// Logout from your indieGala account
     Logout,
     /// List your library
-    Library,
-    /// Install a game from your library
+    Library {
+        #[command(flatten)]
+        query: LibraryOpts,
+    },
+    /// Install one or more games from your library
+    ///
+    /// Games are added to the download queue and installed in order of priority. If the
+    /// program is interrupted, run `queue resume` to continue where you left off.
     Install {
-        /// The slug of the game e.g. syberia-ii
-        slug: String,
+        /// The slugs of the games e.g. syberia-ii
+        #[arg(required_unless_present = "from_file")]
+        slugs: Vec<String>,
+        /// Read the slugs to install from a file, one per line. Empty lines and lines starting
+        /// with `#` are ignored.
+        #[arg(long)]
+        from_file: Option<PathBuf>,
         /// Install specific build version. If ommited, the latest build version will be installed.
+        ///
+        /// Can only be used when installing a single game.
         #[arg(long, short)]
         version: Option<String>,
         /// Base install path. The game will be installed in a subdirectory with the game's slugged
@@ -58,11 +111,46 @@ pub(crate) enum Commands {
         base_path: Option<PathBuf>,
         /// Exact install path. The game will be installed in the selected directory without
         /// creating additional subdirectories.
+        ///
+        /// Can only be used when installing a single game.
         #[arg(long)]
         path: Option<PathBuf>,
         /// The build target OS to install
         #[arg(long)]
         os: Option<BuildOs>,
+        /// Install the game next to its other installs, e.g. to keep both its Windows and Linux
+        /// builds or a beta build. Other commands pick the install with --label.
+        #[arg(long, value_parser = parse_label)]
+        label: Option<String>,
+        /// Queue priority. Games with a higher priority are downloaded first.
+        #[arg(long, default_value_t = 0)]
+        priority: i32,
+        /// Install from a local archive created with the `download` command. Nothing is
+        /// downloaded, so this works offline.
+        #[arg(long)]
+        from_archive: Option<PathBuf>,
+        #[command(flatten)]
+        queue_opts: QueueOpts,
+        #[command(flatten)]
+        install_opts: InstallOpts,
+    },
+    /// Download a build into a local archive without installing it
+    ///
+    /// Builds are installed from the archive with `install --from-archive`. Several games and
+    /// builds can be kept in the same archive, and interrupted downloads are resumed.
+    Download {
+        /// The slug of the game e.g. syberia-ii
+        slug: String,
+        /// Download specific build version. If ommited, the latest build version will be
+        /// downloaded.
+        #[arg(long, short)]
+        version: Option<String>,
+        /// The build target OS to download
+        #[arg(long)]
+        os: Option<BuildOs>,
+        /// Archive directory
+        #[arg(long)]
+        to: PathBuf,
         #[command(flatten)]
         install_opts: InstallOpts,
     },
@@ -73,9 +161,32 @@ pub(crate) enum Commands {
         /// Remove game from installed config but do not delete install folder.
         #[arg(long)]
         keep: bool,
+        /// Install to pick when the game is installed several times: its label, or its OS
+        #[arg(long)]
+        label: Option<String>,
     },
     /// Lists available updates for installed games.
     ListUpdates,
+    /// Hold an installed game at a version, so it isn't updated past it
+    Hold {
+        /// The slug of the game e.g. syberia-ii
+        slug: String,
+        /// Version to hold the game at. Updates up to this version are still offered. Holds the
+        /// game at the installed version if not set.
+        #[arg(long, short)]
+        version: Option<String>,
+        /// Install to pick when the game is installed several times: its label, or its OS
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// Let a held game be updated again
+    Unhold {
+        /// The slug of the game e.g. syberia-ii
+        slug: String,
+        /// Install to pick when the game is installed several times: its label, or its OS
+        #[arg(long)]
+        label: Option<String>,
+    },
     /// Update (or downgrade) an installed game.
     Update {
         /// The slug of the game e.g. syberia-ii
@@ -86,6 +197,9 @@ pub(crate) enum Commands {
         /// You can get a list of available versions by using the `info` command.
         #[arg(long, short)]
         version: Option<String>,
+        /// Install to pick when the game is installed several times: its label, or its OS
+        #[arg(long)]
+        label: Option<String>,
         #[command(flatten)]
         install_opts: InstallOpts,
     },
@@ -93,6 +207,9 @@ pub(crate) enum Commands {
     Launch {
         /// The slug of the game e.g. syberia-ii
         slug: String,
+        /// Install to pick when the game is installed several times: its label, or its OS
+        #[arg(long)]
+        label: Option<String>,
         /// The WINE prefix to use for this game
         #[cfg(not(target_os = "windows"))]
         #[arg(long)]
@@ -107,18 +224,285 @@ pub(crate) enum Commands {
         /// The slug of the game e.g. syberia-ii
         slug: String,
     },
+    /// List every build of a game, newest first
+    Versions {
+        /// The slug of the game e.g. syberia-ii
+        slug: String,
+        /// Only list builds for this OS
+        #[arg(long)]
+        os: Option<BuildOs>,
+    },
+    /// Compare the files of two builds of a game, e.g. before downgrading with
+    /// `update --version`
+    Diff {
+        /// The slug of the game e.g. syberia-ii
+        slug: String,
+        /// Version to compare from
+        from: String,
+        /// Version to compare to
+        to: String,
+        /// OS of the builds. Uses the first OS in your preference order both builds exist for
+        /// if not set.
+        #[arg(long)]
+        os: Option<BuildOs>,
+    },
     /// Verify file integrity for an installed game
     Verify {
         /// The slug of the game e.g. syberia-ii
         slug: String,
+        /// Install to pick when the game is installed several times: its label, or its OS
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// Manage the download queue
+    Queue {
+        #[command(subcommand)]
+        command: QueueCommands,
+    },
+    /// View or change settings. Changes apply to running downloads within a few seconds.
+    Settings {
+        #[command(subcommand)]
+        command: SettingsCommands,
+    },
+    /// Manage the local chunk cache
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+    /// Inspect the build manifest and chunk manifest of a build
+    Manifest {
+        #[command(subcommand)]
+        command: ManifestCommands,
+    },
+    /// Serve a local archive as a mirror of the content CDN
+    ///
+    /// Manifests and chunks are served at the same paths as on the CDN, so other machines on
+    /// your network can install the archived builds from it.
+    Serve {
+        /// Archive directory created with the `download` command
+        #[arg(long)]
+        root: PathBuf,
+        /// Address to listen on. Use e.g. 0.0.0.0:8080 to make the mirror available to other
+        /// machines.
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        bind: SocketAddr,
+    },
+    /// Browse and manage your library in an interactive terminal UI
+    Tui {
+        /// Base install path. Games are installed in a subdirectory with the game's slugged
+        /// name.
+        #[arg(long)]
+        base_path: Option<PathBuf>,
+        /// Wine binary used to launch Windows games
+        #[cfg(not(target_os = "windows"))]
+        #[arg(long)]
+        wine_bin: Option<PathBuf>,
+        /// Wine prefix used to launch Windows games
+        #[cfg(not(target_os = "windows"))]
+        #[arg(long)]
+        wine_prefix: Option<PathBuf>,
+        #[command(flatten)]
+        queue_opts: QueueOpts,
+        #[command(flatten)]
+        install_opts: InstallOpts,
+    },
+    /// Keep a session and the download queue running in the background
+    ///
+    /// The daemon is controlled with JSON-RPC over a Unix domain socket. While it's running,
+    /// `library`, `install`, `queue list`, `queue pause` and `launch` are forwarded to it, and
+    /// queued games are installed by the daemon.
+    #[cfg(unix)]
+    Daemon {
+        /// Wine binary used to launch Windows games
+        #[arg(long)]
+        wine_bin: Option<PathBuf>,
+        /// Wine prefix used to launch Windows games
+        #[arg(long)]
+        wine_prefix: Option<PathBuf>,
+        #[command(flatten)]
+        queue_opts: QueueOpts,
+        #[command(flatten)]
+        install_opts: InstallOpts,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum CacheCommands {
+    /// Print how many chunks are cached and how much space they use
+    Stats,
+    /// Delete every cached chunk
+    Clear,
+    /// Delete the least recently used chunks until the cache fits in its size limit
+    Prune {
+        /// Size to prune the cache down to e.g. 5GiB. Defaults to the cache size limit.
+        #[arg(long, value_parser = parse_size)]
+        max_size: Option<u64>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum ManifestCommands {
+    /// List the files of a build with their size, flags, chunk count and SHA
+    Show {
+        #[command(flatten)]
+        build: ManifestBuildOpts,
+        /// Only list files matching a glob pattern e.g. "data/**/*.pak"
+        #[arg(long, value_parser = parse_glob)]
+        glob: Option<glob::Pattern>,
+        /// Print the files and their chunks as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check the chunk manifest of a build covers every file of its build manifest
+    Check {
+        #[command(flatten)]
+        build: ManifestBuildOpts,
+    },
+    /// Delete the stored manifests of builds that aren't installed anymore
+    Prune,
+}
+
+/// Build to inspect the manifests of. The manifests stored for an install are used when they
+/// match, the manifests of other builds are fetched.
+#[derive(Debug, Args)]
+pub(crate) struct ManifestBuildOpts {
+    /// The slug of the game e.g. syberia-ii
+    pub(crate) slug: String,
+    /// Build version. Defaults to the installed build, or to the latest build if the game isn't
+    /// installed.
+    #[arg(long, short)]
+    pub(crate) version: Option<String>,
+    /// The build target OS
+    #[arg(long)]
+    pub(crate) os: Option<BuildOs>,
+    /// Install to pick when the game is installed several times: its label, or its OS
+    #[arg(long)]
+    pub(crate) label: Option<String>,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub(crate) enum Service {
+    /// The IndieGala website
+    Base,
+    /// The content CDN
+    Content,
+    /// The developer API
+    Dev,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub(crate) enum Toggle {
+    On,
+    Off,
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum SettingsCommands {
+    /// Print the current settings
+    Show,
+    /// Limit the download rate of all downloads
+    Limit {
+        /// The rate limit e.g. 5MiB/s. Use `off` to remove the limit.
+        rate: String,
+    },
+    /// Only download at full speed between START and END
+    Schedule {
+        /// When downloads are allowed to start e.g. 01:00
+        #[arg(value_parser = parse_time, required_unless_present = "off")]
+        start: Option<NaiveTime>,
+        /// When downloads should stop e.g. 07:00
+        #[arg(value_parser = parse_time, required_unless_present = "off")]
+        end: Option<NaiveTime>,
+        /// Rate limit to use outside of the schedule e.g. 500KiB/s. Downloads are paused
+        /// outside of the schedule if not set.
+        #[arg(long, value_parser = parse_rate)]
+        outside_limit: Option<u64>,
+        /// Remove the download schedule
+        #[arg(long, conflicts_with_all = ["start", "end", "outside_limit"])]
+        off: bool,
+    },
+    /// Change the URL of an IndieGala service, e.g. to download from a local mirror
+    Endpoint {
+        /// The service to change
+        service: Service,
+        /// The new URL. Use `default` to go back to the official service.
+        url: String,
+    },
+    /// Keep downloaded chunks in a local cache, so reinstalls, downgrades and installs of the
+    /// same build somewhere else don't have to download them again
+    ChunkCache {
+        /// Turn the chunk cache on or off
+        state: Toggle,
+        /// Maximum size of the cache e.g. 20GiB. The least recently used chunks are removed
+        /// when the cache grows over this size.
+        #[arg(long, value_parser = parse_size)]
+        max_size: Option<u64>,
+    },
+    /// Change the order OSes are tried in when installing a game without --os. By default,
+    /// native builds are picked over Windows builds.
+    OsPreference {
+        /// OSes in order of preference e.g. linux windows. Builds for other OSes are only
+        /// installed when selected with --os.
+        #[arg(required_unless_present = "reset")]
+        os: Vec<BuildOs>,
+        /// Only change the preference of this game
+        #[arg(long)]
+        game: Option<String>,
+        /// Go back to the default order
+        #[arg(long, conflicts_with = "os")]
+        reset: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum QueueCommands {
+    /// List queued downloads in the order they will be installed
+    List,
+    /// Pause a queued download. If the game is currently downloading, the download is stopped.
+    Pause {
+        /// The slug of the game e.g. syberia-ii
+        slug: String,
+        /// Download to pick when the game is queued several times: its label, or its OS
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// Resume paused or failed downloads and process the queue
+    Resume {
+        /// The slug of the game to resume. If ommited, every paused or failed download is
+        /// resumed.
+        slug: Option<String>,
+        /// Download to pick when the game is queued several times: its label, or its OS
+        #[arg(long, requires = "slug")]
+        label: Option<String>,
+        #[command(flatten)]
+        queue_opts: QueueOpts,
+        #[command(flatten)]
+        install_opts: InstallOpts,
+    },
+    /// Remove a game from the download queue
+    Remove {
+        /// The slug of the game e.g. syberia-ii
+        slug: String,
+        /// Download to pick when the game is queued several times: its label, or its OS
+        #[arg(long)]
+        label: Option<String>,
     },
 }
 
 #[derive(Debug, Args)]
+pub(crate) struct QueueOpts {
+    /// How many games to download at the same time. All games share the same
+    /// download worker and memory limits.
+    #[arg(long, default_value_t = 1)]
+    pub(crate) max_parallel_installs: usize,
+}
+
+#[derive(Debug, Clone, Args)]
 pub(crate) struct InstallOpts {
     /// How many download workers to run at one time.
     /// Increasing this value will make downloads faster, but use more memory.
     /// Lowering this value will lower memory usage at the cost of slower downloads.
+    /// When installing several games at once, this limit is shared between them.
     ///
     /// Note: Too many download workers can cause unreliable downloads. The default is
     /// double your CPU_COUNT. You shouldn't deviate too much from this.
@@ -127,6 +511,7 @@ pub(crate) struct InstallOpts {
     /// How much memory to use to store chunks. Lowering this value will potentially make
     /// downloads slower while being lighter on memory usage. Raising it will make the program
     /// use more memory if needed, but can potentially speed up downloads.
+    /// When installing several games at once, this limit is shared between them.
     #[arg(long, default_value_t = *DEFAULT_MAX_MEMORY_USAGE)]
     pub(crate) max_memory_usage: usize,
     /// Print download info instead of installing game.
@@ -136,33 +521,174 @@ pub(crate) struct InstallOpts {
     /// corrupted/tampered files.
     #[arg(long)]
     pub(crate) skip_verify: bool,
+    /// Install even if there isn't enough free disk space.
+    #[arg(long)]
+    pub(crate) force: bool,
+    /// Allocate every file to its final size before downloading. Running out of disk space
+    /// will fail right away instead of in the middle of the download.
+    #[arg(long)]
+    pub(crate) preallocate: bool,
+    /// Limit the download rate of all downloads e.g. 5MiB/s. Overrides the limit set with
+    /// `settings limit` for this run.
+    #[arg(long, value_parser = parse_rate)]
+    pub(crate) limit: Option<u64>,
+    /// Allow installing builds that were disabled or pulled by the developer. They are
+    /// skipped when picking the latest build otherwise.
+    #[arg(long)]
+    pub(crate) include_disabled: bool,
+}
+
+impl From<&InstallOpts> for InstallOptions {
+    fn from(opts: &InstallOpts) -> Self {
+        Self::default()
+            .max_download_workers(opts.max_download_workers)
+            .max_memory_usage(opts.max_memory_usage)
+            .skip_verify(opts.skip_verify)
+            .force(opts.force)
+            .preallocate(opts.preallocate)
+            .limit(opts.limit)
+            .include_disabled(opts.include_disabled)
+    }
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct LibraryOpts {
+    /// Only list games whose name or slug fuzzy matches this text, best matches first
+    #[arg(long, short)]
+    pub(crate) search: Option<String>,
+    /// Only list installed games
+    #[arg(long, conflicts_with = "not_installed")]
+    pub(crate) installed: bool,
+    /// Only list games that aren't installed
+    #[arg(long)]
+    pub(crate) not_installed: bool,
+    /// Only list games with a build for this OS
+    #[arg(long)]
+    pub(crate) os: Option<BuildOs>,
+    /// Only list installed games with an update available
+    #[arg(long)]
+    pub(crate) has_updates: bool,
+    /// Only list games by this developer namespace
+    #[arg(long)]
+    pub(crate) developer: Option<String>,
+    /// Order to list games in. Defaults to the order of your library, or relevance when
+    /// searching.
+    #[arg(long, value_enum)]
+    pub(crate) sort: Option<LibrarySort>,
 }
 
-impl ValueEnum for BuildOs {
-    fn value_variants<'a>() -> &'a [Self] {
-        &[Self::Windows, Self::Mac, Self::Linux]
+impl From<&LibraryOpts> for LibraryQuery {
+    fn from(opts: &LibraryOpts) -> Self {
+        Self {
+            search: opts.search.clone(),
+            installed: match (opts.installed, opts.not_installed) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+            os: opts.os.clone(),
+            has_updates: opts.has_updates,
+            namespace: opts.developer.clone(),
+            sort: opts.sort,
+        }
     }
+}
+
+/// Overrides the URLs of the IndieGala services, e.g. to use a local mirror or a mock server
+#[derive(Debug, Args)]
+#[command(next_help_heading = "Network")]
+pub(crate) struct NetworkOpts {
+    /// URL of the IndieGala website, used to login and sync your library
+    #[arg(long, global = true, env = "FREECARNIVAL_BASE_URL", value_parser = parse_url)]
+    pub(crate) base_url: Option<String>,
+    /// URL of the content CDN builds are downloaded from, e.g. a mirror started with `serve`
+    #[arg(long, global = true, env = "FREECARNIVAL_CONTENT_URL", value_parser = parse_url)]
+    pub(crate) content_url: Option<String>,
+    /// URL of the developer API, used to fetch game details
+    #[arg(long, global = true, env = "FREECARNIVAL_DEV_URL", value_parser = parse_url)]
+    pub(crate) dev_url: Option<String>,
+    /// Seconds to wait for a server to respond before giving up
+    #[arg(long, global = true, env = "FREECARNIVAL_TIMEOUT", default_value_t = *DEFAULT_TIMEOUT)]
+    pub(crate) timeout: u64,
+}
 
-    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
-        match self {
-            Self::Windows => Some(clap::builder::PossibleValue::new("windows")),
-            Self::Mac => {
-                let possible_value = clap::builder::PossibleValue::new("mac");
-                #[cfg(not(target_os = "macos"))]
-                let possible_value = possible_value
-                    .help("You can install macOS games, but you won't be able to run them!");
-
-                Some(possible_value)
-            }
-            Self::Linux => {
-                let possible_value = clap::builder::PossibleValue::new("linux");
-                #[cfg(not(target_os = "linux"))]
-                let possible_value = possible_value.help(
-                    "You can install Linux games, but you probably won't be able to run them!",
-                );
-
-                Some(possible_value)
-            }
+impl NetworkOpts {
+    /// Uses the endpoints set with CLI flags or env vars, then the ones in settings, and falls
+    /// back to the official IndieGala services
+    pub(crate) fn endpoints(&self, settings: &SettingsConfig) -> Endpoints {
+        let mut endpoints = Endpoints::from_settings(settings);
+        if let Some(base_url) = &self.base_url {
+            endpoints.base_url = base_url.to_owned();
+        }
+        if let Some(content_url) = &self.content_url {
+            endpoints.content_url = content_url.to_owned();
+        }
+        if let Some(dev_url) = &self.dev_url {
+            endpoints.dev_url = dev_url.to_owned();
         }
+        endpoints
+    }
+}
+
+/// Parses a transfer rate like `5MiB/s`, `500K` or `1048576` into bytes per second
+pub(crate) fn parse_rate(rate: &str) -> Result<u64, String> {
+    let rate = rate.trim();
+    parse_size(rate.strip_suffix("/s").unwrap_or(rate))
+}
+
+/// Parses a size like `10GiB`, `500K` or `1048576` into bytes
+pub(crate) fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let unit_start = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (value, unit) = size.split_at(unit_start);
+    let value: f64 = value
+        .parse()
+        .map_err(|_| format!("{size} is not a valid size"))?;
+    let multiplier: u64 = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024 * 1024,
+        "g" | "gb" | "gib" => 1024 * 1024 * 1024,
+        _ => return Err(format!("Unknown unit {unit}. Use B, KiB, MiB or GiB")),
+    };
+
+    Ok((value * multiplier as f64) as u64)
+}
+
+/// Checks that a URL is a valid http(s) URL and removes its trailing slashes
+pub(crate) fn parse_url(url: &str) -> Result<String, String> {
+    let parsed =
+        reqwest::Url::parse(url).map_err(|err| format!("{url} is not a valid URL: {err}"))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("{url} is not an http or https URL"));
+    }
+
+    Ok(url.trim_end_matches('/').to_owned())
+}
+
+fn parse_glob(pattern: &str) -> Result<glob::Pattern, String> {
+    glob::Pattern::new(pattern).map_err(|err| format!("{pattern} is not a valid glob: {err}"))
+}
+
+/// Checks a label is usable as part of a directory name
+fn parse_label(label: &str) -> Result<String, String> {
+    let valid = label
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if label.is_empty() || label.starts_with('.') || !valid {
+        return Err(format!(
+            "{label} is not a valid label. Use letters, digits, -, _ and ."
+        ));
     }
+
+    Ok(label.to_owned())
+}
+
+/// Parses a time of day like `23:00` or `23:00:00`
+fn parse_time(time: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M:%S"))
+        .map_err(|_| format!("{time} is not a valid time. Use HH:MM e.g. 23:00"))
 }
@@ -0,0 +1,85 @@
+use std::{sync::Arc, time::Duration};
+
+use reqwest_cookie_store::CookieStoreMutex;
+
+use crate::{
+    api::{self, ApiClient, Endpoints},
+    config::{CookieConfig, GalaConfig, LibraryConfig, UserConfig},
+    error::Error,
+    shared::models::api::{GameDetails, Product, SyncResult},
+};
+
+/// A session with the IndieGala services.
+///
+/// The session cookies saved by the last session are loaded when the client is created. Call
+/// [`GalaClient::save`] before dropping it to keep the user logged in.
+#[derive(Clone)]
+pub struct GalaClient {
+    pub(crate) api: ApiClient,
+    cookie_store: Arc<CookieStoreMutex>,
+}
+
+impl GalaClient {
+    /// Creates a session that sends requests to `endpoints`, giving up on servers that don't
+    /// respond within `timeout`
+    pub fn new(endpoints: Endpoints, timeout: Duration) -> Result<Self, Error> {
+        let CookieConfig(cookie_store) = CookieConfig::load()?;
+        let cookie_store = Arc::new(CookieStoreMutex::new(cookie_store));
+
+        Ok(Self {
+            api: ApiClient::new(&cookie_store, endpoints, timeout),
+            cookie_store,
+        })
+    }
+
+    pub fn endpoints(&self) -> &Endpoints {
+        &self.api.endpoints
+    }
+
+    /// Logs in with an IndieGala account. The library isn't synced until [`GalaClient::sync`]
+    /// is called.
+    pub async fn login(&self, email: &String, password: &String) -> Result<(), Error> {
+        match api::auth::login(&self.api, email, password).await? {
+            Some(result) if result.status == "success" => Ok(()),
+            Some(result) => Err(Error::LoginFailed(result.message)),
+            None => Err(Error::InvalidResponse),
+        }
+    }
+
+    /// Fetches the user's info and library and saves them. Returns `None` if the user isn't
+    /// logged in.
+    pub async fn sync(&self) -> Result<Option<LibraryConfig>, Error> {
+        match api::auth::sync(&self.api).await? {
+            Some(SyncResult {
+                user_config,
+                library_config,
+            }) => {
+                user_config.store()?;
+                library_config.store()?;
+                Ok(Some(library_config))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Fetches how a game is launched. Returns `None` if the developer API doesn't know the
+    /// game.
+    pub async fn game_details(&self, product: &Product) -> Result<Option<GameDetails>, Error> {
+        Ok(api::product::get_game_details(&self.api, product).await?)
+    }
+
+    /// Clears the session along with the saved user info and library
+    pub fn logout(&self) -> Result<(), Error> {
+        UserConfig::clear()?;
+        LibraryConfig::clear()?;
+        self.cookie_store.lock().unwrap().clear();
+        Ok(())
+    }
+
+    /// Saves the session cookies, so the next session stays logged in
+    pub fn save(&self) -> Result<(), Error> {
+        let cookie_store = self.cookie_store.lock().unwrap().clone();
+        CookieConfig(cookie_store).store()?;
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{cmp::Reverse, collections::HashMap, path::PathBuf};
 
 use confy::ConfyError;
 use reqwest_cookie_store::CookieStore;
@@ -7,12 +7,13 @@ use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use crate::{
     constants::PROJECT_NAME,
     shared::models::{
-        api::{Product, UserInfo},
-        InstallInfo,
+        api::{BuildOs, Product, UserInfo},
+        DownloadSchedule, InstallInfo, QueuedInstall,
     },
 };
 
-pub(crate) trait GalaConfig
+/// A config file stored in the user's config directory
+pub trait GalaConfig
 where
     Self: Sized + Serialize + DeserializeOwned + Default,
 {
@@ -28,6 +29,11 @@ where
         confy::store(*PROJECT_NAME, Self::config_name(), Self::default())
     }
 
+    /// Path of the config file
+    fn path() -> Result<PathBuf, ConfyError> {
+        confy::get_configuration_file_path(*PROJECT_NAME, Self::config_name())
+    }
+
     fn config_name() -> &'static str;
 }
 
@@ -51,9 +57,16 @@ impl GalaConfig for CookieConfig {
     }
 }
 
+/// Games in the user's IndieGala library, as of the last sync
 #[derive(Default, Debug, Serialize, Deserialize)]
-pub(crate) struct LibraryConfig {
-    pub(crate) collection: Vec<Product>,
+pub struct LibraryConfig {
+    pub collection: Vec<Product>,
+}
+
+impl LibraryConfig {
+    pub fn product(&self, slug: &str) -> Option<&Product> {
+        self.collection.iter().find(|p| p.slugged_name == slug)
+    }
 }
 
 impl GalaConfig for LibraryConfig {
@@ -62,7 +75,219 @@ impl GalaConfig for LibraryConfig {
     }
 }
 
-pub(crate) type InstalledConfig = HashMap<String, InstallInfo>;
+/// Games waiting to be installed
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct QueueConfig {
+    pub downloads: Vec<QueuedInstall>,
+}
+
+impl QueueConfig {
+    /// Returns queued downloads in the order they should be installed
+    pub fn ordered(&self) -> Vec<&QueuedInstall> {
+        let mut downloads: Vec<&QueuedInstall> = self.downloads.iter().collect();
+        // Stable sort, so downloads with the same priority keep the order they were queued in
+        downloads.sort_by_key(|d| Reverse(d.priority));
+        downloads
+    }
+
+    /// Finds the download of the install `install_id`, see [`install_id`]
+    ///
+    /// [`install_id`]: crate::models::install_id
+    pub fn get(&self, install_id: &str) -> Option<&QueuedInstall> {
+        self.downloads.iter().find(|d| d.id() == install_id)
+    }
+
+    pub fn get_mut(&mut self, install_id: &str) -> Option<&mut QueuedInstall> {
+        self.downloads.iter_mut().find(|d| d.id() == install_id)
+    }
+
+    pub fn remove(&mut self, install_id: &str) -> Option<QueuedInstall> {
+        let index = self.downloads.iter().position(|d| d.id() == install_id)?;
+        Some(self.downloads.remove(index))
+    }
+
+    /// Finds the download of a game picked by `selector`, a label or an OS, the same way
+    /// [`InstalledConfig::get`] picks installs
+    pub fn select(&self, slug: &str, selector: Option<&str>) -> Option<&QueuedInstall> {
+        let downloads: Vec<&QueuedInstall> =
+            self.downloads.iter().filter(|d| d.slug == slug).collect();
+        match selector {
+            Some(selector) => downloads
+                .iter()
+                .find(|d| d.label.as_deref() == Some(selector))
+                .or_else(|| {
+                    // An OS only picks a download if it's the only one for that OS
+                    let mut matches = downloads.iter().filter(|d| d.matches(selector));
+                    matches.next().filter(|_| matches.next().is_none())
+                })
+                .copied(),
+            None => match downloads[..] {
+                [download] => Some(download),
+                _ => downloads.into_iter().find(|d| d.label.is_none()),
+            },
+        }
+    }
+}
+
+impl GalaConfig for QueueConfig {
+    fn config_name() -> &'static str {
+        "queue"
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct SettingsConfig {
+    /// Global download rate limit in bytes per second
+    pub download_limit: Option<u64>,
+    /// Hours during which downloads are allowed to run at full speed
+    pub download_schedule: Option<DownloadSchedule>,
+    /// Keep downloaded chunks in a local cache to reuse them in later installs
+    #[serde(default)]
+    pub chunk_cache: bool,
+    /// Maximum size of the chunk cache in bytes
+    pub chunk_cache_max_size: Option<u64>,
+    /// URL of the IndieGala website. The official website is used if not set.
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// URL of the content CDN. The official CDN is used if not set.
+    #[serde(default)]
+    pub content_url: Option<String>,
+    /// URL of the developer API. The official API is used if not set.
+    #[serde(default)]
+    pub dev_url: Option<String>,
+    /// Order OSes are picked in when installing a game without selecting one. Uses
+    /// [`BuildOs::default_preference`] if not set.
+    #[serde(default)]
+    pub os_preference: Option<Vec<BuildOs>>,
+    /// OS preference of specific games by slug. Overrides `os_preference`.
+    #[serde(default)]
+    pub game_os_preference: HashMap<String, Vec<BuildOs>>,
+}
+
+impl SettingsConfig {
+    /// Order OSes are picked in when installing the game with `slug`
+    pub fn os_preference(&self, slug: &str) -> Vec<BuildOs> {
+        match self
+            .game_os_preference
+            .get(slug)
+            .or(self.os_preference.as_ref())
+        {
+            Some(preference) => preference.clone(),
+            None => BuildOs::default_preference(),
+        }
+    }
+}
+
+impl GalaConfig for SettingsConfig {
+    fn config_name() -> &'static str {
+        "settings"
+    }
+}
+
+/// Installed games by slug. A game can be installed several times side by side, e.g. its
+/// Windows and Linux builds, as long as every install has its own label.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    from = "HashMap<String, StoredInstalls>",
+    into = "HashMap<String, Vec<InstallInfo>>"
+)]
+pub struct InstalledConfig {
+    games: HashMap<String, Vec<InstallInfo>>,
+}
+
+/// Installs of a game as stored in the config file
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredInstalls {
+    Several(Vec<InstallInfo>),
+    /// Configs written before games could be installed side by side have a single install per
+    /// game
+    Single(InstallInfo),
+}
+
+impl From<HashMap<String, StoredInstalls>> for InstalledConfig {
+    fn from(stored: HashMap<String, StoredInstalls>) -> Self {
+        let games = stored
+            .into_iter()
+            .map(|(slug, installs)| match installs {
+                StoredInstalls::Several(installs) => (slug, installs),
+                StoredInstalls::Single(install) => (slug, vec![install]),
+            })
+            .filter(|(_, installs)| !installs.is_empty())
+            .collect();
+        Self { games }
+    }
+}
+
+impl From<InstalledConfig> for HashMap<String, Vec<InstallInfo>> {
+    fn from(installed: InstalledConfig) -> Self {
+        installed.games
+    }
+}
+
+impl InstalledConfig {
+    /// Every install of the game with `slug`, in the order they were installed
+    pub fn installs(&self, slug: &str) -> &[InstallInfo] {
+        self.games.get(slug).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    pub fn contains(&self, slug: &str) -> bool {
+        self.games.contains_key(slug)
+    }
+
+    /// Finds the install of a game picked by `selector`, a label or an OS. Without a selector,
+    /// picks the unlabeled install, or the only install of the game.
+    pub fn get(&self, slug: &str, selector: Option<&str>) -> Option<&InstallInfo> {
+        let installs = self.installs(slug);
+        match selector {
+            Some(selector) => installs
+                .iter()
+                .find(|install| install.label.as_deref() == Some(selector))
+                .or_else(|| {
+                    // An OS only picks an install if it's the only one for that OS
+                    let mut matches = installs.iter().filter(|i| i.matches(selector));
+                    matches.next().filter(|_| matches.next().is_none())
+                }),
+            None => match installs {
+                [install] => Some(install),
+                _ => installs.iter().find(|install| install.label.is_none()),
+            },
+        }
+    }
+
+    /// Adds an install of the game with `slug`, replacing its install with the same label
+    pub fn insert(&mut self, slug: String, install_info: InstallInfo) {
+        let installs = self.games.entry(slug).or_default();
+        match installs
+            .iter_mut()
+            .find(|install| install.label == install_info.label)
+        {
+            Some(install) => *install = install_info,
+            None => installs.push(install_info),
+        }
+    }
+
+    /// Removes the install of the game with `slug` that has `label`
+    pub fn remove(&mut self, slug: &str, label: Option<&str>) -> Option<InstallInfo> {
+        let installs = self.games.get_mut(slug)?;
+        let index = installs
+            .iter()
+            .position(|install| install.label.as_deref() == label)?;
+        let install_info = installs.remove(index);
+        if installs.is_empty() {
+            self.games.remove(slug);
+        }
+        Some(install_info)
+    }
+
+    /// Every install of every game, with the slug of the game
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &InstallInfo)> {
+        self.games
+            .iter()
+            .flat_map(|(slug, installs)| installs.iter().map(move |install| (slug, install)))
+    }
+}
 
 impl GalaConfig for InstalledConfig {
     fn config_name() -> &'static str {
@@ -5,17 +5,19 @@ use lazy_static::lazy_static;
 use reqwest::header::{self, HeaderMap};
 
 lazy_static! {
-    pub(crate) static ref BASE_URL: &'static str = "https://www.indiegala.com";
-    pub(crate) static ref CONTENT_URL: &'static str = "https://content.indiegalacdn.com";
-    pub(crate) static ref DEV_URL: &'static str = "https://developers.indiegala.com";
-    pub(crate) static ref MAX_CHUNK_SIZE: usize = 1048576; // 1 MiB
-    pub(crate) static ref DEFAULT_MAX_DL_WORKERS: usize = std::cmp::min(num_cpus::get() * 2, 16);
-    pub(crate) static ref DEFAULT_MAX_MEMORY_USAGE: usize = *MAX_CHUNK_SIZE * 1024; // 1 GiB
-    pub(crate) static ref DEFAULT_BASE_INSTALL_PATH: PathBuf = UserDirs::new().expect("Failed to retrieve home directory.").home_dir().join("Games").join(*PROJECT_NAME);
-    pub(crate) static ref PROJECT_NAME: &'static str = env!("CARGO_PKG_NAME");
-    pub(crate) static ref PROJECT_VERSION: &'static str = env!("CARGO_PKG_VERSION");
+    pub static ref DEFAULT_BASE_URL: &'static str = "https://www.indiegala.com";
+    pub static ref DEFAULT_CONTENT_URL: &'static str = "https://content.indiegalacdn.com";
+    pub static ref DEFAULT_DEV_URL: &'static str = "https://developers.indiegala.com";
+    pub static ref DEFAULT_TIMEOUT: u64 = 60;
+    pub static ref MAX_CHUNK_SIZE: usize = 1048576; // 1 MiB
+    pub static ref DEFAULT_MAX_DL_WORKERS: usize = std::cmp::min(num_cpus::get() * 2, 16);
+    pub static ref DEFAULT_MAX_MEMORY_USAGE: usize = *MAX_CHUNK_SIZE * 1024; // 1 GiB
+    pub static ref DEFAULT_CHUNK_CACHE_SIZE: u64 = 10 * 1024 * 1024 * 1024; // 10 GiB
+    pub static ref DEFAULT_BASE_INSTALL_PATH: PathBuf = UserDirs::new().expect("Failed to retrieve home directory.").home_dir().join("Games").join(*PROJECT_NAME);
+    pub static ref PROJECT_NAME: &'static str = env!("CARGO_PKG_NAME");
+    pub static ref PROJECT_VERSION: &'static str = env!("CARGO_PKG_VERSION");
     pub(crate) static ref VERSION_CODENAME: &'static str = include_str!("../CODENAME");
-    pub(crate) static ref HELP_VERSION: &'static str = {
+    pub static ref HELP_VERSION: &'static str = {
         Box::leak(format!("{} - {}", *PROJECT_VERSION, *VERSION_CODENAME).into_boxed_str())
     };
     pub(crate) static ref DEFAULT_HEADERS: HeaderMap = {
@@ -0,0 +1,583 @@
+use std::{
+    fs::Permissions,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use directories::ProjectDirs;
+use log::{info, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    net::{
+        unix::{OwnedReadHalf, OwnedWriteHalf},
+        UnixListener, UnixStream,
+    },
+    sync::{broadcast, Mutex, Notify},
+};
+
+use crate::{
+    archive::ArchiveIndex,
+    client::GalaClient,
+    config::{GalaConfig, InstalledConfig, LibraryConfig, QueueConfig},
+    constants::{DEFAULT_BASE_INSTALL_PATH, PROJECT_NAME},
+    error::Error,
+    installer::{InstallOptions, Installer, Progress},
+    launcher::Launcher,
+    shared::models::{api::BuildOs, install_id, QueueStatus, QueuedInstall},
+};
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const REQUEST_FAILED: i64 = -32000;
+
+/// Path of the daemon's control socket when none is given
+pub fn default_socket_path() -> PathBuf {
+    let project = ProjectDirs::from("rs", "", *PROJECT_NAME).unwrap();
+    project
+        .runtime_dir()
+        .unwrap_or_else(|| project.cache_dir())
+        .join("daemon.sock")
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<Error> for RpcError {
+    fn from(err: Error) -> Self {
+        Self::new(REQUEST_FAILED, err)
+    }
+}
+
+/// Params of `install.start`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InstallParams {
+    pub slug: String,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub os: Option<BuildOs>,
+    /// Directory to install the game to. Defaults to a folder named after the game in the
+    /// default base install path.
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    pub skip_verify: bool,
+    /// Local archive to install the game from
+    #[serde(default)]
+    pub archive: Option<PathBuf>,
+    #[serde(default)]
+    pub include_disabled: bool,
+    /// Install even if there isn't enough free disk space
+    #[serde(default)]
+    pub force: bool,
+    /// Allocate every file to its final size before downloading
+    #[serde(default)]
+    pub preallocate: bool,
+    /// Label to install the game next to its other installs
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+/// Params of `launch`. Games are launched with the Wine settings the daemon was started with.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LaunchParams {
+    pub slug: String,
+    /// Install to launch when the game is installed several times: its label, or its OS
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+/// Params of `install.pause`
+#[derive(Debug, Deserialize)]
+struct PauseParams {
+    slug: String,
+    /// Download to pick when the game is queued several times: its label, or its OS
+    #[serde(default)]
+    label: Option<String>,
+}
+
+/// Keeps a session and the download queue running in the background, controlled through
+/// JSON-RPC 2.0 over a Unix domain socket.
+///
+/// Only the user running the daemon can connect to the socket. Requests and responses are sent
+/// as one JSON object per line. Supported methods:
+///
+/// - `library.list`: games in the library, synced first
+/// - `queue.list`: queued games in the order they'll be installed
+/// - `install.start`: queues a game with [`InstallParams`] and starts installing it
+/// - `install.pause`: pauses a queued game by `slug`, and `label` if it's queued several times
+/// - `launch`: launches an installed game with [`LaunchParams`] and returns its `exit_code`
+/// - `progress.subscribe`: sends every [`Progress`] event as a `progress` notification from
+///   then on
+pub struct Daemon {
+    client: GalaClient,
+    installer: Installer,
+    max_parallel_installs: usize,
+    wine_bin: Option<PathBuf>,
+    wine_prefix: Option<PathBuf>,
+    progress: broadcast::Sender<Progress>,
+    queue_changed: Notify,
+    /// Shared with the queue worker, held while the queue is changed
+    queue_lock: Arc<Mutex<()>>,
+}
+
+impl Daemon {
+    pub fn new(
+        client: GalaClient,
+        install_options: InstallOptions,
+        max_parallel_installs: usize,
+    ) -> Self {
+        let (progress, _) = broadcast::channel(1024);
+        let sender = progress.clone();
+        let installer = Installer::new(&client, install_options).on_progress(move |event| {
+            // Nobody is listening if sending fails, which is fine
+            let _ = sender.send(event.clone());
+        });
+
+        Self {
+            client,
+            queue_lock: installer.queue_lock(),
+            installer,
+            max_parallel_installs,
+            wine_bin: None,
+            wine_prefix: None,
+            progress,
+            queue_changed: Notify::new(),
+        }
+    }
+
+    /// Wine binary used to launch Windows games
+    pub fn wine_bin(mut self, wine_bin: Option<PathBuf>) -> Self {
+        self.wine_bin = wine_bin;
+        self
+    }
+
+    /// Wine prefix used to launch Windows games
+    pub fn wine_prefix(mut self, wine_prefix: Option<PathBuf>) -> Self {
+        self.wine_prefix = wine_prefix;
+        self
+    }
+
+    /// Listens on `socket_path` until interrupted. Fails if another daemon is already
+    /// listening there.
+    pub async fn run(self, socket_path: &Path) -> Result<(), Error> {
+        if UnixStream::connect(socket_path).await.is_ok() {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::AddrInUse,
+                format!("A daemon is already running on {}", socket_path.display()),
+            )));
+        }
+        // Left behind by a daemon that didn't shut down cleanly
+        let _ = std::fs::remove_file(socket_path);
+        if let Some(parent) = socket_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let listener = UnixListener::bind(socket_path)?;
+        std::fs::set_permissions(socket_path, Permissions::from_mode(0o600))?;
+        let uid = std::fs::metadata(socket_path)?.uid();
+        info!("Daemon listening on {}", socket_path.display());
+
+        let daemon = Arc::new(self);
+        let worker = tokio::spawn(daemon.clone().process_queue());
+        // Resume whatever was left in the queue by the last run
+        daemon.queue_changed.notify_one();
+
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let stream = match accepted {
+                        Ok((stream, _)) => stream,
+                        // e.g. when running out of file descriptors, which can be temporary
+                        Err(err) => {
+                            warn!("Failed to accept a connection: {err}");
+                            continue;
+                        }
+                    };
+                    // Anyone who can connect can launch programs as this user
+                    match stream.peer_cred() {
+                        Ok(cred) if cred.uid() == uid => {
+                            tokio::spawn(daemon.clone().handle_connection(stream));
+                        }
+                        Ok(cred) => warn!("Refused a connection from user {}", cred.uid()),
+                        Err(err) => warn!("Refused a connection: {err}"),
+                    }
+                }
+                _ = tokio::signal::ctrl_c() => break,
+            }
+        }
+
+        worker.abort();
+        std::fs::remove_file(socket_path)?;
+        daemon.client.save()
+    }
+
+    async fn process_queue(self: Arc<Self>) {
+        loop {
+            self.queue_changed.notified().await;
+            // Keep serving the queue, the next change may succeed where this one failed
+            if let Err(err) = self
+                .installer
+                .process_queue(self.max_parallel_installs)
+                .await
+            {
+                warn!("Failed to process the download queue: {}", err);
+            }
+        }
+    }
+
+    async fn handle_connection(self: Arc<Self>, stream: UnixStream) {
+        let (reader, writer) = stream.into_split();
+        let writer = Arc::new(Mutex::new(writer));
+        let mut lines = BufReader::new(reader).lines();
+
+        while let Ok(Some(line)) = lines.next_line().await {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let request = match serde_json::from_str::<RpcRequest>(&line) {
+                Ok(request) => request,
+                Err(err) => {
+                    let error = RpcError::new(PARSE_ERROR, err);
+                    send(
+                        &writer,
+                        &json!({"jsonrpc": "2.0", "id": null, "error": error}),
+                    )
+                    .await;
+                    continue;
+                }
+            };
+
+            // Requests are handled concurrently, so a running game doesn't block the connection
+            let daemon = self.clone();
+            let writer = writer.clone();
+            tokio::spawn(async move {
+                let result = daemon.handle_request(&request, &writer).await;
+                // Notifications don't get a response
+                let id = match request.id {
+                    Some(id) => id,
+                    None => return,
+                };
+                let response = match result {
+                    Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                    Err(error) => json!({"jsonrpc": "2.0", "id": id, "error": error}),
+                };
+                send(&writer, &response).await;
+            });
+        }
+    }
+
+    async fn handle_request(
+        &self,
+        request: &RpcRequest,
+        writer: &Arc<Mutex<OwnedWriteHalf>>,
+    ) -> Result<Value, RpcError> {
+        match request.method.as_str() {
+            "library.list" => {
+                let library = match self.sync().await {
+                    Some(library) => library,
+                    None => LibraryConfig::load().map_err(Error::from)?,
+                };
+                Ok(json!(library.collection))
+            }
+            "queue.list" => {
+                let queue = QueueConfig::load().map_err(Error::from)?;
+                Ok(json!(queue.ordered()))
+            }
+            "install.start" => {
+                let params: InstallParams = parse_params(&request.params)?;
+                let queued = self.queue_install(params).await?;
+                Ok(json!(queued))
+            }
+            "install.pause" => {
+                let PauseParams { slug, label } = parse_params(&request.params)?;
+                let _queue_guard = self.queue_lock.lock().await;
+                let mut queue = QueueConfig::load().map_err(Error::from)?;
+                let install_id = match queue.select(&slug, label.as_deref()) {
+                    Some(download) => download.id(),
+                    None => {
+                        return Err(RpcError::new(
+                            REQUEST_FAILED,
+                            format!("{} is not queued", install_id(&slug, label.as_deref())),
+                        ))
+                    }
+                };
+                if let Some(download) = queue.get_mut(&install_id) {
+                    download.status = QueueStatus::Paused;
+                }
+                queue.store().map_err(Error::from)?;
+                self.installer.pause(&install_id);
+                Ok(json!(true))
+            }
+            "launch" => {
+                let params: LaunchParams = parse_params(&request.params)?;
+                let exit_code = self.launch(params).await?;
+                Ok(json!({ "exit_code": exit_code }))
+            }
+            "progress.subscribe" => {
+                let mut events = self.progress.subscribe();
+                let writer = writer.clone();
+                tokio::spawn(async move {
+                    loop {
+                        let event = match events.recv().await {
+                            Ok(event) => event,
+                            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                            Err(broadcast::error::RecvError::Closed) => return,
+                        };
+                        let notification =
+                            json!({"jsonrpc": "2.0", "method": "progress", "params": event});
+                        if !send(&writer, &notification).await {
+                            return;
+                        }
+                    }
+                });
+                Ok(json!(true))
+            }
+            method => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method {method}"),
+            )),
+        }
+    }
+
+    /// Syncs the library, since requests forwarded by the CLI skip its own sync. Returns
+    /// `None` if the library couldn't be synced, e.g. when offline.
+    async fn sync(&self) -> Option<LibraryConfig> {
+        match self.client.sync().await {
+            Ok(Some(library)) => Some(library),
+            Ok(None) => {
+                warn!("Failed to sync the library. Are you logged in?");
+                None
+            }
+            Err(err) => {
+                warn!("Failed to sync the library: {err}");
+                None
+            }
+        }
+    }
+
+    async fn queue_install(&self, params: InstallParams) -> Result<QueuedInstall, RpcError> {
+        let slug = params.slug;
+        let failed = |message: String| Err(RpcError::new(REQUEST_FAILED, message));
+
+        let products = match &params.archive {
+            Some(archive_path) => {
+                ArchiveIndex::load(archive_path)
+                    .map_err(Error::from)?
+                    .products
+            }
+            None => match self.sync().await {
+                Some(library) => library.collection,
+                None => LibraryConfig::load().map_err(Error::from)?.collection,
+            },
+        };
+
+        let install_id = install_id(&slug, params.label.as_deref());
+        let installed = InstalledConfig::load().map_err(Error::from)?;
+        if installed
+            .installs(&slug)
+            .iter()
+            .any(|install| install.label == params.label)
+        {
+            return failed(format!("{install_id} already installed."));
+        }
+        let _queue_guard = self.queue_lock.lock().await;
+        let mut queue = QueueConfig::load().map_err(Error::from)?;
+        if queue.get(&install_id).is_some() {
+            return failed(format!("{install_id} is already queued."));
+        }
+        let product = match products.iter().find(|p| p.slugged_name == slug) {
+            Some(product) => product,
+            None => return failed(format!("Couldn't find {slug}")),
+        };
+        if let Some(version) = &params.version {
+            if product.find_version(version, params.os.as_ref()).is_none() {
+                return failed(format!("Can't find build {version} for {slug}"));
+            }
+        }
+
+        let queued = QueuedInstall {
+            install_path: params
+                .path
+                .unwrap_or_else(|| DEFAULT_BASE_INSTALL_PATH.join(&install_id)),
+            slug,
+            version: params.version,
+            os: params.os,
+            priority: params.priority,
+            skip_verify: params.skip_verify,
+            archive: params.archive,
+            include_disabled: params.include_disabled,
+            force: params.force,
+            preallocate: params.preallocate,
+            label: params.label,
+            status: QueueStatus::Queued,
+        };
+        queue.downloads.push(queued.clone());
+        queue.store().map_err(Error::from)?;
+        self.queue_changed.notify_one();
+
+        Ok(queued)
+    }
+
+    async fn launch(&self, params: LaunchParams) -> Result<Option<i32>, RpcError> {
+        let slug = params.slug;
+        let installed = InstalledConfig::load().map_err(Error::from)?;
+        let install_info = match installed.get(&slug, params.label.as_deref()) {
+            Some(info) => info,
+            None => {
+                return Err(RpcError::new(
+                    REQUEST_FAILED,
+                    match params.label {
+                        Some(label) => format!("No install of {slug} matches {label}"),
+                        None => format!("{slug} is not installed"),
+                    },
+                ))
+            }
+        };
+        let library = LibraryConfig::load().map_err(Error::from)?;
+        let product = match library.product(&slug) {
+            Some(product) => product,
+            None => {
+                return Err(RpcError::new(
+                    REQUEST_FAILED,
+                    format!("Couldn't find {slug} in library"),
+                ))
+            }
+        };
+
+        let launcher = Launcher::new(&self.client)
+            .wine_bin(self.wine_bin.clone())
+            .wine_prefix(self.wine_prefix.clone());
+        match launcher
+            .launch(product, install_info)
+            .await
+            .map_err(Error::from)?
+        {
+            Some(status) => Ok(status.code()),
+            None => Err(RpcError::new(
+                REQUEST_FAILED,
+                format!("Failed to launch {slug}"),
+            )),
+        }
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(params: &Value) -> Result<T, RpcError> {
+    serde_json::from_value(params.clone()).map_err(|err| RpcError::new(INVALID_PARAMS, err))
+}
+
+/// Writes a message to a connection. Returns `false` if the connection was closed.
+async fn send(writer: &Mutex<OwnedWriteHalf>, message: &Value) -> bool {
+    let mut line = message.to_string();
+    line.push('\n');
+    writer.lock().await.write_all(line.as_bytes()).await.is_ok()
+}
+
+/// Connection to a running [`Daemon`]
+pub struct DaemonClient {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+    next_id: u64,
+}
+
+impl DaemonClient {
+    /// Connects to the daemon listening on `socket_path`. Fails if no daemon is running.
+    pub async fn connect(socket_path: &Path) -> std::io::Result<Self> {
+        let (reader, writer) = UnixStream::connect(socket_path).await?.into_split();
+
+        Ok(Self {
+            lines: BufReader::new(reader).lines(),
+            writer,
+            next_id: 1,
+        })
+    }
+
+    /// Calls a method and waits for its result. Progress notifications received in the
+    /// meantime are dropped.
+    pub async fn call<T: DeserializeOwned>(
+        &mut self,
+        method: &str,
+        params: Value,
+    ) -> Result<T, Error> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let request = RpcRequest {
+            jsonrpc: "2.0".to_owned(),
+            id: Some(json!(id)),
+            method: method.to_owned(),
+            params,
+        };
+        let mut line = serde_json::to_string(&request).map_err(|_| Error::InvalidResponse)?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes()).await?;
+
+        loop {
+            let message = self.next_message().await?.ok_or(Error::InvalidResponse)?;
+            if message.get("id") != Some(&json!(id)) {
+                continue;
+            }
+            if let Some(error) = message.get("error") {
+                let error: RpcError =
+                    serde_json::from_value(error.clone()).map_err(|_| Error::InvalidResponse)?;
+                return Err(Error::Daemon(error.message));
+            }
+            let result = message.get("result").cloned().unwrap_or(Value::Null);
+            return serde_json::from_value(result).map_err(|_| Error::InvalidResponse);
+        }
+    }
+
+    /// Waits for the next progress event after calling `progress.subscribe`. Returns `None`
+    /// once the daemon closes the connection.
+    pub async fn next_progress(&mut self) -> Result<Option<Progress>, Error> {
+        loop {
+            let message = match self.next_message().await? {
+                Some(message) => message,
+                None => return Ok(None),
+            };
+            if message.get("method") != Some(&json!("progress")) {
+                continue;
+            }
+            let params = message.get("params").cloned().unwrap_or(Value::Null);
+            return serde_json::from_value(params)
+                .map(Some)
+                .map_err(|_| Error::InvalidResponse);
+        }
+    }
+
+    async fn next_message(&mut self) -> Result<Option<Value>, Error> {
+        match self.lines.next_line().await? {
+            Some(line) => serde_json::from_str(&line)
+                .map(Some)
+                .map_err(|_| Error::InvalidResponse),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Build manifest diffing. Delta manifests are written from the changes found here by
+//! [`delta_manifest`](crate::manifest::delta_manifest).
+
+use std::collections::HashMap;
+
+pub(crate) trait ManifestEntry {
+    fn file_name(&self) -> &str;
+    fn sha(&self) -> &str;
+    fn is_directory(&self) -> bool;
+    fn is_empty(&self) -> bool;
+}
+
+#[derive(Debug)]
+pub(crate) enum FileChange<T> {
+    /// File doesn't exist in the old build
+    Added(T),
+    /// File exists in the old build with different contents
+    Modified(T),
+    /// File is new or modified, but its contents exist in the old build under `source`, so it
+    /// can be copied locally instead of downloaded
+    Copied {
+        entry: T,
+        source: String,
+        added: bool,
+    },
+    /// File doesn't exist in the new build
+    Removed(T),
+}
+
+/// Compares two build manifests and calls `on_change` for every file that changed.
+///
+/// The old manifest is indexed by file name and SHA, and the new manifest is streamed through
+/// once, so this runs in O(n). Changes are reported in the order of the new manifest, followed
+/// by removed files in the order of the old manifest.
+pub(crate) fn diff_manifests<T, O, N, F>(old_manifest: O, new_manifest: N, mut on_change: F)
+where
+    T: ManifestEntry,
+    O: IntoIterator<Item = T>,
+    N: IntoIterator<Item = T>,
+    F: FnMut(FileChange<T>),
+{
+    let old_entries: Vec<T> = old_manifest.into_iter().collect();
+    let mut by_name: HashMap<&str, usize> = HashMap::with_capacity(old_entries.len());
+    let mut by_sha: HashMap<&str, usize> = HashMap::with_capacity(old_entries.len());
+    for (index, entry) in old_entries.iter().enumerate() {
+        by_name.insert(entry.file_name(), index);
+        if !entry.is_directory() && !entry.is_empty() {
+            by_sha.entry(entry.sha()).or_insert(index);
+        }
+    }
+
+    let mut kept = vec![false; old_entries.len()];
+    for new_entry in new_manifest {
+        let old_index = by_name.get(new_entry.file_name()).copied();
+        if let Some(index) = old_index {
+            kept[index] = true;
+            if old_entries[index].sha() == new_entry.sha() {
+                continue;
+            }
+        }
+
+        let source = match new_entry.is_directory() || new_entry.is_empty() {
+            true => None,
+            false => by_sha
+                .get(new_entry.sha())
+                .map(|index| old_entries[*index].file_name())
+                .filter(|source| *source != new_entry.file_name()),
+        };
+        on_change(match (source, old_index) {
+            (Some(source), old_index) => FileChange::Copied {
+                source: source.to_owned(),
+                entry: new_entry,
+                added: old_index.is_none(),
+            },
+            (None, Some(_)) => FileChange::Modified(new_entry),
+            (None, None) => FileChange::Added(new_entry),
+        });
+    }
+
+    for (entry, kept) in old_entries.into_iter().zip(kept) {
+        if !kept {
+            on_change(FileChange::Removed(entry));
+        }
+    }
+}
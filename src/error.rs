@@ -0,0 +1,98 @@
+use std::fmt;
+
+use human_bytes::human_bytes;
+
+use crate::manifest::ManifestError;
+
+/// Errors returned by the library
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// A request to one of the IndieGala services failed
+    Http(reqwest::Error),
+    Io(std::io::Error),
+    /// A config file couldn't be loaded or saved
+    Config(confy::ConfyError),
+    /// A server stopped responding
+    TimedOut,
+    /// The server sent a response that couldn't be parsed
+    InvalidResponse,
+    /// The server rejected the login, with the reason it gave
+    LoginFailed(String),
+    /// The game doesn't have a build for the selected version or OS
+    BuildNotFound,
+    /// The selected build was disabled or pulled by the developer
+    BuildDisabled {
+        version: String,
+        reason: String,
+    },
+    /// The selected build is already installed
+    AlreadyInstalled(String),
+    /// The game is held at a version, and there's no newer build it can be updated to
+    UpdateHeld(String),
+    /// There isn't enough free disk space for the build. Sizes are in bytes.
+    NotEnoughSpace {
+        needed: u64,
+        available: u64,
+    },
+    /// Some chunks couldn't be downloaded or failed verification
+    ChunksFailed,
+    /// A build manifest or chunk manifest couldn't be parsed
+    InvalidManifest(ManifestError),
+    /// The daemon failed to handle a request, with the reason it gave
+    Daemon(String),
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Self::Http(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<ManifestError> for Error {
+    fn from(err: ManifestError) -> Self {
+        Self::InvalidManifest(err)
+    }
+}
+
+impl From<confy::ConfyError> for Error {
+    fn from(err: confy::ConfyError) -> Self {
+        Self::Config(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Http(err) => write!(f, "{}", err),
+            Self::Io(err) => write!(f, "{}", err),
+            Self::Config(err) => write!(f, "{}", err),
+            Self::TimedOut => write!(f, "Timed out waiting for the server"),
+            Self::InvalidResponse => write!(f, "Failed to parse the server response"),
+            Self::LoginFailed(message) => write!(f, "Login failed: {}", message),
+            Self::BuildNotFound => write!(f, "Couldn't find a matching build"),
+            Self::BuildDisabled { version, reason } => {
+                write!(f, "Build {} is {}", version, reason)
+            }
+            Self::AlreadyInstalled(version) => write!(f, "Build {} is already installed", version),
+            Self::UpdateHeld(version) => write!(f, "The game is held at {}", version),
+            Self::NotEnoughSpace { needed, available } => write!(
+                f,
+                "Not enough free disk space. {} is needed, but only {} is available.",
+                human_bytes(*needed as f64),
+                human_bytes(*available as f64)
+            ),
+            Self::ChunksFailed => write!(f, "Some chunks failed to download or verify"),
+            Self::InvalidManifest(err) => write!(f, "Invalid manifest: {}", err),
+            Self::Daemon(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,190 @@
+use std::collections::HashSet;
+
+use freecarnival::{
+    config::{GalaConfig, InstalledConfig},
+    constants::{DEFAULT_MAX_DL_WORKERS, DEFAULT_MAX_MEMORY_USAGE},
+    daemon::{DaemonClient, InstallParams, LaunchParams},
+    models::{install_id, Product, QueuedInstall},
+    search::LibraryQuery,
+    Error, Progress,
+};
+use serde_json::{json, Value};
+
+use crate::{
+    cli::{Commands, QueueCommands},
+    progress::ProgressBars,
+};
+
+/// Runs a command through a running daemon instead of in this process. Returns `false` if the
+/// daemon can't handle the command.
+pub(crate) async fn forward(daemon: &mut DaemonClient, command: &Commands) -> bool {
+    match command {
+        Commands::Library { query } => match daemon
+            .call::<Vec<Product>>("library.list", json!(null))
+            .await
+        {
+            Ok(products) => {
+                let installed = InstalledConfig::load().expect("Failed to load installed");
+                let products = LibraryQuery::from(query).run(products, &installed).await;
+                for product in products {
+                    println!("{}", product);
+                }
+            }
+            Err(err) => println!("Failed to list library: {err}"),
+        },
+        Commands::Install {
+            slugs,
+            from_file: None,
+            version,
+            base_path,
+            path,
+            os,
+            label,
+            priority,
+            from_archive,
+            install_opts,
+            ..
+        } if !install_opts.info => {
+            if slugs.len() > 1 && (version.is_some() || path.is_some()) {
+                println!("--version and --path can only be used when installing a single game");
+                return true;
+            }
+            // Downloads of the daemon share its limits, which are set when it's started
+            if install_opts.limit.is_some()
+                || install_opts.max_download_workers != *DEFAULT_MAX_DL_WORKERS
+                || install_opts.max_memory_usage != *DEFAULT_MAX_MEMORY_USAGE
+            {
+                println!(
+                    "--limit, --max-download-workers and --max-memory-usage can't be used while the daemon is running. Start the daemon with them instead."
+                );
+                return true;
+            }
+
+            // Subscribe before queueing anything, so no progress is missed
+            if let Err(err) = daemon.call::<bool>("progress.subscribe", json!(null)).await {
+                println!("Failed to subscribe to progress: {err}");
+                return true;
+            }
+            let mut pending = HashSet::new();
+            for slug in slugs {
+                let params = InstallParams {
+                    slug: slug.to_owned(),
+                    version: version.clone(),
+                    os: os.clone(),
+                    path: match (path, base_path) {
+                        (Some(path), _) => Some(path.to_owned()),
+                        (None, Some(base_path)) => {
+                            Some(base_path.join(install_id(slug, label.as_deref())))
+                        }
+                        (None, None) => None,
+                    },
+                    priority: *priority,
+                    skip_verify: install_opts.skip_verify,
+                    archive: from_archive.clone(),
+                    include_disabled: install_opts.include_disabled,
+                    force: install_opts.force,
+                    preallocate: install_opts.preallocate,
+                    label: label.clone(),
+                };
+                match daemon
+                    .call::<QueuedInstall>("install.start", json!(params))
+                    .await
+                {
+                    Ok(queued) => {
+                        println!("Adding {slug} to the download queue...");
+                        pending.insert(queued.id());
+                    }
+                    Err(Error::Daemon(message)) => println!("{message}"),
+                    Err(err) => println!("Failed to queue {slug}: {err}"),
+                }
+            }
+
+            wait_for_installs(daemon, pending).await;
+        }
+        Commands::Queue {
+            command: QueueCommands::List,
+        } => match daemon
+            .call::<Vec<QueuedInstall>>("queue.list", json!(null))
+            .await
+        {
+            Ok(downloads) if downloads.is_empty() => println!("The download queue is empty"),
+            Ok(downloads) => {
+                for download in downloads {
+                    println!("{}", download);
+                }
+            }
+            Err(err) => println!("Failed to list download queue: {err}"),
+        },
+        Commands::Queue {
+            command: QueueCommands::Pause { slug, label },
+        } => match daemon
+            .call::<bool>("install.pause", json!({ "slug": slug, "label": label }))
+            .await
+        {
+            Ok(_) => println!("{} paused.", install_id(slug, label.as_deref())),
+            Err(err) => println!("{err}"),
+        },
+        Commands::Launch {
+            slug,
+            label,
+            wine_bin,
+            wine_prefix,
+        } => {
+            if wine_bin.is_some() || wine_prefix.is_some() {
+                println!(
+                    "--wine-bin and --wine-prefix can't be used while the daemon is running. Start the daemon with them instead."
+                );
+                return true;
+            }
+            let params = LaunchParams {
+                slug: slug.to_owned(),
+                label: label.clone(),
+            };
+            match daemon.call::<Value>("launch", json!(params)).await {
+                Ok(result) => match result.get("exit_code").and_then(Value::as_i64) {
+                    Some(code) => println!("Process exited with: exit status: {code}"),
+                    None => println!("Process exited without an exit code"),
+                },
+                Err(err) => println!("Failed to launch {slug}: {err}"),
+            }
+        }
+        _ => return false,
+    };
+
+    true
+}
+
+/// Shows the progress of games installed by the daemon until they're done
+async fn wait_for_installs(daemon: &mut DaemonClient, mut pending: HashSet<String>) {
+    let progress_bars = ProgressBars::default();
+    while !pending.is_empty() {
+        let progress = match daemon.next_progress().await {
+            Ok(Some(progress)) => progress,
+            Ok(None) => {
+                println!("The daemon stopped before the install finished");
+                return;
+            }
+            Err(err) => {
+                println!("Failed to read progress from the daemon: {err}");
+                return;
+            }
+        };
+        progress_bars.update(&progress);
+
+        match progress {
+            Progress::Installed {
+                install_id,
+                version,
+            } if pending.remove(&install_id) => {
+                println!("Successfully installed {} ({})", install_id, version);
+            }
+            Progress::Failed { install_id, error } if pending.remove(&install_id) => {
+                println!("Failed to install {install_id}: {error}");
+            }
+            Progress::Paused { install_id } if pending.remove(&install_id) => {
+                println!("Stopped downloading {install_id}.");
+            }
+            _ => {}
+        }
+    }
+}
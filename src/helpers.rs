@@ -1,32 +1,94 @@
 use std::{
     collections::{HashMap, HashSet},
-    path::PathBuf,
-    sync::Arc,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
 };
 
 use async_recursion::async_recursion;
 use bytes::Bytes;
 use directories::ProjectDirs;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use fs2::FileExt;
+use lazy_static::lazy_static;
+use log::{debug, info, warn};
 use os_path::OsPath;
 use queues::{queue, IsQueue, Queue};
 use sha2::{Digest, Sha256};
 use tokio::{
     fs::File,
-    io::AsyncWriteExt,
+    io::{AsyncSeekExt, AsyncWriteExt},
     sync::{OwnedSemaphorePermit, Semaphore},
 };
+use tokio_util::sync::CancellationToken;
 
 use crate::{
-    api,
-    cli::InstallOpts,
+    api::ApiClient,
+    cache::ChunkCache,
+    config::{GalaConfig, SettingsConfig},
     constants::{MAX_CHUNK_SIZE, PROJECT_NAME},
-    shared::models::{
-        api::{BuildOs, Product},
-        BuildManifestChunksRecord, BuildManifestRecord, ChangeTag,
+    installer::{InstallOptions, Progress},
+    manifest::{
+        self, BuildManifest, BuildManifestChunksRecord, ChangeTag, ChunkManifest,
+        ChunkManifestWriter, InstallPaths, ManifestError,
     },
+    shared::models::api::{BuildOs, Product},
+    source::BuildSource,
+    throttle::RateLimiter,
 };
 
+/// Finds the most likely executable of a native Linux build. Executables closer to
+/// `install_path` win, then `.x86_64` binaries over `.sh` scripts over other ELF files.
+#[cfg(target_os = "linux")]
+pub(crate) async fn find_linux_exe(install_path: &Path) -> Option<PathBuf> {
+    let mut dirs = std::collections::VecDeque::from([install_path.to_path_buf()]);
+    while let Some(dir) = dirs.pop_front() {
+        let mut best: Option<(u8, PathBuf)> = None;
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let entry_path = entry.path();
+            if entry_path.is_dir() {
+                dirs.push_back(entry_path);
+                continue;
+            }
+
+            let rank = match entry_path.extension().and_then(|ext| ext.to_str()) {
+                Some("x86_64") => 0,
+                Some("sh") => 1,
+                None if is_elf(&entry_path).await => 2,
+                _ => continue,
+            };
+            if best
+                .as_ref()
+                .map_or(true, |(best_rank, _)| rank < *best_rank)
+            {
+                best = Some((rank, entry_path));
+            }
+        }
+        if let Some((_, exe)) = best {
+            return Some(exe);
+        }
+    }
+
+    None
+}
+
+#[cfg(target_os = "linux")]
+async fn is_elf(path: &Path) -> bool {
+    use tokio::io::AsyncReadExt;
+
+    let mut magic = [0u8; 4];
+    match File::open(path).await {
+        Ok(mut file) => file.read_exact(&mut magic).await.is_ok() && magic == *b"\x7fELF",
+        Err(_) => false,
+    }
+}
+
 #[async_recursion]
 pub(crate) async fn find_exe_recursive(path: &PathBuf) -> Option<PathBuf> {
     let mut subdirs = vec![];
@@ -43,7 +105,7 @@ pub(crate) async fn find_exe_recursive(path: &PathBuf) -> Option<PathBuf> {
 
                 if entry_path.is_file() {
                     // Check if the current path is a file with a .exe extension
-                    println!("Checking file: {}", entry_path.display());
+                    debug!("Checking file: {}", entry_path.display());
                     if let (Some(ext), Some(file_name)) =
                         (entry_path.extension(), entry_path.file_name())
                     {
@@ -62,7 +124,7 @@ pub(crate) async fn find_exe_recursive(path: &PathBuf) -> Option<PathBuf> {
             }
         }
         Err(err) => {
-            println!("Failed to iterate over {}: {:?}", path.display(), err);
+            warn!("Failed to iterate over {}: {:?}", path.display(), err);
         }
     }
 
@@ -72,7 +134,7 @@ pub(crate) async fn find_exe_recursive(path: &PathBuf) -> Option<PathBuf> {
     }
 
     for dir in subdirs {
-        println!("Checking directory: {}", dir.display());
+        debug!("Checking directory: {}", dir.display());
         if let Some(exe_path) = find_exe_recursive(&dir.to_path_buf()).await {
             return Some(exe_path);
         }
@@ -92,84 +154,12 @@ pub(crate) async fn read_or_generate_delta_manifest(
     if let Ok(exising_delta) =
         read_build_manifest(&manifest_delta_version, slug, "manifest_delta").await
     {
-        println!("Using existing delta manifest");
+        debug!("Using existing delta manifest");
         return Ok(exising_delta);
     }
 
-    println!("Generating delta manifest...");
-    let mut new_manifest_rdr = csv::Reader::from_reader(new_manifest_bytes);
-    let new_manifest_iter: Vec<BuildManifestRecord> = new_manifest_rdr
-        .byte_records()
-        .map(|r| {
-            let mut record = r.expect("Failed to get byte record");
-            record.push_field(b"");
-            record
-                .deserialize::<BuildManifestRecord>(None)
-                .expect("Failed to deserialize updated build manifest")
-        })
-        .collect();
-    let mut old_manifest_rdr = csv::Reader::from_reader(old_manifest_bytes);
-    let old_manifest_iter: Vec<BuildManifestRecord> = old_manifest_rdr
-        .byte_records()
-        .map(|r| {
-            let mut record = r.expect("Failed to get byte record");
-            record.push_field(b"");
-            record
-                .deserialize::<BuildManifestRecord>(None)
-                .expect("Failed to deserialize old build manifest")
-        })
-        .collect();
-
-    let new_file_names: HashSet<&String> = new_manifest_iter
-        .iter()
-        .map(|entry| &entry.file_name)
-        .collect();
-    let mut build_manifest_delta_wtr = csv::Writer::from_writer(vec![]);
-
-    for new_entry in &new_manifest_iter {
-        let added = !old_manifest_iter
-            .iter()
-            .any(|entry| entry.file_name == new_entry.file_name);
-
-        if added {
-            build_manifest_delta_wtr
-                .serialize(BuildManifestRecord {
-                    tag: Some(ChangeTag::Added),
-                    ..new_entry.clone()
-                })
-                .expect("Failed to serialize delta build manifest");
-            continue;
-        }
-
-        let modified = match old_manifest_iter
-            .iter()
-            .find(|entry| entry.file_name == new_entry.file_name)
-        {
-            Some(old_entry) => old_entry.sha != new_entry.sha,
-            None => false,
-        };
-
-        if modified {
-            build_manifest_delta_wtr
-                .serialize(BuildManifestRecord {
-                    tag: Some(ChangeTag::Modified),
-                    ..new_entry.clone()
-                })
-                .expect("Failed to serialize delta build manifest");
-        }
-    }
-
-    for old_entry in old_manifest_iter {
-        if !new_file_names.contains(&old_entry.file_name) {
-            build_manifest_delta_wtr
-                .serialize(BuildManifestRecord {
-                    tag: Some(ChangeTag::Removed),
-                    ..old_entry
-                })
-                .expect("Failed to serialize delta build manifest");
-        }
-    }
-    let delta_bytes = build_manifest_delta_wtr.into_inner().unwrap();
+    debug!("Generating delta manifest...");
+    let delta_bytes = manifest::delta_manifest(old_manifest_bytes, new_manifest_bytes)?;
     store_build_manifest(
         &delta_bytes,
         &format!("{}_{}", old_version, new_version),
@@ -192,72 +182,34 @@ pub(crate) async fn read_or_generate_delta_chunks_manifest(
     if let Ok(exising_delta) =
         read_build_manifest(&manifest_delta_version, slug, "manifest_delta_chunks").await
     {
-        println!("Using existing chunks delta manifest");
+        debug!("Using existing chunks delta manifest");
         return Ok(exising_delta);
     }
 
-    println!("Generating chunks delta manifest...");
-    let mut delta_manifest_rdr = csv::Reader::from_reader(delta_manifest_bytes);
-    let mut delta_manifest = delta_manifest_rdr.byte_records().map(|r| {
-        let record = r.expect("Failed to get byte record");
-        record.deserialize::<BuildManifestRecord>(None)
-    });
-    let mut current_file = delta_manifest
-        .next()
-        .expect("Failed to deserialize build manifest delta")
-        .expect("There were no changes in this update?");
-
-    let mut new_manifest_rdr = csv::Reader::from_reader(new_manifest_bytes);
-    let new_manifest_byte_records = new_manifest_rdr.byte_records();
-    let mut build_manifest_delta_wtr = csv::Writer::from_writer(vec![]);
-
-    for record in new_manifest_byte_records {
-        let record = record.expect("Failed to get byte record");
-        let record = record
-            .deserialize::<BuildManifestChunksRecord>(None)
-            .expect("Failed to deserialize build manifest chunks");
-
-        // Removed files are always last in the delta manifest, so we can break here
-        if current_file.tag == Some(ChangeTag::Removed) {
-            break;
-        }
-
-        // We want to ignore chunks for removed files and folders
-        while current_file.is_directory() || current_file.is_empty() {
-            current_file = match delta_manifest.next() {
-                Some(file) => {
-                    println!("Skipping over {}", current_file.file_name);
-                    file.expect("Failed to deserialize build manifest delta")
-                }
-                None => {
-                    println!("Done processing delta chunks");
-                    break;
-                }
-            };
-        }
-
-        if record.file_path != current_file.file_name {
-            continue;
+    debug!("Generating chunks delta manifest...");
+    // Only files that have to be downloaded need chunks. Removed files, folders, empty files
+    // and files copied from the installed build are skipped.
+    let mut changed_files = HashSet::new();
+    for record in BuildManifest::new(delta_manifest_bytes)? {
+        let record = record?;
+        if record.tag != Some(ChangeTag::Removed)
+            && record.copy_source.is_none()
+            && !record.is_directory()
+            && !record.is_empty()
+        {
+            changed_files.insert(record.file_name);
         }
+    }
 
-        build_manifest_delta_wtr
-            .serialize(&record)
-            .expect("Failed to serialize build manifest chunks");
-
-        if usize::from(record.id) + 1 == current_file.chunks {
-            println!("Done processing chunks for {}", record.file_path);
-            // Move on to the next file
-            current_file = match delta_manifest.next() {
-                Some(file) => file.expect("Failed to deserialize build manifest delta"),
-                None => {
-                    println!("Done processing delta chunks");
-                    break;
-                }
-            };
+    let mut build_manifest_delta_wtr = ChunkManifestWriter::new(vec![])?;
+    for record in ChunkManifest::new(new_manifest_bytes)? {
+        let record = record?;
+        if changed_files.contains(&record.file_path) {
+            build_manifest_delta_wtr.write(&record)?;
         }
     }
 
-    let delta_bytes = build_manifest_delta_wtr.into_inner().unwrap();
+    let delta_bytes = build_manifest_delta_wtr.into_inner()?;
     store_build_manifest(
         &delta_bytes,
         &format!("{}_{}", old_version, new_version),
@@ -269,14 +221,37 @@ pub(crate) async fn read_or_generate_delta_chunks_manifest(
     Ok(delta_bytes)
 }
 
+lazy_static! {
+    /// Directory the manifests of installed builds are stored in, under the data directory.
+    /// Manifests stored in the config directory by older versions are moved there the first
+    /// time it's used.
+    static ref MANIFESTS_DIR: PathBuf = {
+        let project = ProjectDirs::from("rs", "", *PROJECT_NAME).unwrap();
+        let path = project.data_dir().join("manifests");
+        let old_path = project.config_dir().join("manifests");
+        if !path.exists() && old_path.is_dir() {
+            // Manifests that can't be moved are fetched again when they're needed
+            let _ = std::fs::create_dir_all(project.data_dir())
+                .and_then(|_| std::fs::rename(&old_path, &path));
+        }
+
+        path
+    };
+}
+
+/// Directory the manifests of installed builds are stored in
+pub(crate) fn manifests_dir() -> PathBuf {
+    MANIFESTS_DIR.clone()
+}
+
+/// Stores a manifest of `build_number` with the other manifests of the install `install_id`
 pub(crate) async fn store_build_manifest(
     body: &[u8],
     build_number: &String,
-    product_slug: &String,
+    install_id: &String,
     file_suffix: &str,
 ) -> tokio::io::Result<()> {
-    let project = ProjectDirs::from("rs", "", *PROJECT_NAME).unwrap();
-    let path = project.config_dir().join("manifests").join(product_slug);
+    let path = manifests_dir().join(install_id);
     tokio::fs::create_dir_all(&path).await?;
 
     let path = path.join(format!("{}_{}.csv", build_number, file_suffix));
@@ -285,70 +260,221 @@ pub(crate) async fn store_build_manifest(
 
 pub(crate) async fn read_build_manifest(
     build_number: &String,
-    product_slug: &String,
+    install_id: &String,
     file_suffix: &str,
 ) -> tokio::io::Result<Vec<u8>> {
-    let project = ProjectDirs::from("rs", "", *PROJECT_NAME).unwrap();
-    let path = project
-        .config_dir()
-        .join("manifests")
-        .join(product_slug)
+    let path = manifests_dir()
+        .join(install_id)
         .join(format!("{}_{}.csv", build_number, file_suffix));
     tokio::fs::read(path).await
 }
 
+pub(crate) type ProgressHandler = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+/// Download limits and progress reporting shared by every game being installed at the same time
+#[derive(Clone)]
+pub(crate) struct DownloadPool {
+    download_workers: Arc<Semaphore>,
+    memory: Arc<Semaphore>,
+    limiter: Arc<RateLimiter>,
+    cache: Option<Arc<ChunkCache>>,
+    /// Cancels the tasks of every running install, by install id
+    running: Arc<std::sync::Mutex<HashMap<String, CancellationToken>>>,
+    /// Held while the download queue is loaded, changed and stored, so changes made at the
+    /// same time by this process aren't lost
+    pub(crate) queue_lock: Arc<tokio::sync::Mutex<()>>,
+    pub(crate) progress: Option<ProgressHandler>,
+}
+
+impl DownloadPool {
+    pub(crate) fn new(install_opts: &InstallOptions) -> Self {
+        let max_chunks_in_memory = install_opts.max_memory_usage / *MAX_CHUNK_SIZE;
+
+        Self {
+            download_workers: Arc::new(Semaphore::new(install_opts.max_download_workers)),
+            memory: Arc::new(Semaphore::new(max_chunks_in_memory)),
+            limiter: Arc::new(RateLimiter::new(install_opts.limit)),
+            cache: ChunkCache::from_settings(&SettingsConfig::load().unwrap_or_default())
+                .map(Arc::new),
+            running: Arc::default(),
+            queue_lock: Arc::default(),
+            progress: None,
+        }
+    }
+
+    /// Registers the install `install_id` as running, so it can be stopped with
+    /// [`DownloadPool::cancel`]. Its tasks are stopped once the returned guard is dropped. If
+    /// the install is already running, the guard only stops the tasks started with it.
+    pub(crate) fn start(&self, install_id: &str) -> RunningInstall {
+        let mut running = self.running.lock().unwrap();
+        let (token, registered) = match running.get(install_id) {
+            Some(token) => (token.child_token(), false),
+            None => {
+                let token = CancellationToken::new();
+                running.insert(install_id.to_owned(), token.clone());
+                (token, true)
+            }
+        };
+
+        RunningInstall {
+            install_id: install_id.to_owned(),
+            token,
+            registered,
+            running: self.running.clone(),
+        }
+    }
+
+    /// Stops the tasks of the install `install_id`. Returns `false` if it isn't running.
+    pub(crate) fn cancel(&self, install_id: &str) -> bool {
+        match self.running.lock().unwrap().remove(install_id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn report(&self, progress: Progress) {
+        if let Some(handler) = &self.progress {
+            handler(&progress);
+        }
+    }
+}
+
+/// Guard of an install registered with [`DownloadPool::start`]. Dropping it, e.g. when the
+/// install future is dropped, stops every task the install spawned.
+pub(crate) struct RunningInstall {
+    install_id: String,
+    token: CancellationToken,
+    registered: bool,
+    running: Arc<std::sync::Mutex<HashMap<String, CancellationToken>>>,
+}
+
+impl RunningInstall {
+    pub(crate) fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+}
+
+impl Drop for RunningInstall {
+    fn drop(&mut self) {
+        self.token.cancel();
+        if self.registered {
+            // The install may have been cancelled and started again since
+            let mut running = self.running.lock().unwrap();
+            if running
+                .get(&self.install_id)
+                .is_some_and(CancellationToken::is_cancelled)
+            {
+                running.remove(&self.install_id);
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn build_from_manifest(
-    client: reqwest::Client,
+    source: BuildSource,
     product: Arc<Product>,
+    install_id: &str,
     os: Arc<BuildOs>,
     build_manifest_bytes: &[u8],
     build_manifest_chunks_bytes: &[u8],
+    installed_manifest_bytes: Option<&[u8]>,
     install_path: OsPath,
-    install_opts: InstallOpts,
+    install_opts: InstallOptions,
+    pool: &DownloadPool,
 ) -> tokio::io::Result<bool> {
-    let mut write_queue = queue![];
+    // Every path is checked before anything is written, so a manifest with an unsafe path
+    // doesn't change anything in the install
+    let mut paths = InstallPaths::new(*os != BuildOs::Linux);
+    if let Some(installed_manifest_bytes) = installed_manifest_bytes {
+        // Delta manifests only list changed files, so the case of the paths already on disk
+        // comes from the installed build. It was checked when it was installed.
+        for record in BuildManifest::new(installed_manifest_bytes)?.flatten() {
+            let _ = paths.resolve(&record.file_name);
+        }
+    }
+    let mut records = vec![];
+    for record in BuildManifest::new(build_manifest_bytes)? {
+        let mut record = record?;
+        record.file_name = paths.resolve(&record.file_name)?;
+        if let Some(copy_source) = &record.copy_source {
+            record.copy_source = Some(paths.resolve(copy_source)?);
+        }
+        records.push(record);
+    }
+    // Chunks can only be written to files that are downloaded
+    let downloaded_files: HashSet<&str> = records
+        .iter()
+        .filter(|record| {
+            !record.is_directory()
+                && record.tag != Some(ChangeTag::Removed)
+                && record.copy_source.is_none()
+        })
+        .map(|record| record.file_name.as_str())
+        .collect();
+    let mut file_chunk_num_map: HashMap<String, usize> = HashMap::new();
     let mut chunk_queue = queue![];
+    for record in ChunkManifest::new(build_manifest_chunks_bytes)? {
+        let mut record = record?;
+        record.file_path = paths.resolve(&record.file_path)?;
+        if !downloaded_files.contains(record.file_path.as_str()) {
+            return Err(tokio::io::Error::new(
+                tokio::io::ErrorKind::InvalidData,
+                format!(
+                    "{}.bin is a chunk of {}, which isn't a file downloaded by the build manifest",
+                    record.sha, record.file_path
+                ),
+            ));
+        }
+        *file_chunk_num_map
+            .entry(record.file_path.clone())
+            .or_default() += 1;
+        chunk_queue.add(record).unwrap();
+    }
 
     // Create install directory if it doesn't exist
     tokio::fs::create_dir_all(&install_path).await?;
 
-    let mut file_chunk_num_map = HashMap::new();
     let mut total_bytes = 0u64;
 
-    let m = MultiProgress::new();
+    // Files that exist in the installed build under a different path are copied aside before
+    // anything gets deleted, and moved into place below.
+    let copies_path = install_path.join(".freecarnival-copies");
+    let mut copies = HashMap::new();
+    for record in &records {
+        if let Some(copy_source) = &record.copy_source {
+            if copies.is_empty() {
+                info!("Copying files from the installed build...");
+                tokio::fs::create_dir_all(&copies_path).await?;
+            }
+            let copy_path = copies_path.join(copies.len().to_string());
+            tokio::fs::copy(install_path.join(copy_source), &copy_path).await?;
+            copies.insert(record.file_name.clone(), copy_path);
+        }
+    }
 
-    println!("Building folder structure...");
-    let mut manifest_rdr = csv::Reader::from_reader(build_manifest_bytes);
-    let byte_records = manifest_rdr.byte_records();
+    info!("Building folder structure...");
     #[cfg(target_os = "macos")]
     let mut mac_app = mac::MacAppExecutables::new();
 
-    for record in byte_records {
-        let mut record = record.expect("Failed to get byte record");
-        if record.get(5).is_none() {
-            record.push_field(b"");
-        }
-        let record = record
-            .deserialize::<BuildManifestRecord>(None)
-            .expect("Failed to deserialize build manifest");
-
+    for record in records {
         if record.tag == Some(ChangeTag::Modified) || record.tag == Some(ChangeTag::Removed) {
             let file_path = install_path.join(&record.file_name);
-            println!("Removing {}", file_path);
             if record.is_directory() {
-                println!("{} is a directory", file_path);
                 // Is a directory
                 if file_path.exists() && file_path.to_path().is_dir() {
-                    println!("Deleting {}", file_path);
+                    debug!("Deleting {}", file_path);
                     // Delete this directory
                     tokio::fs::remove_dir_all(file_path).await?;
                 }
                 continue;
             }
 
-            println!("{} is a file", file_path);
             if file_path.exists() && file_path.is_file() {
-                println!("Deleting {}", file_path);
+                debug!("Deleting {}", file_path);
                 // Delete this file
                 tokio::fs::remove_file(file_path).await?;
             }
@@ -358,209 +484,438 @@ pub(crate) async fn build_from_manifest(
             }
         }
 
+        if let Some(copy_path) = copies.remove(&record.file_name) {
+            tokio::fs::rename(copy_path, install_path.join(&record.file_name)).await?;
+            continue;
+        }
+
         prepare_file(
             &install_path,
+            #[cfg(target_os = "macos")]
             &os,
             &record.file_name,
             record.is_directory(),
+            record.size_in_bytes as u64,
+            install_opts.preallocate,
             #[cfg(target_os = "macos")]
             &mut mac_app,
         )
         .await?;
 
         if !record.is_directory() {
-            file_chunk_num_map.insert(record.file_name.clone(), record.chunks);
             total_bytes += record.size_in_bytes as u64;
         }
     }
 
-    let dl_sty =
-        ProgressStyle::with_template("{wide_msg} Download: {binary_bytes_per_sec}").unwrap();
-    let wr_sty = ProgressStyle::with_template(
-        "{wide_msg} Disk: {binary_bytes_per_sec}\n[{percent}%] {wide_bar} {bytes:>7}/{total_bytes:7} [{eta_precise}]",
-    )
-    .unwrap()
-    .progress_chars("##-");
-
-    let dl_prog = Arc::new(m.add(ProgressBar::new(total_bytes).with_style(dl_sty)));
-    let wrt_prog =
-        Arc::new(m.insert_after(&dl_prog, ProgressBar::new(total_bytes).with_style(wr_sty)));
-
-    println!("Building queue...");
-    let mut manifest_chunks_rdr = csv::Reader::from_reader(build_manifest_chunks_bytes);
-    let byte_records = manifest_chunks_rdr.byte_records();
-    for record in byte_records {
-        let record = record.expect("Failed to get byte record");
-        let record = record
-            .deserialize::<BuildManifestChunksRecord>(None)
-            .expect("Failed to deserialize chunks manifest");
-
-        let is_last = file_chunk_num_map[&record.file_path] - 1 == usize::from(record.id);
-        if is_last {
-            file_chunk_num_map.remove(&record.file_path);
-        }
-        write_queue
-            .add((record.sha.clone(), record.id, is_last))
-            .unwrap();
-        chunk_queue.add(record).unwrap();
+    if copies_path.exists() {
+        tokio::fs::remove_dir_all(&copies_path).await?;
     }
-    drop(file_chunk_num_map);
+
+    pool.report(Progress::Started {
+        install_id: install_id.to_owned(),
+        name: product.name.clone(),
+        total_bytes,
+        total_files: file_chunk_num_map.len(),
+    });
+
+    let total_chunks = chunk_queue.size();
+    // Stops the download and write tasks if the install is paused, or if this future is dropped
+    let running = pool.start(install_id);
 
     let (tx, rx) =
         async_channel::unbounded::<(BuildManifestChunksRecord, Bytes, OwnedSemaphorePermit)>();
 
-    println!("Spawning write thread...");
+    let write_pool = pool.clone();
+    let write_install_id = install_id.to_owned();
+    let write_token = running.token().clone();
     let write_handler = tokio::spawn(async move {
-        println!("Write thread started.");
-
-        let mut in_buffer = HashMap::new();
-        let mut file_map = HashMap::new();
-
-        while write_queue.size() > 0 {
-            let (record, chunk, permit) = match rx.recv().await {
+        // Files are only kept open while they still have chunks left to write
+        let mut file_map: HashMap<String, (File, usize)> = HashMap::new();
+        let mut chunks_written = 0usize;
+
+        while chunks_written < total_chunks {
+            let msg = tokio::select! {
+                msg = rx.recv() => msg,
+                _ = write_token.cancelled() => break,
+            };
+            let (record, chunk, permit) = match msg {
                 Ok(msg) => msg,
                 Err(_) => {
-                    println!("Write channel has closed");
+                    debug!("Write channel has closed");
                     break;
                 }
             };
 
-            // Some files don't have the chunk id in the sha parts, so they can have reused
-            // SHAs for chunks (e.g. DieYoungPrologue-WindowsNoEditor.pak)
-            let chunk_key = format!("{},{}", record.id, record.sha);
-            in_buffer.insert(chunk_key, (record.file_path, chunk, permit));
-
-            loop {
-                match write_queue.peek() {
-                    Ok((next_chunk, chunk_id, is_last_chunk)) => {
-                        let next_chunk_key = format!("{},{}", chunk_id, next_chunk);
-                        if let Some((file_path, bytes, permit)) = in_buffer.remove(&next_chunk_key)
-                        {
-                            if !file_map.contains_key(&file_path) {
-                                let chunk_file_path = install_path.join(&file_path);
-                                let file = open_file(&chunk_file_path).await.unwrap_or_else(|_| {
-                                    panic!("Failed to open {}", chunk_file_path)
-                                });
-                                file_map.insert(file_path.clone(), file);
-                            }
-                            let file = file_map.get_mut(&file_path).unwrap();
-                            write_queue.remove().unwrap();
-                            // println!("Writing {}", next_chunk);
-                            let bytes_written = bytes.len();
-                            append_chunk(file, bytes).await.unwrap_or_else(|_| {
-                                panic!("Failed to write {}.bin to {}", next_chunk, file_path)
-                            });
-                            drop(permit);
-
-                            wrt_prog.inc(bytes_written as u64);
-
-                            if is_last_chunk {
-                                file_map.remove(&file_path);
-                            }
-
-                            continue;
-                        }
-
-                        // println!(
-                        //     "Not ready to write {}: {} pending",
-                        //     next_chunk,
-                        //     in_buffer.len()
-                        // );
+            if !file_map.contains_key(&record.file_path) {
+                let chunk_file_path = install_path.join(&record.file_path);
+                let file = open_file(&chunk_file_path).await.map_err(|err| {
+                    tokio::io::Error::new(
+                        err.kind(),
+                        format!("Failed to open {}: {}", chunk_file_path, err),
+                    )
+                })?;
+                let chunks_left = file_chunk_num_map[&record.file_path];
+                file_map.insert(record.file_path.clone(), (file, chunks_left));
+            }
+            let (file, chunks_left) = file_map.get_mut(&record.file_path).unwrap();
 
-                        break;
-                    }
-                    Err(_) => {
-                        println!("No more chunks to write");
-                        return;
-                    }
-                }
+            // Every chunk but the last one of a file is exactly MAX_CHUNK_SIZE, so we can write
+            // chunks as soon as they arrive instead of waiting for the ones before them.
+            let offset = usize::from(record.id) * *MAX_CHUNK_SIZE;
+            let bytes_written = chunk.len();
+            write_chunk_at(file, offset as u64, chunk)
+                .await
+                .map_err(|err| {
+                    tokio::io::Error::new(
+                        err.kind(),
+                        format!(
+                            "Failed to write {}.bin to {}: {}",
+                            record.sha, record.file_path, err
+                        ),
+                    )
+                })?;
+            drop(permit);
+
+            write_pool.report(Progress::Written {
+                install_id: write_install_id.clone(),
+                bytes: bytes_written as u64,
+            });
+            chunks_written += 1;
+
+            *chunks_left -= 1;
+            if *chunks_left == 0 {
+                file_map.remove(&record.file_path);
+                write_pool.report(Progress::FileWritten {
+                    install_id: write_install_id.clone(),
+                    file_name: record.file_path,
+                });
             }
         }
-        println!("Write thread finished.");
+
+        Ok::<_, tokio::io::Error>(chunks_written)
     });
 
-    println!("Downloading chunks...");
-    let max_chunks_in_memory = install_opts.max_memory_usage / *MAX_CHUNK_SIZE;
-    let mem_semaphore = Arc::new(Semaphore::new(max_chunks_in_memory));
-    let dl_semaphore = Arc::new(Semaphore::new(install_opts.max_download_workers));
+    info!("Downloading chunks...");
     while let Ok(record) = chunk_queue.remove() {
-        let mem_permit = mem_semaphore.clone().acquire_owned().await.unwrap();
-        let client = client.clone();
+        // Nothing can be written anymore if the write task failed
+        if write_handler.is_finished() {
+            break;
+        }
+        let mem_permit = tokio::select! {
+            permit = pool.memory.clone().acquire_owned() => permit.unwrap(),
+            _ = running.token().cancelled() => break,
+        };
+        let source = source.clone();
         let product = product.clone();
         let os = os.clone();
         let thread_tx = tx.clone();
-        let dl_prog = dl_prog.clone();
-        let dl_semaphore = dl_semaphore.clone();
+        let task_pool = pool.clone();
+        let task_install_id = install_id.to_owned();
+        let task_token = running.token().clone();
 
         tokio::spawn(async move {
-            // println!("Downloading {}", record.sha);
-            let dl_permit = dl_semaphore.acquire().await.unwrap();
-            let chunk = api::product::download_chunk(&client, &product, &os, &record.sha)
-                .await
-                .unwrap_or_else(|_| panic!("Failed to download {}.bin", &record.sha));
-            drop(dl_permit);
-
-            dl_prog.inc(chunk.len() as u64);
-
-            if !install_opts.skip_verify {
-                let chunk_parts = &record.sha.split('_').collect::<Vec<&str>>();
-                match chunk_parts.last() {
-                    Some(chunk_sha) => {
-                        // println!("Verifying {}", record.sha);
-                        let chunk_corrupted = !verify_chunk(&chunk, chunk_sha);
-
-                        if chunk_corrupted {
-                            println!("Sha: {}", chunk_sha);
-                            println!(
-                                "{} failed verification. {} is corrupted.",
-                                &record.sha, &record.file_path
-                            );
-                            return false;
-                        }
-                    }
-                    None => {
-                        println!("Couldn't find Chunk SHA. Skipping verification...");
-                    }
-                }
-            }
+            let chunk = tokio::select! {
+                chunk = fetch_chunk(
+                    &source,
+                    &product,
+                    &os,
+                    &record,
+                    install_opts.skip_verify,
+                    &task_pool,
+                ) => chunk,
+                _ = task_token.cancelled() => None,
+            };
+            let chunk = match chunk {
+                Some(chunk) => chunk,
+                None => return false,
+            };
 
-            thread_tx.send((record, chunk, mem_permit)).await.unwrap();
+            task_pool.report(Progress::Downloaded {
+                install_id: task_install_id.clone(),
+                bytes: chunk.len() as u64,
+            });
 
-            true
+            // The write task stops early if the install is paused, so a closed channel means
+            // the chunk isn't needed anymore
+            thread_tx.send((record, chunk, mem_permit)).await.is_ok()
         });
     }
 
-    println!("Waiting for write thread to finish...");
-    write_handler.await?;
+    // Download tasks hold their own senders, so the channel closes once every task is done,
+    // even if some chunks failed to download or verify.
+    drop(tx);
+
+    let chunks_written = write_handler.await??;
+    pool.report(Progress::Finished {
+        install_id: install_id.to_owned(),
+    });
+    if running.token().is_cancelled() {
+        return Err(tokio::io::Error::new(
+            tokio::io::ErrorKind::Interrupted,
+            "The install was paused",
+        ));
+    }
+    if chunks_written < total_chunks {
+        warn!(
+            "{} out of {} chunks couldn't be installed.",
+            total_chunks - chunks_written,
+            total_chunks
+        );
+        return Ok(false);
+    }
 
     #[cfg(target_os = "macos")]
     if *os == BuildOs::Mac {
         mac_app.mark_as_executable().await?;
     }
 
-    // TODO: Redo logic for verification
     Ok(true)
 }
 
+/// Reads a chunk from the chunk cache, or fetches it from `source` and verifies it. Returns
+/// `None` if the chunk couldn't be fetched or is corrupted.
+async fn fetch_chunk(
+    source: &BuildSource,
+    product: &Product,
+    os: &BuildOs,
+    record: &BuildManifestChunksRecord,
+    skip_verify: bool,
+    pool: &DownloadPool,
+) -> Option<Bytes> {
+    // Archived chunks are already on disk, so there's no point in caching them, and reading
+    // them doesn't count towards the rate limit
+    let from_cdn = matches!(source, BuildSource::Cdn(_));
+    let cache = pool.cache.as_deref().filter(|_| from_cdn);
+    if let Some(cache) = cache {
+        // Cached chunks are verified when read
+        if let Some(chunk) = cache.get(&record.sha).await {
+            return Some(chunk);
+        }
+    }
+
+    let dl_permit = pool.download_workers.acquire().await.unwrap();
+    // Chunks are at most MAX_CHUNK_SIZE, so that much is taken before the request is sent
+    if from_cdn {
+        pool.limiter
+            .acquire(*MAX_CHUNK_SIZE, |progress| pool.report(progress))
+            .await;
+    }
+    let chunk = source.chunk(product, os, &record.sha).await;
+    if from_cdn {
+        let downloaded = chunk.as_ref().map_or(0, |chunk| chunk.len());
+        pool.limiter.settle(*MAX_CHUNK_SIZE, downloaded).await;
+    }
+    let chunk = match chunk {
+        Ok(chunk) => chunk,
+        Err(err) => {
+            warn!("Failed to download {}.bin: {}", &record.sha, err);
+            return None;
+        }
+    };
+    drop(dl_permit);
+
+    if !skip_verify {
+        match chunk_hash(&record.sha) {
+            Some(chunk_sha) => {
+                let chunk_corrupted = !verify_chunk(&chunk, chunk_sha);
+
+                if chunk_corrupted {
+                    warn!(
+                        "{} failed verification. {} is corrupted.",
+                        &record.sha, &record.file_path
+                    );
+                    return None;
+                }
+            }
+            None => {
+                warn!("Couldn't find Chunk SHA. Skipping verification...");
+            }
+        }
+    }
+
+    // Unverified chunks aren't cached, so they can't end up in other installs
+    if let Some(cache) = cache.filter(|_| !skip_verify) {
+        cache.insert(&record.sha, &chunk).await;
+    }
+
+    Some(chunk)
+}
+
+/// Used to give every chunk being written to an archive its own temporary file
+static ARCHIVE_TMP_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Downloads every chunk of a build into `build_dir` without assembling any files. Chunks that
+/// were already downloaded are skipped, so interrupted downloads can be resumed. Fails if the
+/// chunk manifest has an invalid chunk SHA.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn archive_chunks(
+    client: ApiClient,
+    product: Arc<Product>,
+    install_id: &str,
+    os: Arc<BuildOs>,
+    build_manifest_bytes: &[u8],
+    build_manifest_chunks_bytes: &[u8],
+    build_dir: &Path,
+    install_opts: InstallOptions,
+    pool: &DownloadPool,
+) -> tokio::io::Result<bool> {
+    tokio::fs::create_dir_all(build_dir).await?;
+
+    let mut file_sizes = HashMap::new();
+    for record in BuildManifest::new(build_manifest_bytes)? {
+        let record = record?;
+        file_sizes.insert(record.file_name, record.size_in_bytes);
+    }
+
+    // The same chunk can be used by several files, but only has to be stored once
+    let mut shas = HashSet::new();
+    let mut total_bytes = 0u64;
+    let mut records = vec![];
+    for record in ChunkManifest::new(build_manifest_chunks_bytes)? {
+        let record = record?;
+        // Chunk SHAs end up in paths, and the build can't be archived without every chunk
+        if !is_valid_chunk_sha(&record.sha) {
+            return Err(tokio::io::Error::new(
+                tokio::io::ErrorKind::InvalidData,
+                format!("{} isn't a valid chunk SHA", record.sha),
+            ));
+        }
+        if shas.insert(record.sha.clone()) && !build_dir.join(&record.sha).exists() {
+            // Every chunk but the last one of a file is exactly MAX_CHUNK_SIZE
+            let file_size = file_sizes.get(&record.file_path).copied().unwrap_or(0);
+            let offset = usize::from(record.id) * *MAX_CHUNK_SIZE;
+            total_bytes += file_size.saturating_sub(offset).min(*MAX_CHUNK_SIZE) as u64;
+            records.push(record);
+        }
+    }
+    info!(
+        "{} out of {} chunks left to download.",
+        records.len(),
+        shas.len()
+    );
+
+    pool.report(Progress::Started {
+        install_id: install_id.to_owned(),
+        name: product.name.clone(),
+        total_bytes,
+        total_files: 0,
+    });
+
+    let source = BuildSource::Cdn(client);
+    // Stops the download tasks if this future is dropped
+    let running = pool.start(install_id);
+    let mut handles = vec![];
+    for record in records {
+        let mem_permit = tokio::select! {
+            permit = pool.memory.clone().acquire_owned() => permit.unwrap(),
+            _ = running.token().cancelled() => break,
+        };
+        let source = source.clone();
+        let product = product.clone();
+        let os = os.clone();
+        let task_pool = pool.clone();
+        let task_install_id = install_id.to_owned();
+        let task_token = running.token().clone();
+        let chunk_path = build_dir.join(&record.sha);
+
+        handles.push(tokio::spawn(async move {
+            let chunk = tokio::select! {
+                chunk = fetch_chunk(
+                    &source,
+                    &product,
+                    &os,
+                    &record,
+                    install_opts.skip_verify,
+                    &task_pool,
+                ) => chunk,
+                _ = task_token.cancelled() => None,
+            };
+            let chunk = match chunk {
+                Some(chunk) => chunk,
+                None => return false,
+            };
+            let bytes = chunk.len() as u64;
+            task_pool.report(Progress::Downloaded {
+                install_id: task_install_id.clone(),
+                bytes,
+            });
+
+            // Write to a temporary file first, so a partially written chunk is never archived.
+            // Downloads of the same build running at the same time can write the same chunk, so
+            // each one gets its own file.
+            let tmp_path = chunk_path.with_file_name(format!(
+                "{}.{}-{}.tmp",
+                record.sha,
+                std::process::id(),
+                ARCHIVE_TMP_COUNT.fetch_add(1, Ordering::Relaxed)
+            ));
+            if let Err(err) = tokio::fs::write(&tmp_path, &chunk).await {
+                warn!("Failed to write {}: {:?}", tmp_path.display(), err);
+                let _ = tokio::fs::remove_file(&tmp_path).await;
+                return false;
+            }
+            if let Err(err) = tokio::fs::rename(&tmp_path, &chunk_path).await {
+                warn!("Failed to write {}: {:?}", chunk_path.display(), err);
+                let _ = tokio::fs::remove_file(&tmp_path).await;
+                return false;
+            }
+            drop(mem_permit);
+
+            task_pool.report(Progress::Written {
+                install_id: task_install_id,
+                bytes,
+            });
+
+            true
+        }));
+    }
+
+    let mut failed = 0usize;
+    for handle in handles {
+        if !matches!(handle.await, Ok(true)) {
+            failed += 1;
+        }
+    }
+    pool.report(Progress::Finished {
+        install_id: install_id.to_owned(),
+    });
+    if running.token().is_cancelled() {
+        return Err(tokio::io::Error::new(
+            tokio::io::ErrorKind::Interrupted,
+            "The download was paused",
+        ));
+    }
+
+    if failed > 0 {
+        warn!("{} chunks couldn't be downloaded.", failed);
+    }
+
+    Ok(failed == 0)
+}
+
 pub(crate) async fn open_file(file_path: &OsPath) -> tokio::io::Result<File> {
     tokio::fs::OpenOptions::new()
-        .append(true)
+        .write(true)
         .open(file_path)
         .await
 }
 
-pub(crate) async fn append_chunk(
+pub(crate) async fn write_chunk_at(
     file: &mut tokio::fs::File,
+    offset: u64,
     chunk: Bytes,
 ) -> tokio::io::Result<()> {
+    file.seek(SeekFrom::Start(offset)).await?;
     file.write_all(&chunk).await
 }
 
 pub(crate) async fn prepare_file(
     base_install_path: &OsPath,
-    os: &BuildOs,
+    #[cfg(target_os = "macos")] os: &BuildOs,
     file_name: &String,
     is_directory: bool,
+    size: u64,
+    preallocate: bool,
     #[cfg(target_os = "macos")] mac_executable: &mut mac::MacAppExecutables,
 ) -> tokio::io::Result<()> {
     let file_path = base_install_path.join(file_name);
@@ -571,8 +926,16 @@ pub(crate) async fn prepare_file(
             tokio::fs::create_dir(&file_path).await?;
         }
     } else {
-        // Create empty file.
-        tokio::fs::File::create(&file_path).await?;
+        // Create the file with its final size, so chunks can be written at their offsets in
+        // any order.
+        let file = tokio::fs::File::create(&file_path).await?;
+        if preallocate && size > 0 {
+            // Reserve the disk space up front so we fail now instead of halfway through
+            let file = file.into_std().await;
+            tokio::task::spawn_blocking(move || file.allocate(size)).await??;
+        } else {
+            file.set_len(size).await?;
+        }
     }
 
     #[cfg(target_os = "macos")]
@@ -588,6 +951,38 @@ pub(crate) async fn prepare_file(
     Ok(())
 }
 
+/// Sums the size of every file in a build manifest
+pub(crate) fn build_manifest_size(build_manifest_bytes: &[u8]) -> Result<u64, ManifestError> {
+    BuildManifest::new(build_manifest_bytes)?
+        .try_fold(0u64, |acc, record| Ok(acc + record?.size_in_bytes as u64))
+}
+
+/// Size of the files a delta manifest copies from the installed build. The copies are made
+/// before any file is deleted.
+pub(crate) fn copied_files_size(delta_manifest_bytes: &[u8]) -> Result<u64, ManifestError> {
+    BuildManifest::new(delta_manifest_bytes)?.try_fold(0u64, |acc, record| {
+        let record = record?;
+        Ok(match record.copy_source {
+            Some(_) => acc + record.size_in_bytes as u64,
+            None => acc,
+        })
+    })
+}
+
+/// Returns the free space of the filesystem `path` will be created in. The closest existing
+/// parent is checked if `path` doesn't exist yet.
+pub(crate) fn available_space(path: &Path) -> std::io::Result<u64> {
+    let mut existing = path;
+    while !existing.exists() {
+        match existing.parent() {
+            Some(parent) => existing = parent,
+            None => break,
+        }
+    }
+
+    fs2::available_space(existing)
+}
+
 pub(crate) fn verify_file_hash(file_path: &OsPath, sha: &str) -> std::io::Result<bool> {
     let mut file = std::fs::File::open(file_path)?;
     let mut hasher = Sha256::new();
@@ -598,6 +993,19 @@ pub(crate) fn verify_file_hash(file_path: &OsPath, sha: &str) -> std::io::Result
     Ok(file_sha == sha)
 }
 
+/// Checks that a chunk SHA can safely be used as a file name
+pub(crate) fn is_valid_chunk_sha(chunk_sha: &str) -> bool {
+    !chunk_sha.is_empty()
+        && chunk_sha
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Extracts the SHA256 hash of a chunk from its chunk SHA
+pub(crate) fn chunk_hash(chunk_sha: &str) -> Option<&str> {
+    chunk_sha.rsplit('_').next().filter(|hash| !hash.is_empty())
+}
+
 pub(crate) fn verify_chunk(chunk: &Bytes, sha: &str) -> bool {
     let mut hasher = Sha256::new();
     hasher.update(chunk);
@@ -612,6 +1020,7 @@ pub(crate) mod mac {
     use std::path::{Path, PathBuf};
 
     use async_recursion::async_recursion;
+    use log::{debug, warn};
     use serde::Deserialize;
 
     #[async_recursion]
@@ -623,7 +1032,7 @@ pub(crate) mod mac {
                 while let Ok(Some(entry)) = subpath.next_entry().await {
                     let entry_path = entry.path();
                     // Check if the current path is a .app extension
-                    println!("Checking file: {}", entry_path.display());
+                    debug!("Checking file: {}", entry_path.display());
                     if let Some(ext) = entry_path.extension() {
                         if ext == "app" {
                             return Some(entry_path);
@@ -636,12 +1045,12 @@ pub(crate) mod mac {
                 }
             }
             Err(err) => {
-                println!("Failed to iterate over {}: {:?}", path.display(), err);
+                warn!("Failed to iterate over {}: {:?}", path.display(), err);
             }
         }
 
         for dir in subdirs {
-            println!("Checking directory: {}", dir.display());
+            debug!("Checking directory: {}", dir.display());
             if let Some(app_path) = find_app_recursive(&dir.to_path_buf()).await {
                 return Some(app_path);
             }
@@ -698,7 +1107,7 @@ pub(crate) mod mac {
                     tokio::fs::set_permissions(executable_path, permissions).await?;
                 }
                 None => {
-                    println!("No executable set, cannot mark as executable.");
+                    warn!("No executable set, cannot mark as executable.");
                 }
             };
 
@@ -0,0 +1,402 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::ApiClient,
+    client::GalaClient,
+    constants::{DEFAULT_MAX_DL_WORKERS, DEFAULT_MAX_MEMORY_USAGE},
+    error::Error,
+    helpers::DownloadPool,
+    manifest::Manifests,
+    shared::models::{
+        api::{BuildOs, Product, ProductVersion},
+        InstallInfo,
+    },
+    source::BuildSource,
+    utils,
+};
+
+/// Limits and checks used when downloading builds. Start from [`InstallOptions::default`] and
+/// change the options with the builder methods.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct InstallOptions {
+    /// How many chunks to download at the same time
+    pub max_download_workers: usize,
+    /// How much memory, in bytes, downloaded chunks can use before they're written to disk
+    pub max_memory_usage: usize,
+    /// Skip verifying downloaded chunks
+    pub skip_verify: bool,
+    /// Install even if there isn't enough free disk space
+    pub force: bool,
+    /// Allocate every file to its final size before downloading
+    pub preallocate: bool,
+    /// Download rate limit in bytes per second. Uses the limit in settings if not set.
+    pub limit: Option<u64>,
+    /// Allow installing builds that were disabled or pulled by the developer
+    pub include_disabled: bool,
+}
+
+impl Default for InstallOptions {
+    fn default() -> Self {
+        Self {
+            max_download_workers: *DEFAULT_MAX_DL_WORKERS,
+            max_memory_usage: *DEFAULT_MAX_MEMORY_USAGE,
+            skip_verify: false,
+            force: false,
+            preallocate: false,
+            limit: None,
+            include_disabled: false,
+        }
+    }
+}
+
+impl InstallOptions {
+    pub fn max_download_workers(mut self, max_download_workers: usize) -> Self {
+        self.max_download_workers = max_download_workers;
+        self
+    }
+
+    pub fn max_memory_usage(mut self, max_memory_usage: usize) -> Self {
+        self.max_memory_usage = max_memory_usage;
+        self
+    }
+
+    pub fn skip_verify(mut self, skip_verify: bool) -> Self {
+        self.skip_verify = skip_verify;
+        self
+    }
+
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    pub fn preallocate(mut self, preallocate: bool) -> Self {
+        self.preallocate = preallocate;
+        self
+    }
+
+    pub fn limit(mut self, limit: Option<u64>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn include_disabled(mut self, include_disabled: bool) -> Self {
+        self.include_disabled = include_disabled;
+        self
+    }
+}
+
+/// Progress of a game being downloaded, reported to the callback set with
+/// [`Installer::on_progress`]. Events are keyed by the id of the install, see
+/// [`install_id`](crate::models::install_id), so installs of the same game can be told apart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Progress {
+    /// Started downloading `total_bytes` of a game, spread over `total_files` files. No files
+    /// are reported for archive downloads, which don't assemble files.
+    Started {
+        install_id: String,
+        name: String,
+        total_bytes: u64,
+        total_files: usize,
+    },
+    /// Downloaded some bytes of a game
+    Downloaded { install_id: String, bytes: u64 },
+    /// Wrote some bytes of a game to disk
+    Written { install_id: String, bytes: u64 },
+    /// Wrote the last chunk of a file
+    FileWritten {
+        install_id: String,
+        file_name: String,
+    },
+    /// Done downloading a game, whether it succeeded or not
+    Finished { install_id: String },
+    /// A queued game was installed
+    Installed { install_id: String, version: String },
+    /// A queued game failed to install
+    Failed { install_id: String, error: String },
+    /// A queued game was paused or removed from the queue while downloading
+    Paused { install_id: String },
+    /// Downloads were limited to `limit` bytes per second by the rate limit or the download
+    /// schedule, or went back to full speed if it's `None`
+    Throttled { limit: Option<u64> },
+    /// Downloads were paused until the download schedule starts again
+    Suspended,
+}
+
+/// Space needed to install or download a build. Sizes are in bytes.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct InstallSize {
+    pub download_size: u64,
+    pub disk_size: u64,
+    /// Free space where the build would be installed, if it could be checked
+    pub free_space: Option<u64>,
+}
+
+/// Space needed to update an installed game. Sizes are in bytes.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct UpdateSize {
+    pub download_size: u64,
+    /// Size of the game once updated
+    pub disk_size: u64,
+    /// How much more space the game takes once updated. Negative if it gets smaller.
+    pub needed_space: i64,
+}
+
+/// Files that change between two builds of a game. Sizes are in bytes.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct BuildDiff {
+    pub added: Vec<ChangedFile>,
+    pub modified: Vec<ChangedFile>,
+    pub removed: Vec<ChangedFile>,
+    /// How much has to be downloaded to update from the old build to the new one
+    pub download_size: u64,
+    pub old_size: u64,
+    pub new_size: u64,
+}
+
+/// A file that was added, modified or removed in a build
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ChangedFile {
+    pub path: String,
+    pub size: u64,
+    /// File of the old build with the same contents. The file is copied from it instead of
+    /// being downloaded.
+    pub copied_from: Option<String>,
+}
+
+/// Installs, updates and downloads games.
+///
+/// Every install started from the same installer shares its download worker, memory and rate
+/// limits, so several games can be installed at the same time.
+#[derive(Clone)]
+pub struct Installer {
+    client: ApiClient,
+    options: InstallOptions,
+    archive: Option<PathBuf>,
+    pool: DownloadPool,
+}
+
+impl Installer {
+    pub fn new(client: &GalaClient, options: InstallOptions) -> Self {
+        Self {
+            client: client.api.clone(),
+            pool: DownloadPool::new(&options),
+            options,
+            archive: None,
+        }
+    }
+
+    /// Calls `on_progress` as games are downloaded
+    pub fn on_progress<F>(mut self, on_progress: F) -> Self
+    where
+        F: Fn(&Progress) + Send + Sync + 'static,
+    {
+        self.pool.progress = Some(Arc::new(on_progress));
+        self
+    }
+
+    /// Installs builds from a local archive created with [`Installer::download`] instead of
+    /// downloading them
+    pub fn from_archive(mut self, archive_path: PathBuf) -> Self {
+        self.archive = Some(archive_path);
+        self
+    }
+
+    fn source(&self) -> BuildSource {
+        match &self.archive {
+            Some(archive_path) => BuildSource::Archive(archive_path.to_owned()),
+            None => BuildSource::Cdn(self.client.clone()),
+        }
+    }
+
+    /// Installs `version` of a game, or its latest build for `os`. The game isn't added to the
+    /// installed config, so callers can decide where to keep track of it. Set `label` to
+    /// install the game next to its other installs.
+    pub async fn install(
+        &self,
+        product: &Product,
+        install_path: &Path,
+        version: Option<&ProductVersion>,
+        os: Option<BuildOs>,
+        label: Option<String>,
+    ) -> Result<InstallInfo, Error> {
+        utils::install(
+            self.source(),
+            product,
+            install_path,
+            self.options.clone(),
+            version,
+            os,
+            label,
+            &self.pool,
+        )
+        .await
+    }
+
+    /// Checks how much space installing a build would take
+    pub async fn install_size(
+        &self,
+        product: &Product,
+        install_path: &Path,
+        version: Option<&ProductVersion>,
+        os: Option<BuildOs>,
+    ) -> Result<InstallSize, Error> {
+        utils::install_size(
+            self.source(),
+            product,
+            install_path,
+            version,
+            os,
+            self.options.include_disabled,
+        )
+        .await
+    }
+
+    /// Checks how much space a build takes, without printing anything. The build manifest is
+    /// only fetched if it isn't the installed build.
+    pub async fn build_size(
+        &self,
+        product: &Product,
+        version: &ProductVersion,
+        install_info: Option<&InstallInfo>,
+    ) -> Result<InstallSize, Error> {
+        utils::build_size(self.source(), product, version, install_info).await
+    }
+
+    /// Downloads a build into a local archive without installing it. Builds are only added to
+    /// the archive once every chunk was downloaded.
+    pub async fn download(
+        &self,
+        product: &Product,
+        archive_path: &Path,
+        version: Option<&ProductVersion>,
+        os: Option<BuildOs>,
+    ) -> Result<(), Error> {
+        utils::download(
+            self.client.clone(),
+            product,
+            archive_path,
+            self.options.clone(),
+            version,
+            os,
+            &self.pool,
+        )
+        .await
+    }
+
+    /// Checks how much space downloading a build into an archive would take
+    pub async fn download_size(
+        &self,
+        product: &Product,
+        archive_path: &Path,
+        version: Option<&ProductVersion>,
+        os: Option<BuildOs>,
+    ) -> Result<InstallSize, Error> {
+        utils::download_size(
+            &self.client,
+            product,
+            archive_path,
+            version,
+            os,
+            self.options.include_disabled,
+        )
+        .await
+    }
+
+    /// Updates (or downgrades) an installed game to `version`, or to its latest build. Only
+    /// the files that changed are downloaded.
+    pub async fn update(
+        &self,
+        product: &Product,
+        install_info: &InstallInfo,
+        version: Option<&ProductVersion>,
+    ) -> Result<InstallInfo, Error> {
+        utils::update(
+            self.client.clone(),
+            product,
+            self.options.clone(),
+            install_info,
+            version,
+            &self.pool,
+        )
+        .await
+    }
+
+    /// Checks how much space updating an installed game would take
+    pub async fn update_size(
+        &self,
+        product: &Product,
+        install_info: &InstallInfo,
+        version: Option<&ProductVersion>,
+    ) -> Result<UpdateSize, Error> {
+        utils::update_size(
+            &self.client,
+            product,
+            install_info,
+            version,
+            self.options.include_disabled,
+        )
+        .await
+    }
+
+    /// Fetches the build manifest and chunk manifest of a build, without storing them
+    pub async fn manifests(
+        &self,
+        product: &Product,
+        version: &ProductVersion,
+    ) -> Result<Manifests, Error> {
+        let source = self.source();
+        let manifest = source.build_manifest(product, version).await?;
+        let manifest_chunks = source.build_manifest_chunks(product, version).await?;
+        Ok(Manifests::parse(&manifest, &manifest_chunks)?)
+    }
+
+    /// Compares the files of two builds of a game, e.g. to see what an update or a downgrade
+    /// would change. Installs of the game aren't touched.
+    pub async fn diff(
+        &self,
+        product: &Product,
+        from: &ProductVersion,
+        to: &ProductVersion,
+    ) -> Result<BuildDiff, Error> {
+        utils::diff_builds(self.source(), product, from, to).await
+    }
+
+    /// Stops downloading the install `install_id`, e.g. after pausing it in the download
+    /// queue. Returns `false` if it isn't being downloaded.
+    pub fn pause(&self, install_id: &str) -> bool {
+        self.pool.cancel(install_id)
+    }
+
+    /// Lock to hold while changing the download queue, so the changes aren't overwritten by
+    /// [`Installer::process_queue`] storing the queue at the same time
+    pub fn queue_lock(&self) -> Arc<tokio::sync::Mutex<()>> {
+        self.pool.queue_lock.clone()
+    }
+
+    /// Installs every queued game in order of priority, running up to `max_parallel_installs`
+    /// installs at the same time. Installed games are removed from the queue and added to the
+    /// installed config. Fails if the queue or installed config can't be read or saved; games
+    /// that fail to install are marked as failed in the queue instead.
+    pub async fn process_queue(&self, max_parallel_installs: usize) -> Result<(), Error> {
+        utils::process_queue(
+            self.client.clone(),
+            self.options.clone(),
+            max_parallel_installs,
+            &self.pool,
+        )
+        .await
+    }
+}
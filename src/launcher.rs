@@ -0,0 +1,64 @@
+#[cfg(not(target_os = "windows"))]
+use std::path::PathBuf;
+use std::process::ExitStatus;
+
+use crate::{
+    api::ApiClient,
+    client::GalaClient,
+    shared::models::{api::Product, InstallInfo},
+    utils,
+};
+
+/// Launches installed games, running Windows games through Wine on Linux and macOS
+#[derive(Clone)]
+pub struct Launcher {
+    client: ApiClient,
+    #[cfg(not(target_os = "windows"))]
+    wine_bin: Option<PathBuf>,
+    #[cfg(not(target_os = "windows"))]
+    wine_prefix: Option<PathBuf>,
+}
+
+impl Launcher {
+    pub fn new(client: &GalaClient) -> Self {
+        Self {
+            client: client.api.clone(),
+            #[cfg(not(target_os = "windows"))]
+            wine_bin: None,
+            #[cfg(not(target_os = "windows"))]
+            wine_prefix: None,
+        }
+    }
+
+    /// Wine binary used to run Windows games. Windows games can't be launched without one.
+    #[cfg(not(target_os = "windows"))]
+    pub fn wine_bin(mut self, wine_bin: Option<PathBuf>) -> Self {
+        self.wine_bin = wine_bin;
+        self
+    }
+
+    /// Wine prefix used to run Windows games. Wine's default prefix is used if not set.
+    #[cfg(not(target_os = "windows"))]
+    pub fn wine_prefix(mut self, wine_prefix: Option<PathBuf>) -> Self {
+        self.wine_prefix = wine_prefix;
+        self
+    }
+
+    /// Launches a game and waits for it to exit. Returns `None` if no executable was found.
+    pub async fn launch(
+        &self,
+        product: &Product,
+        install_info: &InstallInfo,
+    ) -> std::io::Result<Option<ExitStatus>> {
+        utils::launch(
+            &self.client,
+            product,
+            install_info,
+            #[cfg(not(target_os = "windows"))]
+            self.wine_bin.clone(),
+            #[cfg(not(target_os = "windows"))]
+            self.wine_prefix.clone(),
+        )
+        .await
+    }
+}
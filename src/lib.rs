@@ -0,0 +1,83 @@
+//! Library for managing an IndieGala game library. It powers the `freecarnival` CLI, and can be
+//! used to build other frontends on top of the same config files.
+//!
+//! Start a [`GalaClient`] session to login and sync the library, then use an [`Installer`] to
+//! install, update and download games, a [`Verifier`] to check installed files and a
+//! [`Launcher`] to run games. On Unix, a [`daemon::Daemon`] can keep a session and the download
+//! queue running in the background, controlled over a JSON-RPC socket.
+//!
+//! The library doesn't print anything. Messages about what it's doing are logged with the `log`
+//! crate, and download progress is reported as [`Progress`] events.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use freecarnival::{
+//!     config::{GalaConfig, InstalledConfig, SettingsConfig},
+//!     Endpoints, GalaClient, InstallOptions, Installer,
+//! };
+//!
+//! # async fn run() -> Result<(), freecarnival::Error> {
+//! let settings = SettingsConfig::load()?;
+//! let client = GalaClient::new(Endpoints::from_settings(&settings), Duration::from_secs(60))?;
+//! let library = client.sync().await?.expect("Not logged in");
+//! let product = library.product("syberia-ii").expect("Not in library");
+//!
+//! // Progress is reported as events, which frontends turn into progress bars
+//! let (progress_tx, _progress_rx) = std::sync::mpsc::channel();
+//! let installer = Installer::new(&client, InstallOptions::default())
+//!     .on_progress(move |progress| drop(progress_tx.send(progress.clone())));
+//! let install_info = installer
+//!     .install(product, "/games/syberia-ii".as_ref(), None, None, None)
+//!     .await?;
+//!
+//! let mut installed = InstalledConfig::load()?;
+//! installed.insert(product.slugged_name.clone(), install_info);
+//! installed.store()?;
+//! client.save()?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod api;
+pub mod archive;
+pub mod cache;
+mod client;
+pub mod config;
+pub mod constants;
+#[cfg(unix)]
+pub mod daemon;
+mod delta;
+mod error;
+mod helpers;
+mod installer;
+mod launcher;
+pub mod manifest;
+pub mod search;
+pub mod serve;
+mod shared;
+mod source;
+mod throttle;
+mod utils;
+mod verifier;
+
+/// Games, builds and installs
+pub mod models {
+    pub use crate::shared::models::{
+        api::{compare_versions, BuildOs, GameDetails, Product, ProductVersion},
+        install_id, DownloadSchedule, InstallInfo, QueueStatus, QueuedInstall,
+    };
+}
+
+pub use api::Endpoints;
+pub use client::GalaClient;
+pub use config::LibraryConfig as Library;
+pub use error::Error;
+pub use installer::{
+    BuildDiff, ChangedFile, InstallOptions, InstallSize, Installer, Progress, UpdateSize,
+};
+pub use launcher::Launcher;
+pub use utils::{
+    check_updates, disk_usage, prune_install_manifests, prune_manifests, uninstall, AvailableUpdate,
+};
+pub use verifier::Verifier;
@@ -0,0 +1,63 @@
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::progress;
+
+type Sink = Box<dyn Fn(String) + Send + Sync>;
+
+lazy_static! {
+    /// Where messages are sent instead of being printed, e.g. while the TUI is drawn
+    static ref SINK: RwLock<Option<Sink>> = RwLock::new(None);
+}
+
+/// Prints the messages logged by the library, above the progress bars
+struct Logger;
+
+static LOGGER: Logger = Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let message = match record.level() {
+            Level::Error | Level::Warn | Level::Info => record.args().to_string(),
+            Level::Debug | Level::Trace => format!("[{}] {}", record.level(), record.args()),
+        };
+        match SINK.read().unwrap().as_ref() {
+            Some(sink) => sink(message),
+            None => progress::println(&message),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Starts printing the messages logged by the library. Debug messages are only printed if
+/// `verbose` is set.
+pub(crate) fn init(verbose: bool) {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(if verbose {
+            LevelFilter::Debug
+        } else {
+            LevelFilter::Info
+        });
+    }
+}
+
+/// Sends logged messages to `sink` instead of printing them, until [`reset`] is called
+pub(crate) fn redirect(sink: impl Fn(String) + Send + Sync + 'static) {
+    *SINK.write().unwrap() = Some(Box::new(sink));
+}
+
+/// Prints logged messages again
+pub(crate) fn reset() {
+    *SINK.write().unwrap() = None;
+}
@@ -1,41 +1,79 @@
-use std::sync::Arc;
+use std::{io::IsTerminal, sync::Arc, time::Duration};
 
-use crate::cli::Cli;
-use crate::config::GalaConfig;
-use crate::{api::auth, config::InstalledConfig};
-use api::GalaClient;
 use clap::Parser;
-use cli::Commands;
-use config::{CookieConfig, LibraryConfig, UserConfig};
-use constants::DEFAULT_BASE_INSTALL_PATH;
-use reqwest_cookie_store::CookieStoreMutex;
-use shared::models::api::{LoginResult, SyncResult};
+use cli::{
+    CacheCommands, Cli, Commands, ManifestBuildOpts, ManifestCommands, QueueCommands, Service,
+    SettingsCommands, Toggle,
+};
+use crossterm::style::Stylize;
+#[cfg(unix)]
+use freecarnival::daemon::{self, Daemon, DaemonClient};
+use freecarnival::{
+    archive::ArchiveIndex,
+    cache::ChunkCache,
+    check_updates,
+    config::{GalaConfig, InstalledConfig, LibraryConfig, QueueConfig, SettingsConfig},
+    constants::{DEFAULT_BASE_INSTALL_PATH, DEFAULT_CHUNK_CACHE_SIZE},
+    disk_usage,
+    manifest::Manifests,
+    models::{install_id, BuildOs, DownloadSchedule, QueueStatus, QueuedInstall},
+    prune_install_manifests, prune_manifests,
+    search::LibraryQuery,
+    serve, uninstall, Error, GalaClient, InstallOptions, Installer, Launcher, Verifier,
+};
+use futures::StreamExt;
+use human_bytes::human_bytes;
+use progress::ProgressBars;
+use resolve::{select_download, select_install};
 
-mod api;
 mod cli;
-mod config;
-mod constants;
-mod helpers;
-mod shared;
-mod utils;
+#[cfg(unix)]
+mod forward;
+mod logger;
+mod progress;
+mod resolve;
+mod tui;
 
 #[tokio::main]
 async fn main() {
-    let args = Cli::parse();
-    let CookieConfig(cookie_store) = CookieConfig::load().expect("Failed to load cookie store");
-    let cookie_store = Arc::new(CookieStoreMutex::new(cookie_store));
-    let client = reqwest::Client::with_gala(&cookie_store);
+    let mut args = Cli::parse();
+    logger::init(args.verbose);
+    #[cfg(unix)]
+    let socket_path = args
+        .socket
+        .clone()
+        .unwrap_or_else(daemon::default_socket_path);
+    #[cfg(unix)]
+    if let Ok(mut daemon) = DaemonClient::connect(&socket_path).await {
+        if !resolve::resolve_command(&mut args.command) {
+            return;
+        }
+        if forward::forward(&mut daemon, &args.command).await {
+            return;
+        }
+    }
+
+    let settings = SettingsConfig::load().expect("Failed to load settings");
+    let client = GalaClient::new(
+        args.network.endpoints(&settings),
+        Duration::from_secs(args.network.timeout),
+    )
+    .expect("Failed to load cookie store");
 
     if args.needs_sync() {
         println!("Syncing library...");
-        match api::auth::sync(&client).await {
-            Ok(result) => save_user_info(&result),
+        match client.sync().await {
+            Ok(None) => println!("Failed to sync data. Are you logged in?"),
+            Ok(Some(_)) => {}
             Err(err) => {
                 println!("Failed to sync: {err:#?}");
                 return;
             }
         };
     }
+    if !resolve::resolve_command(&mut args.command) {
+        return;
+    }
 
     match args.command {
         Commands::Login { email, password } => {
@@ -46,124 +84,255 @@ async fn main() {
                 }
             };
 
-            match auth::login(&client, &email, &password).await {
-                Ok(Some(LoginResult { message, status })) => {
-                    if status != "success" {
-                        println!("Login failed: {}", message);
-                        return;
-                    }
-
-                    match auth::sync(&client).await {
-                        Ok(result) => save_user_info(&result),
-                        Err(err) => println!("Failed to sync: {err:#?}"),
-                    };
+            match client.login(&email, &password).await {
+                Ok(()) => match client.sync().await {
+                    Ok(None) => println!("Failed to sync data. Are you logged in?"),
+                    Ok(Some(_)) => {}
+                    Err(err) => println!("Failed to sync: {err:#?}"),
+                },
+                Err(err @ Error::LoginFailed(_)) => {
+                    println!("{}", err);
+                    return;
                 }
-                Ok(None) => {
+                Err(Error::InvalidResponse) => {
                     println!("Failed to parse login response");
                 }
                 Err(err) => println!("Failed to login: {err:#?}"),
             }
         }
         Commands::Logout => {
-            UserConfig::clear().expect("Error clearing user config");
-            LibraryConfig::clear().expect("Error clearing library");
-            cookie_store.lock().unwrap().clear();
+            client.logout().expect("Error clearing user config");
         }
-        Commands::Library => {
+        Commands::Library { query } => {
             let library = LibraryConfig::load().expect("Failed to load library");
-            for product in library.collection {
+            let installed = InstalledConfig::load().expect("Failed to load installed");
+            let products = LibraryQuery::from(&query)
+                .run(library.collection, &installed)
+                .await;
+            for product in products {
                 println!("{}", product);
             }
         }
         Commands::Install {
-            slug,
+            slugs,
+            from_file,
             version,
             path,
             base_path,
             os,
+            label,
+            priority,
+            from_archive,
+            queue_opts,
             install_opts,
         } => {
-            let mut installed = InstalledConfig::load().expect("Failed to load installed");
-            if installed.contains_key(&slug) && !install_opts.info {
-                println!("{slug} already installed.");
+            let mut slugs = slugs;
+            if let Some(from_file) = from_file {
+                match std::fs::read_to_string(&from_file) {
+                    Ok(contents) => slugs.extend(
+                        contents
+                            .lines()
+                            .map(|line| line.trim())
+                            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                            .map(|line| line.to_owned()),
+                    ),
+                    Err(err) => {
+                        println!("Failed to read {}: {:?}", from_file.display(), err);
+                        return;
+                    }
+                }
+            }
+            if slugs.len() > 1 && (version.is_some() || path.is_some()) {
+                println!("--version and --path can only be used when installing a single game");
                 return;
             }
 
-            let install_path = match (path, base_path) {
-                (Some(path), _) => path,
-                (None, Some(base_path)) => base_path.join(&slug),
-                (None, None) => DEFAULT_BASE_INSTALL_PATH.join(&slug),
+            let installed = InstalledConfig::load().expect("Failed to load installed");
+            let products = match &from_archive {
+                Some(archive_path) => match ArchiveIndex::load(archive_path) {
+                    Ok(index) => index.products,
+                    Err(err) => {
+                        println!("Failed to read archive: {:?}", err);
+                        return;
+                    }
+                },
+                None => {
+                    LibraryConfig::load()
+                        .expect("Failed to load library")
+                        .collection
+                }
+            };
+            let mut queue = QueueConfig::load().expect("Failed to load download queue");
+            let installer = new_installer(&client, InstallOptions::from(&install_opts));
+            let size_installer = match &from_archive {
+                Some(archive_path) => installer.clone().from_archive(archive_path.to_owned()),
+                None => installer.clone(),
             };
+            for slug in slugs {
+                let install_id = install_id(&slug, label.as_deref());
+                let install_path = match (&path, &base_path) {
+                    (Some(path), _) => path.to_owned(),
+                    (None, Some(base_path)) => base_path.join(&install_id),
+                    (None, None) => DEFAULT_BASE_INSTALL_PATH.join(&install_id),
+                };
+                let installs = installed.installs(&slug);
+                if installs.iter().any(|i| i.label == label) && !install_opts.info {
+                    println!("{install_id} already installed.");
+                    if label.is_none() {
+                        println!("Use --label to install another build next to it.");
+                    }
+                    continue;
+                }
+                if installs.iter().any(|i| i.install_path == install_path) {
+                    println!(
+                        "{} already has an install of {slug}.",
+                        install_path.display()
+                    );
+                    continue;
+                }
+                if queue.get(&install_id).is_some() && !install_opts.info {
+                    println!("{install_id} is already queued.");
+                    continue;
+                }
 
-            let library = LibraryConfig::load().expect("Failed to load library");
-            let selected_version = match (
-                version,
-                library.collection.iter().find(|p| p.slugged_name == slug),
-            ) {
-                (Some(version), Some(product)) => {
-                    match product.version.iter().find(|v| {
-                        v.version == version
-                            && match &os {
-                                Some(target) => v.os == *target,
-                                None => true,
-                            }
-                    }) {
+                let product = match products.iter().find(|p| p.slugged_name == slug) {
+                    Some(product) => product,
+                    None => {
+                        match from_archive {
+                            Some(_) => println!("{slug} is not in the archive"),
+                            None => println!("{slug} is not in your library"),
+                        }
+                        continue;
+                    }
+                };
+                let selected_version = match &version {
+                    Some(version) => match product.find_version(version, os.as_ref()) {
                         Some(version) => Some(version),
                         None => {
                             println!("Can't find or install build {version} for {slug}");
-                            return;
+                            continue;
                         }
-                    }
+                    },
+                    None => None,
+                };
+
+                if install_opts.info {
+                    match size_installer
+                        .install_size(product, &install_path, selected_version, os.clone())
+                        .await
+                    {
+                        Ok(size) => {
+                            println!("Download Size: {}", human_bytes(size.download_size as f64));
+                            println!("Disk Size: {}", human_bytes(size.disk_size as f64));
+                            if let Some(free_space) = size.free_space {
+                                println!("Free Space: {}", human_bytes(free_space as f64));
+                            }
+                        }
+                        Err(err) => {
+                            println!("Failed to install {}: {}", &slug, err);
+                        }
+                    };
+                    continue;
                 }
-                (_, None) => {
+
+                println!("Adding {slug} to the download queue...");
+                let mut download = QueuedInstall::new(slug, install_path);
+                download.version = version.clone();
+                download.os = os.clone();
+                download.priority = priority;
+                download.skip_verify = install_opts.skip_verify;
+                download.archive = from_archive.clone();
+                download.include_disabled = install_opts.include_disabled;
+                download.force = install_opts.force;
+                download.preallocate = install_opts.preallocate;
+                download.label = label.clone();
+                queue.downloads.push(download);
+            }
+            if install_opts.info {
+                return;
+            }
+            queue.store().expect("Failed to update download queue");
+
+            if let Err(err) = installer
+                .process_queue(queue_opts.max_parallel_installs)
+                .await
+            {
+                println!("Failed to process the download queue: {}", err);
+            }
+        }
+        Commands::Download {
+            slug,
+            version,
+            os,
+            to,
+            install_opts,
+        } => {
+            let library = LibraryConfig::load().expect("Failed to load library");
+            let product = match library.product(&slug) {
+                Some(product) => product,
+                None => {
                     println!("{slug} is not in your library");
                     return;
                 }
-                _ => None,
             };
-            match utils::install(
-                client.clone(),
-                &slug,
-                &install_path,
-                install_opts,
-                selected_version,
-                os,
-            )
-            .await
-            {
-                Ok(Ok((info, Some(install_info)))) => {
-                    println!("{}", info);
+            let selected_version = match &version {
+                Some(version) => match product.find_version(version, os.as_ref()) {
+                    Some(version) => Some(version),
+                    None => {
+                        println!("Can't find build {version} for {slug}");
+                        return;
+                    }
+                },
+                None => None,
+            };
 
-                    installed.insert(slug, install_info);
-                    installed
-                        .store()
-                        .expect("Failed to update installed config");
-                }
-                Ok(Ok((info, None))) => {
-                    println!("{}", info);
-                }
-                Ok(Err(err)) => {
-                    println!("Failed to install {}: {:?}", &slug, err);
+            let installer = new_installer(&client, InstallOptions::from(&install_opts));
+            if install_opts.info {
+                match installer
+                    .download_size(product, &to, selected_version, os)
+                    .await
+                {
+                    Ok(size) => {
+                        println!("Download Size: {}", human_bytes(size.download_size as f64));
+                        if let Some(free_space) = size.free_space {
+                            println!("Free Space: {}", human_bytes(free_space as f64));
+                        }
+                    }
+                    Err(err) => println!("Failed to download {slug}: {err}"),
                 }
-                Err(err) => {
-                    println!("Failed to install {}: {:?}", &slug, err);
+                return;
+            }
+
+            match installer
+                .download(product, &to, selected_version, os)
+                .await
+            {
+                Ok(()) => println!("Successfully downloaded {} to {}", slug, to.display()),
+                Err(err @ Error::NotEnoughSpace { .. }) => {
+                    println!("Failed to download {slug}: {err} Use --force to download anyway.")
                 }
-            };
+                Err(err @ Error::BuildDisabled { .. }) => println!(
+                    "Failed to download {slug}: {err}. Use --include-disabled to download it anyway."
+                ),
+                Err(Error::ChunksFailed) => println!(
+                    "Failed to download {slug}: Some chunks failed to download. Run the command again to retry them."
+                ),
+                Err(err) => println!("Failed to download {slug}: {err}"),
+            }
         }
-        Commands::Uninstall { slug, keep } => {
+        Commands::Uninstall { slug, keep, label } => {
             let mut installed = InstalledConfig::load().expect("Failed to load installed");
-            let install_info = match installed.remove(&slug) {
-                Some(info) => info,
-                None => {
-                    println!("{slug} is not installed.");
-                    return;
-                }
+            let install_info = match select_install(&installed, &slug, label.as_deref()) {
+                Some(info) => info.clone(),
+                None => return,
             };
+            installed.remove(&slug, install_info.label.as_deref());
+            let slug = install_info.id(&slug);
 
             let folder_removed = if keep {
                 false
             } else {
-                match utils::uninstall(&install_info.install_path).await {
+                match uninstall(&install_info.install_path).await {
                     Ok(()) => true,
                     Err(err) => {
                         println!("Failed to uninstall {slug}: {:?}", err);
@@ -174,6 +343,9 @@ async fn main() {
             installed
                 .store()
                 .expect("Failed to update installed config");
+            if let Err(err) = prune_install_manifests(&installed, &slug).await {
+                println!("Failed to delete the manifests of {slug}: {err}");
+            }
             println!(
                 "{slug} uninstalled successfuly. {} was {}.",
                 install_info.install_path.display(),
@@ -188,15 +360,24 @@ async fn main() {
             let installed = InstalledConfig::load().expect("Failed to load installed");
             let library = LibraryConfig::load().expect("Failed to load library");
 
-            match utils::check_updates(library, installed).await {
+            match check_updates(library, installed).await {
                 Ok(available_updates) => {
                     if available_updates.is_empty() {
                         println!("No available updates");
                         return;
                     }
 
-                    for (slug, latest_version) in available_updates {
-                        println!("{slug} has an update -> {latest_version}");
+                    let dim_held = std::io::stdout().is_terminal();
+                    for update in available_updates {
+                        let line =
+                            format!("{} has an update -> {}", update.install_id, update.version);
+                        match update.held {
+                            Some(reason) if dim_held => {
+                                println!("{}", format!("{line} ({reason})").dim())
+                            }
+                            Some(reason) => println!("{line} ({reason})"),
+                            None => println!("{line}"),
+                        }
                     }
                 }
                 Err(err) => {
@@ -204,67 +385,141 @@ async fn main() {
                 }
             };
         }
-        Commands::Update {
+        Commands::Hold {
             slug,
             version,
-            install_opts,
+            label,
         } => {
             let mut installed = InstalledConfig::load().expect("Failed to load installed");
-            let install_info = match installed.remove(&slug) {
-                Some(info) => info,
+            let mut install_info = match select_install(&installed, &slug, label.as_deref()) {
+                Some(info) => info.clone(),
+                None => return,
+            };
+            if let Some(version) = &version {
+                let library = LibraryConfig::load().expect("Failed to load library");
+                let found = library
+                    .product(&slug)
+                    .and_then(|product| product.find_version(version, Some(&install_info.os)));
+                if found.is_none() {
+                    println!("Couldn't find build {version} for {slug}");
+                    return;
+                }
+            }
+
+            let held_version = version.unwrap_or_else(|| install_info.version.clone());
+            println!(
+                "{} is held at {held_version}. It won't be updated past it.",
+                install_info.id(&slug)
+            );
+            install_info.hold = Some(held_version);
+            installed.insert(slug, install_info);
+            installed
+                .store()
+                .expect("Failed to update installed config");
+        }
+        Commands::Unhold { slug, label } => {
+            let mut installed = InstalledConfig::load().expect("Failed to load installed");
+            let mut install_info = match select_install(&installed, &slug, label.as_deref()) {
+                Some(info) => info.clone(),
+                None => return,
+            };
+            match install_info.hold.take() {
+                Some(_) => println!("{} is no longer held.", install_info.id(&slug)),
                 None => {
-                    println!("{slug} is not installed.");
+                    println!("{} isn't held.", install_info.id(&slug));
                     return;
                 }
+            }
+            installed.insert(slug, install_info);
+            installed
+                .store()
+                .expect("Failed to update installed config");
+        }
+        Commands::Update {
+            slug,
+            version,
+            label,
+            install_opts,
+        } => {
+            let mut installed = InstalledConfig::load().expect("Failed to load installed");
+            let install_info = match select_install(&installed, &slug, label.as_deref()) {
+                Some(info) => info.clone(),
+                None => return,
             };
             let library = LibraryConfig::load().expect("Failed to load library");
-            let selected_version = match (
-                version,
-                library.collection.iter().find(|p| p.slugged_name == slug),
-            ) {
-                (Some(version), Some(product)) => {
-                    match product.version.iter().find(|v| v.version == version) {
-                        Some(version) => Some(version),
-                        None => {
-                            println!("Couldn't find build {version} for {slug}");
-                            return;
-                        }
-                    }
-                }
-                (_, None) => {
+            let product = match library.product(&slug) {
+                Some(product) => product,
+                None => {
                     println!("{slug} is not in your library");
                     return;
                 }
-                _ => None,
+            };
+            let selected_version = match &version {
+                Some(version) => match product.find_version(version, Some(&install_info.os)) {
+                    Some(version) => Some(version),
+                    None => {
+                        println!("Couldn't find build {version} for {slug}");
+                        return;
+                    }
+                },
+                None => None,
             };
 
-            match utils::update(
-                client.clone(),
-                &library,
-                &slug,
-                install_opts,
-                &install_info,
-                selected_version,
-            )
-            .await
+            let installer = new_installer(&client, InstallOptions::from(&install_opts));
+            if install_opts.info {
+                match installer
+                    .update_size(product, &install_info, selected_version)
+                    .await
+                {
+                    Ok(size) => {
+                        println!("Download Size: {}", human_bytes(size.download_size as f64));
+                        println!(
+                            "Needed Space: {}{}",
+                            if size.needed_space < 0 { "-" } else { "" },
+                            human_bytes(size.needed_space.unsigned_abs() as f64)
+                        );
+                        println!("Total Disk Size: {}", human_bytes(size.disk_size as f64));
+                    }
+                    Err(err) => println!("Failed to update {slug}: {err}"),
+                }
+                return;
+            }
+
+            match installer
+                .update(product, &install_info, selected_version)
+                .await
             {
-                Ok((info, Some(install_info))) => {
-                    println!("{}", info);
+                Ok(install_info) => {
+                    let install_id = install_info.id(&slug);
+                    println!("Updated {install_id} successfully.");
                     installed.insert(slug, install_info);
                     installed
                         .store()
                         .expect("Failed to update installed config");
+                    if let Err(err) = prune_install_manifests(&installed, &install_id).await {
+                        println!("Failed to delete the manifests of the old build: {err}");
+                    }
                 }
-                Ok((info, None)) => {
-                    println!("{}", info);
+                Err(Error::AlreadyInstalled(version)) => {
+                    println!("Build {version} is already installed");
                 }
+                Err(err @ Error::UpdateHeld(_)) => println!(
+                    "Not updating {slug}: {err}. Use `unhold` or --version to update it anyway."
+                ),
+                Err(err @ Error::NotEnoughSpace { .. }) => {
+                    println!("Failed to update {slug}: {err} Use --force to update anyway.");
+                }
+                Err(err @ Error::BuildDisabled { .. }) => println!(
+                    "Failed to update {slug}: {err}. Use --include-disabled to update anyway."
+                ),
                 Err(err) => {
-                    println!("Failed to update {slug}: {:?}", err);
+                    println!("Failed to update {slug}: {err}");
                 }
             };
         }
         Commands::Launch {
             slug,
+            label,
             #[cfg(not(target_os = "windows"))]
             wine_bin,
             #[cfg(not(target_os = "windows"))]
@@ -272,14 +527,11 @@ async fn main() {
         } => {
             let installed = InstalledConfig::load().expect("Failed to load installed");
             let library = LibraryConfig::load().expect("Failed to load library");
-            let install_info = match installed.get(&slug) {
+            let install_info = match select_install(&installed, &slug, label.as_deref()) {
                 Some(info) => info,
-                None => {
-                    println!("{slug} is not installed");
-                    return;
-                }
+                None => return,
             };
-            let product = match library.collection.iter().find(|p| p.slugged_name == slug) {
+            let product = match library.product(&slug) {
                 Some(prod) => prod,
                 None => {
                     println!("Couldn't find {slug} in library");
@@ -287,17 +539,10 @@ async fn main() {
                 }
             };
 
-            match utils::launch(
-                &client,
-                product,
-                install_info,
-                #[cfg(not(target_os = "windows"))]
-                wine_bin,
-                #[cfg(not(target_os = "windows"))]
-                wine_prefix,
-            )
-            .await
-            {
+            let launcher = Launcher::new(&client);
+            #[cfg(not(target_os = "windows"))]
+            let launcher = launcher.wine_bin(wine_bin).wine_prefix(wine_prefix);
+            match launcher.launch(product, install_info).await {
                 Ok(Some(status)) => {
                     println!("Process exited with: {}", status);
                 }
@@ -311,7 +556,7 @@ async fn main() {
         }
         Commands::Info { slug } => {
             let library = LibraryConfig::load().expect("Failed to load library");
-            let product = match library.collection.iter().find(|p| p.slugged_name == slug) {
+            let product = match library.product(&slug) {
                 Some(p) => p,
                 None => {
                     println!("{slug} is not in your library");
@@ -320,61 +565,630 @@ async fn main() {
             };
 
             let installed = InstalledConfig::load().expect("Failed to load installed");
-            let install_info = installed.get(&slug);
+            let installs = installed.installs(&slug);
 
-            println!(
-                "Available Versions:\n{}",
-                product
-                    .version
+            println!("{}", product);
+            if installs.is_empty() {
+                println!("Not installed");
+            }
+            for info in installs {
+                match &info.label {
+                    Some(label) => println!(
+                        "Installed Version: {} ({}, {label})",
+                        info.version,
+                        info.os.name()
+                    ),
+                    None => println!("Installed Version: {} ({})", info.version, info.os.name()),
+                }
+                println!("Install Path: {}", info.install_path.display());
+                match disk_usage(&info.install_path).await {
+                    Ok(size) => println!("Size on Disk: {}", human_bytes(size as f64)),
+                    Err(err) => println!("Failed to check size on disk: {}", err),
+                }
+                match product.update_for(info) {
+                    Some(latest) => println!("Update Available: {}", latest.version),
+                    None => println!("Up to date"),
+                }
+                if let Some(held_version) = &info.hold {
+                    match product.held_update(info) {
+                        Some(latest) => {
+                            println!("Held at {held_version}, {} is available", latest.version)
+                        }
+                        None => println!("Held at {held_version}"),
+                    }
+                }
+            }
+
+            match client.game_details(product).await {
+                Ok(Some(details)) => {
+                    if let Some(exe_path) = details.exe_path {
+                        println!("Executable: {exe_path}");
+                    }
+                    if let Some(args) = details.args.filter(|args| !args.is_empty()) {
+                        println!("Arguments: {args}");
+                    }
+                    if let Some(cwd) = details.cwd.filter(|cwd| !cwd.is_empty()) {
+                        println!("Working Directory: {cwd}");
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => println!("Failed to fetch game details: {err}"),
+            }
+
+            let installer = Installer::new(&client, InstallOptions::default());
+            let mut versions: Vec<_> = product.version.iter().collect();
+            versions.sort_by_key(|v| std::cmp::Reverse(v.date));
+            // Games can have many builds, so only a few manifests are fetched at a time
+            let sizes: Vec<_> = futures::stream::iter(versions.iter().map(|version| {
+                let install_info = installs
                     .iter()
-                    .map(|v| format!("\n{}", v))
-                    .collect::<Vec<String>>()
-                    .join("\n")
-            );
+                    .find(|i| i.version == version.version && i.os == version.os);
+                installer.build_size(product, version, install_info)
+            }))
+            .buffered(4)
+            .collect()
+            .await;
+
+            println!("\nAvailable Versions:");
+            for (version, size) in versions.into_iter().zip(sizes) {
+                println!();
+                match size {
+                    Ok(size) => {
+                        println!("Download Size: {}", human_bytes(size.download_size as f64));
+                        println!("Install Size: {}", human_bytes(size.disk_size as f64));
+                    }
+                    Err(err) => println!("Size: unknown ({err})"),
+                }
+                print!("{}", version);
+            }
         }
-        Commands::Verify { slug } => {
+        Commands::Versions { slug, os } => {
+            let library = LibraryConfig::load().expect("Failed to load library");
+            let product = match library.product(&slug) {
+                Some(product) => product,
+                None => {
+                    println!("{slug} is not in your library");
+                    return;
+                }
+            };
             let installed = InstalledConfig::load().expect("Failed to load installed");
-            let install_info = match installed.get(&slug) {
-                Some(info) => info,
+
+            let mut versions: Vec<_> = product
+                .version
+                .iter()
+                .filter(|version| os.as_ref().map_or(true, |os| version.os == *os))
+                .collect();
+            if versions.is_empty() {
+                println!("{slug} has no builds for that OS");
+                return;
+            }
+            versions.sort_by(|a, b| b.cmp_build(a));
+            for version in versions {
+                let mut notes: Vec<String> = installed
+                    .installs(&slug)
+                    .iter()
+                    .filter(|info| info.version == version.version && info.os == version.os)
+                    .map(|info| match info.label {
+                        Some(_) => format!("installed as {}", info.id(&slug)),
+                        None => "installed".to_owned(),
+                    })
+                    .collect();
+                notes.extend(version.disabled_reason());
+                let notes = if notes.is_empty() {
+                    String::new()
+                } else {
+                    format!(" ({})", notes.join(", "))
+                };
+                println!(
+                    "[{}] {} {}{notes}",
+                    version.version,
+                    version.date,
+                    version.os.name()
+                );
+            }
+        }
+        Commands::Diff { slug, from, to, os } => {
+            let library = LibraryConfig::load().expect("Failed to load library");
+            let product = match library.product(&slug) {
+                Some(product) => product,
+                None => {
+                    println!("{slug} is not in your library");
+                    return;
+                }
+            };
+            let os = os.or_else(|| {
+                let settings = SettingsConfig::load().unwrap_or_default();
+                settings.os_preference(&slug).into_iter().find(|os| {
+                    product.find_version(&from, Some(os)).is_some()
+                        && product.find_version(&to, Some(os)).is_some()
+                })
+            });
+            let from_build = match product.find_version(&from, os.as_ref()) {
+                Some(version) => version,
                 None => {
-                    println!("{slug} is not installed.");
+                    println!("Couldn't find build {from} for {slug}");
+                    return;
+                }
+            };
+            let to_build = match product.find_version(&to, Some(&from_build.os)) {
+                Some(version) => version,
+                None => {
+                    println!(
+                        "Couldn't find build {to} for {slug} on {}",
+                        from_build.os.name()
+                    );
+                    return;
+                }
+            };
+
+            let installer = Installer::new(&client, InstallOptions::default());
+            let diff = match installer.diff(product, from_build, to_build).await {
+                Ok(diff) => diff,
+                Err(err) => {
+                    println!("Failed to compare builds: {err}");
                     return;
                 }
             };
+            println!("{slug} {from} -> {to} ({})", from_build.os.name());
+            for (change, files) in [
+                ("Added", &diff.added),
+                ("Modified", &diff.modified),
+                ("Removed", &diff.removed),
+            ] {
+                for file in files {
+                    match &file.copied_from {
+                        Some(source) => println!(
+                            "{change}: {} ({}, copied from {source})",
+                            file.path,
+                            human_bytes(file.size as f64)
+                        ),
+                        None => println!(
+                            "{change}: {} ({})",
+                            file.path,
+                            human_bytes(file.size as f64)
+                        ),
+                    }
+                }
+            }
+            println!(
+                "{} added, {} modified, {} removed",
+                diff.added.len(),
+                diff.modified.len(),
+                diff.removed.len()
+            );
+            println!("Download Size: {}", human_bytes(diff.download_size as f64));
+            println!(
+                "Disk Size: {} -> {}",
+                human_bytes(diff.old_size as f64),
+                human_bytes(diff.new_size as f64)
+            );
+        }
+        Commands::Verify { slug, label } => {
+            let installed = InstalledConfig::load().expect("Failed to load installed");
+            let install_info = match select_install(&installed, &slug, label.as_deref()) {
+                Some(info) => info,
+                None => return,
+            };
 
-            match utils::verify(&slug, install_info).await {
+            let library = LibraryConfig::load().expect("Failed to load library");
+            let mut verifier = Verifier::new(&slug, install_info);
+            if let Some(product) = library.product(&slug) {
+                verifier = verifier.refetch_manifest(&client, product);
+            }
+
+            let install_id = install_info.id(&slug);
+            match verifier.verify().await {
                 Ok(true) => {
-                    println!("{slug} passed verification.");
+                    println!("{install_id} passed verification.");
                 }
                 Ok(false) => {
-                    println!("{slug} is corrupted. Please reinstall.");
+                    println!("{install_id} is corrupted. Please reinstall.");
                 }
                 Err(err) => {
                     println!("Failed to verify files: {}", err);
                 }
             }
         }
+        Commands::Settings { command } => {
+            let mut settings = settings;
+            match command {
+                SettingsCommands::Show => {
+                    println!(
+                        "Download limit: {}",
+                        match settings.download_limit {
+                            Some(limit) => format!("{}/s", human_bytes(limit as f64)),
+                            None => "off".to_owned(),
+                        }
+                    );
+                    println!(
+                        "Download schedule: {}",
+                        match &settings.download_schedule {
+                            Some(schedule) => schedule.to_string(),
+                            None => "off".to_owned(),
+                        }
+                    );
+                    println!(
+                        "Chunk cache: {} (max {})",
+                        if settings.chunk_cache { "on" } else { "off" },
+                        human_bytes(
+                            settings
+                                .chunk_cache_max_size
+                                .unwrap_or(*DEFAULT_CHUNK_CACHE_SIZE)
+                                as f64
+                        )
+                    );
+                    println!("Base URL: {}", client.endpoints().base_url);
+                    println!("Content URL: {}", client.endpoints().content_url);
+                    println!("Dev URL: {}", client.endpoints().dev_url);
+                    println!("OS preference: {}", os_names(&settings.os_preference("")));
+                    let mut game_os_preference: Vec<_> =
+                        settings.game_os_preference.iter().collect();
+                    game_os_preference.sort_by_key(|(slug, _)| *slug);
+                    for (slug, preference) in game_os_preference {
+                        println!("OS preference of {slug}: {}", os_names(preference));
+                    }
+                    return;
+                }
+                SettingsCommands::Limit { rate } => {
+                    settings.download_limit = if rate == "off" {
+                        None
+                    } else {
+                        match cli::parse_rate(&rate) {
+                            Ok(limit) => Some(limit),
+                            Err(err) => {
+                                println!("{err}");
+                                return;
+                            }
+                        }
+                    };
+                }
+                SettingsCommands::Schedule {
+                    start,
+                    end,
+                    outside_limit,
+                    off: _,
+                } => {
+                    settings.download_schedule = match (start, end) {
+                        (Some(start), Some(end)) => Some(DownloadSchedule {
+                            start,
+                            end,
+                            outside_limit,
+                        }),
+                        _ => None,
+                    };
+                }
+                SettingsCommands::Endpoint { service, url } => {
+                    let url = match url.as_str() {
+                        "default" => None,
+                        url => match cli::parse_url(url) {
+                            Ok(url) => Some(url),
+                            Err(err) => {
+                                println!("{err}");
+                                return;
+                            }
+                        },
+                    };
+                    match service {
+                        Service::Base => settings.base_url = url,
+                        Service::Content => settings.content_url = url,
+                        Service::Dev => settings.dev_url = url,
+                    };
+                }
+                SettingsCommands::ChunkCache { state, max_size } => {
+                    settings.chunk_cache = matches!(state, Toggle::On);
+                    if max_size.is_some() {
+                        settings.chunk_cache_max_size = max_size;
+                    }
+                }
+                SettingsCommands::OsPreference { os, game, reset: _ } => {
+                    let preference = (!os.is_empty()).then_some(os);
+                    match (game, preference) {
+                        (Some(slug), Some(preference)) => {
+                            settings.game_os_preference.insert(slug, preference);
+                        }
+                        (Some(slug), None) => {
+                            settings.game_os_preference.remove(&slug);
+                        }
+                        (None, preference) => settings.os_preference = preference,
+                    }
+                }
+            };
+            settings.store().expect("Failed to save settings");
+            println!("Settings saved.");
+        }
+        Commands::Cache { command } => {
+            let cache_path = ChunkCache::default_path();
+            match command {
+                CacheCommands::Stats => match ChunkCache::stats(&cache_path) {
+                    Ok(stats) => {
+                        println!("Location: {}", cache_path.display());
+                        println!("Chunks: {}", stats.chunks);
+                        println!("Size: {}", human_bytes(stats.size as f64));
+                    }
+                    Err(err) => println!("Failed to read chunk cache: {:?}", err),
+                },
+                CacheCommands::Clear => match ChunkCache::clear(&cache_path) {
+                    Ok(()) => println!("Chunk cache cleared."),
+                    Err(err) => println!("Failed to clear chunk cache: {:?}", err),
+                },
+                CacheCommands::Prune { max_size } => {
+                    let settings = SettingsConfig::load().expect("Failed to load settings");
+                    let max_size = max_size
+                        .or(settings.chunk_cache_max_size)
+                        .unwrap_or(*DEFAULT_CHUNK_CACHE_SIZE);
+                    match ChunkCache::prune(&cache_path, max_size) {
+                        Ok(stats) => println!(
+                            "Chunk cache pruned to {} ({} chunks).",
+                            human_bytes(stats.size as f64),
+                            stats.chunks
+                        ),
+                        Err(err) => println!("Failed to prune chunk cache: {:?}", err),
+                    }
+                }
+            };
+        }
+        Commands::Manifest { command } => match command {
+            ManifestCommands::Show { build, glob, json } => {
+                let mut manifests = match load_manifests(&client, &build).await {
+                    Some(manifests) => manifests,
+                    None => return,
+                };
+                if let Some(pattern) = &glob {
+                    manifests.filter(pattern);
+                }
+                if json {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&manifests)
+                            .expect("Failed to serialize manifests")
+                    );
+                    return;
+                }
+
+                for file in &manifests.files {
+                    let flags = match (file.directory, file.empty) {
+                        (true, _) => "dir",
+                        (false, true) => "empty",
+                        (false, false) => "-",
+                    };
+                    println!(
+                        "{:>12} {:<5} {:>5} {} {}",
+                        file.size, flags, file.chunks, file.sha, file.path
+                    );
+                }
+                let total_size: u64 = manifests.files.iter().map(|file| file.size).sum();
+                println!(
+                    "{} files, {} chunks, {}",
+                    manifests.files.len(),
+                    manifests.chunks.len(),
+                    human_bytes(total_size as f64)
+                );
+            }
+            ManifestCommands::Check { build } => {
+                let manifests = match load_manifests(&client, &build).await {
+                    Some(manifests) => manifests,
+                    None => return,
+                };
+                let problems = manifests.check();
+                if problems.is_empty() {
+                    println!(
+                        "The manifests of {} are consistent ({} files, {} chunks).",
+                        build.slug,
+                        manifests.files.len(),
+                        manifests.chunks.len()
+                    );
+                    return;
+                }
+                for problem in &problems {
+                    println!("{problem}");
+                }
+                println!(
+                    "Found {} problem(s) in the manifests of {}.",
+                    problems.len(),
+                    build.slug
+                );
+            }
+            ManifestCommands::Prune => {
+                let installed = InstalledConfig::load().expect("Failed to load installed");
+                match prune_manifests(&installed).await {
+                    Ok(freed) => println!(
+                        "Deleted {} of manifests of builds that aren't installed.",
+                        human_bytes(freed as f64)
+                    ),
+                    Err(err) => println!("Failed to delete manifests: {err}"),
+                }
+            }
+        },
+        Commands::Serve { root, bind } => {
+            let has_builds = ArchiveIndex::load(&root)
+                .map(|index| !index.products.is_empty())
+                .unwrap_or(false);
+            if !has_builds {
+                println!("{} doesn't contain any downloaded builds", root.display());
+                return;
+            }
+            if let Err(err) = serve::serve(root, bind).await {
+                println!("Failed to serve content mirror: {:?}", err);
+            }
+        }
+        Commands::Tui {
+            base_path,
+            #[cfg(not(target_os = "windows"))]
+            wine_bin,
+            #[cfg(not(target_os = "windows"))]
+            wine_prefix,
+            queue_opts,
+            install_opts,
+        } => {
+            let options = tui::TuiOptions {
+                install_options: InstallOptions::from(&install_opts),
+                max_parallel_installs: queue_opts.max_parallel_installs,
+                base_path,
+                #[cfg(not(target_os = "windows"))]
+                wine_bin,
+                #[cfg(not(target_os = "windows"))]
+                wine_prefix,
+            };
+            if let Err(err) = tui::run(client.clone(), options).await {
+                println!("Failed to run the TUI: {:?}", err);
+            }
+        }
+        #[cfg(unix)]
+        Commands::Daemon {
+            wine_bin,
+            wine_prefix,
+            queue_opts,
+            install_opts,
+        } => {
+            let daemon = Daemon::new(
+                client.clone(),
+                InstallOptions::from(&install_opts),
+                queue_opts.max_parallel_installs,
+            )
+            .wine_bin(wine_bin)
+            .wine_prefix(wine_prefix);
+            if let Err(err) = daemon.run(&socket_path).await {
+                println!("Failed to run daemon: {}", err);
+            }
+        }
+        Commands::Queue { command } => match command {
+            QueueCommands::List => {
+                let queue = QueueConfig::load().expect("Failed to load download queue");
+                if queue.downloads.is_empty() {
+                    println!("The download queue is empty");
+                    return;
+                }
+
+                for download in queue.ordered() {
+                    println!("{}", download);
+                }
+            }
+            QueueCommands::Pause { slug, label } => {
+                let mut queue = QueueConfig::load().expect("Failed to load download queue");
+                let install_id = match select_download(&queue, &slug, label.as_deref()) {
+                    Some(install_id) => install_id,
+                    None => return,
+                };
+                if let Some(download) = queue.get_mut(&install_id) {
+                    download.status = QueueStatus::Paused;
+                }
+                queue.store().expect("Failed to update download queue");
+                println!("{install_id} paused.");
+            }
+            QueueCommands::Resume {
+                slug,
+                label,
+                queue_opts,
+                install_opts,
+            } => {
+                let mut queue = QueueConfig::load().expect("Failed to load download queue");
+                match &slug {
+                    Some(slug) => match select_download(&queue, slug, label.as_deref()) {
+                        Some(install_id) => {
+                            if let Some(download) = queue.get_mut(&install_id) {
+                                download.status = QueueStatus::Queued;
+                            }
+                        }
+                        None => return,
+                    },
+                    None => {
+                        for download in queue.downloads.iter_mut() {
+                            download.status = QueueStatus::Queued;
+                        }
+                    }
+                };
+                queue.store().expect("Failed to update download queue");
+
+                if let Err(err) = new_installer(&client, InstallOptions::from(&install_opts))
+                    .process_queue(queue_opts.max_parallel_installs)
+                    .await
+                {
+                    println!("Failed to process the download queue: {}", err);
+                }
+            }
+            QueueCommands::Remove { slug, label } => {
+                let mut queue = QueueConfig::load().expect("Failed to load download queue");
+                let install_id = match select_download(&queue, &slug, label.as_deref()) {
+                    Some(install_id) => install_id,
+                    None => return,
+                };
+                queue.remove(&install_id);
+                queue.store().expect("Failed to update download queue");
+                println!("{install_id} removed from the download queue.");
+            }
+        },
     };
 
-    drop(client);
-    let cookie_store = Arc::try_unwrap(cookie_store).expect("Failed to unwrap cookie store");
-    let cookie_store = cookie_store
-        .into_inner()
-        .expect("Failed to unwrap CookieStoreMutex");
-    CookieConfig(cookie_store)
-        .store()
-        .expect("Failed to save cookie config");
+    client.save().expect("Failed to save cookie config");
 }
 
-fn save_user_info(data: &Option<SyncResult>) {
-    if let Some(SyncResult {
-        user_config,
-        library_config,
-    }) = data
-    {
-        user_config.store().expect("Failed to save user config");
-        library_config
-            .store()
-            .expect("Failed to save library config");
+/// Reads the manifests stored for the selected install, or fetches the manifests of the
+/// selected build if they aren't stored
+async fn load_manifests(client: &GalaClient, build: &ManifestBuildOpts) -> Option<Manifests> {
+    let library = LibraryConfig::load().expect("Failed to load library");
+    let slug = &build.slug;
+    let product = match library.product(slug) {
+        Some(product) => product,
+        None => {
+            println!("{slug} is not in your library");
+            return None;
+        }
+    };
+    let installed = InstalledConfig::load().expect("Failed to load installed");
+    let install_info = match &build.label {
+        Some(label) => Some(select_install(&installed, slug, Some(label))?),
+        None => installed.get(slug, None),
+    };
+
+    let selected_install = install_info.filter(|info| {
+        build.version.as_ref().map_or(true, |v| *v == info.version)
+            && build.os.as_ref().map_or(true, |os| *os == info.os)
+    });
+    if let Some(info) = selected_install {
+        match Manifests::read_stored(&info.id(slug), &info.version).await {
+            Ok(manifests) => return Some(manifests),
+            Err(err) => println!("Couldn't read the stored manifests ({err}), fetching them..."),
+        }
+    }
+
+    let os = build
+        .os
+        .clone()
+        .or_else(|| selected_install.map(|info| info.os.clone()));
+    let version = match (&build.version, selected_install) {
+        (Some(version), _) => product.find_version(version, os.as_ref()),
+        (None, Some(info)) => product.find_version(&info.version, Some(&info.os)),
+        (None, None) => product.latest_version(os.as_ref(), true),
+    };
+    let version = match version {
+        Some(version) => version,
+        None => {
+            println!("Couldn't find a matching build for {slug}");
+            return None;
+        }
+    };
+
+    let installer = Installer::new(client, InstallOptions::default());
+    match installer.manifests(product, version).await {
+        Ok(manifests) => Some(manifests),
+        Err(err) => {
+            println!("Failed to fetch the manifests of {slug}: {err}");
+            None
+        }
     }
 }
+
+fn os_names(preference: &[BuildOs]) -> String {
+    preference
+        .iter()
+        .map(|os| os.name())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Creates an installer that shows progress bars for every game being downloaded
+fn new_installer(client: &GalaClient, install_options: InstallOptions) -> Installer {
+    let progress_bars = Arc::new(ProgressBars::default());
+    Installer::new(client, install_options)
+        .on_progress(move |progress| progress_bars.update(progress))
+}
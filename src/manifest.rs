@@ -0,0 +1,793 @@
+//! Readers and writers for build manifests and chunk manifests.
+//!
+//! Build manifests list the files of a build, and chunk manifests the chunks every file is
+//! downloaded in. Both are CSV files. Columns are looked up by name, so manifests with the
+//! columns in another order, without the optional columns of delta manifests, or with a byte
+//! order mark can be read. File names are decoded as UTF-8, or as latin-1 when they aren't valid
+//! UTF-8, and always written back as UTF-8.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, io,
+    str::FromStr,
+};
+
+use csv::ByteRecord;
+use serde::Serialize;
+
+use crate::{
+    constants::MAX_CHUNK_SIZE,
+    delta::{diff_manifests, FileChange, ManifestEntry},
+    error::Error,
+    helpers::read_build_manifest,
+};
+
+/// A file of a build manifest, or a change to one in a delta manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildManifestRecord {
+    pub size_in_bytes: usize,
+    pub chunks: usize,
+    pub sha: String,
+    pub flags: u8,
+    pub file_name: String,
+    /// How the file changed. Only set in delta manifests.
+    pub tag: Option<ChangeTag>,
+    /// File from the installed build with the same contents. Only set in delta manifests.
+    pub copy_source: Option<String>,
+}
+
+impl BuildManifestRecord {
+    pub fn is_directory(&self) -> bool {
+        self.flags == 40
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size_in_bytes == 0
+    }
+}
+
+impl ManifestEntry for BuildManifestRecord {
+    fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    fn sha(&self) -> &str {
+        &self.sha
+    }
+
+    fn is_directory(&self) -> bool {
+        self.is_directory()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeTag {
+    Added,
+    Modified,
+    Removed,
+}
+
+impl ChangeTag {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Added => "Added",
+            Self::Modified => "Modified",
+            Self::Removed => "Removed",
+        }
+    }
+}
+
+impl FromStr for ChangeTag {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "added" => Ok(Self::Added),
+            "modified" => Ok(Self::Modified),
+            "removed" => Ok(Self::Removed),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A chunk of a chunk manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildManifestChunksRecord {
+    /// Index of the chunk in its file
+    pub id: u16,
+    pub file_path: String,
+    pub sha: String,
+}
+
+/// A row of a manifest that couldn't be read
+#[derive(Debug)]
+pub struct ManifestError {
+    row: Option<u64>,
+    message: String,
+}
+
+impl ManifestError {
+    fn new(row: Option<u64>, message: String) -> Self {
+        Self { row, message }
+    }
+
+    /// Line of the manifest the error is on, counting the header as row 1
+    pub fn row(&self) -> Option<u64> {
+        self.row
+    }
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.row {
+            Some(row) => write!(f, "row {}: {}", row, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+impl From<csv::Error> for ManifestError {
+    fn from(err: csv::Error) -> Self {
+        let row = err.position().map(|position| position.line());
+        Self::new(row, err.to_string())
+    }
+}
+
+impl From<ManifestError> for io::Error {
+    fn from(err: ManifestError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// A column of a manifest, with the names it can have once lowercased and stripped of anything
+/// that isn't alphanumeric
+struct Column {
+    name: &'static str,
+    aliases: &'static [&'static str],
+    required: bool,
+}
+
+const BUILD_COLUMNS: [Column; 7] = [
+    Column {
+        name: "Size in Bytes",
+        aliases: &["sizeinbytes", "size"],
+        required: true,
+    },
+    Column {
+        name: "Chunks",
+        aliases: &["chunks"],
+        required: true,
+    },
+    Column {
+        name: "SHA",
+        aliases: &["sha"],
+        required: true,
+    },
+    Column {
+        name: "Flags",
+        aliases: &["flags"],
+        required: true,
+    },
+    Column {
+        name: "File Name",
+        aliases: &["filename", "filepath"],
+        required: true,
+    },
+    Column {
+        name: "Change Tag",
+        aliases: &["changetag"],
+        required: false,
+    },
+    Column {
+        name: "Copy Source",
+        aliases: &["copysource"],
+        required: false,
+    },
+];
+
+const CHUNK_COLUMNS: [Column; 3] = [
+    Column {
+        name: "ID",
+        aliases: &["id"],
+        required: true,
+    },
+    Column {
+        name: "Filepath",
+        aliases: &["filepath", "filename"],
+        required: true,
+    },
+    Column {
+        name: "Chunk SHA",
+        aliases: &["chunksha", "sha"],
+        required: true,
+    },
+];
+
+/// Reads the header of a manifest and finds the index of every column in it. Empty manifests
+/// don't have a header and are read as having no rows.
+fn read_columns<R: io::Read, const N: usize>(
+    reader: &mut csv::Reader<R>,
+    columns: &[Column; N],
+) -> Result<[Option<usize>; N], ManifestError> {
+    let header = reader.byte_headers()?;
+    let mut indices = [None; N];
+    if header.is_empty() {
+        return Ok(indices);
+    }
+
+    let names: Vec<String> = header
+        .iter()
+        .map(|name| {
+            String::from_utf8_lossy(name)
+                .chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect()
+        })
+        .collect();
+    for (index, column) in indices.iter_mut().zip(columns) {
+        *index = names
+            .iter()
+            .position(|name| column.aliases.contains(&name.as_str()));
+        if index.is_none() && column.required {
+            return Err(ManifestError::new(
+                Some(1),
+                format!("missing the {} column", column.name),
+            ));
+        }
+    }
+
+    Ok(indices)
+}
+
+fn csv_reader<R: io::Read>(reader: R) -> csv::Reader<R> {
+    // Rows can leave out the optional columns at the end
+    csv::ReaderBuilder::new().flexible(true).from_reader(reader)
+}
+
+/// Field of a row, or an empty field if the row doesn't have the column
+fn get_field(record: &ByteRecord, index: Option<usize>) -> &[u8] {
+    index.and_then(|index| record.get(index)).unwrap_or(b"")
+}
+
+fn parse_number<T: FromStr>(bytes: &[u8], column: &Column) -> Result<T, String> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .ok_or_else(|| {
+            format!(
+                "invalid {} {:?}",
+                column.name,
+                String::from_utf8_lossy(bytes)
+            )
+        })
+}
+
+fn parse_ascii(bytes: &[u8], column: &Column) -> Result<String, String> {
+    match std::str::from_utf8(bytes) {
+        Ok(s) if s.is_ascii() => Ok(s.to_owned()),
+        _ => Err(format!(
+            "invalid {} {:?}",
+            column.name,
+            String::from_utf8_lossy(bytes)
+        )),
+    }
+}
+
+/// Decodes a file name as UTF-8, falling back to latin-1, which older builds use
+fn parse_name(bytes: &[u8], column: &Column) -> Result<String, String> {
+    if bytes.is_empty() {
+        return Err(format!("empty {}", column.name));
+    }
+
+    Ok(match std::str::from_utf8(bytes) {
+        Ok(name) => name.to_owned(),
+        Err(_) => bytes.iter().copied().map(char::from).collect(),
+    })
+}
+
+/// Streams the files of a build manifest or delta manifest
+pub struct BuildManifest<R> {
+    reader: csv::Reader<R>,
+    columns: [Option<usize>; 7],
+    record: ByteRecord,
+    done: bool,
+}
+
+impl<R: io::Read> BuildManifest<R> {
+    /// Reads the header of the manifest
+    pub fn new(reader: R) -> Result<Self, ManifestError> {
+        let mut reader = csv_reader(reader);
+        let columns = read_columns(&mut reader, &BUILD_COLUMNS)?;
+
+        Ok(Self {
+            reader,
+            columns,
+            record: ByteRecord::new(),
+            done: false,
+        })
+    }
+
+    fn parse_record(&self) -> Result<BuildManifestRecord, String> {
+        let [size, chunks, sha, flags, file_name, tag, copy_source] = &BUILD_COLUMNS;
+        let field = |index: usize| get_field(&self.record, self.columns[index]);
+
+        let tag = match field(5) {
+            b"" => None,
+            value => Some(parse_ascii(value, tag)?.parse().map_err(|_| {
+                format!("invalid {} {:?}", tag.name, String::from_utf8_lossy(value))
+            })?),
+        };
+        let copy_source = match field(6) {
+            b"" => None,
+            value => Some(parse_name(value, copy_source)?),
+        };
+
+        Ok(BuildManifestRecord {
+            size_in_bytes: parse_number(field(0), size)?,
+            chunks: parse_number(field(1), chunks)?,
+            sha: parse_ascii(field(2), sha)?,
+            flags: parse_number(field(3), flags)?,
+            file_name: parse_name(field(4), file_name)?,
+            tag,
+            copy_source,
+        })
+    }
+}
+
+impl<R: io::Read> Iterator for BuildManifest<R> {
+    type Item = Result<BuildManifestRecord, ManifestError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.reader.read_byte_record(&mut self.record) {
+            Ok(true) => Some(self.parse_record().map_err(|message| {
+                ManifestError::new(self.record.position().map(|p| p.line()), message)
+            })),
+            Ok(false) => None,
+            Err(err) => {
+                self.done = true;
+                Some(Err(err.into()))
+            }
+        }
+    }
+}
+
+/// Streams the chunks of a chunk manifest
+pub struct ChunkManifest<R> {
+    reader: csv::Reader<R>,
+    columns: [Option<usize>; 3],
+    record: ByteRecord,
+    done: bool,
+}
+
+impl<R: io::Read> ChunkManifest<R> {
+    /// Reads the header of the manifest
+    pub fn new(reader: R) -> Result<Self, ManifestError> {
+        let mut reader = csv_reader(reader);
+        let columns = read_columns(&mut reader, &CHUNK_COLUMNS)?;
+
+        Ok(Self {
+            reader,
+            columns,
+            record: ByteRecord::new(),
+            done: false,
+        })
+    }
+
+    fn parse_record(&self) -> Result<BuildManifestChunksRecord, String> {
+        let [id, file_path, sha] = &CHUNK_COLUMNS;
+        let field = |index: usize| get_field(&self.record, self.columns[index]);
+
+        Ok(BuildManifestChunksRecord {
+            id: parse_number(field(0), id)?,
+            file_path: parse_name(field(1), file_path)?,
+            sha: parse_ascii(field(2), sha)?,
+        })
+    }
+}
+
+impl<R: io::Read> Iterator for ChunkManifest<R> {
+    type Item = Result<BuildManifestChunksRecord, ManifestError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.reader.read_byte_record(&mut self.record) {
+            Ok(true) => Some(self.parse_record().map_err(|message| {
+                ManifestError::new(self.record.position().map(|p| p.line()), message)
+            })),
+            Ok(false) => None,
+            Err(err) => {
+                self.done = true;
+                Some(Err(err.into()))
+            }
+        }
+    }
+}
+
+/// Writes build manifests and delta manifests. Every column is written, so the output can be
+/// read back by [`BuildManifest`] whether it's a delta or not.
+pub struct BuildManifestWriter<W: io::Write> {
+    writer: csv::Writer<W>,
+}
+
+impl<W: io::Write> BuildManifestWriter<W> {
+    /// Writes the header of the manifest
+    pub fn new(writer: W) -> io::Result<Self> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(BUILD_COLUMNS.iter().map(|column| column.name))?;
+
+        Ok(Self { writer })
+    }
+
+    pub fn write(&mut self, record: &BuildManifestRecord) -> io::Result<()> {
+        self.writer.write_record([
+            record.size_in_bytes.to_string().as_str(),
+            record.chunks.to_string().as_str(),
+            &record.sha,
+            record.flags.to_string().as_str(),
+            &record.file_name,
+            record.tag.as_ref().map_or("", ChangeTag::as_str),
+            record.copy_source.as_deref().unwrap_or(""),
+        ])?;
+
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> io::Result<W> {
+        self.writer.into_inner().map_err(|err| err.into_error())
+    }
+}
+
+/// Writes chunk manifests
+pub struct ChunkManifestWriter<W: io::Write> {
+    writer: csv::Writer<W>,
+}
+
+impl<W: io::Write> ChunkManifestWriter<W> {
+    /// Writes the header of the manifest
+    pub fn new(writer: W) -> io::Result<Self> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(CHUNK_COLUMNS.iter().map(|column| column.name))?;
+
+        Ok(Self { writer })
+    }
+
+    pub fn write(&mut self, record: &BuildManifestChunksRecord) -> io::Result<()> {
+        self.writer.write_record([
+            record.id.to_string().as_str(),
+            &record.file_path,
+            &record.sha,
+        ])?;
+
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> io::Result<W> {
+        self.writer.into_inner().map_err(|err| err.into_error())
+    }
+}
+
+/// Compares the build manifests of two builds and writes the delta manifest of the files that
+/// changed. Files whose contents exist in the old build are tagged with the file they can be
+/// copied from instead of being downloaded.
+pub fn delta_manifest(old_manifest: &[u8], new_manifest: &[u8]) -> io::Result<Vec<u8>> {
+    let old_manifest = BuildManifest::new(old_manifest)?.collect::<Result<Vec<_>, _>>()?;
+    // The new manifest is streamed, so the first invalid row stops the diff and is returned
+    // once it's done
+    let mut new_manifest_error = None;
+    let new_manifest = BuildManifest::new(new_manifest)?
+        .map_while(|record| record.map_err(|err| new_manifest_error = Some(err)).ok());
+    let mut delta_wtr = BuildManifestWriter::new(vec![])?;
+    let mut write_result = Ok(());
+
+    diff_manifests(old_manifest, new_manifest, |change| {
+        let record = match change {
+            FileChange::Added(entry) => BuildManifestRecord {
+                tag: Some(ChangeTag::Added),
+                ..entry
+            },
+            FileChange::Modified(entry) => BuildManifestRecord {
+                tag: Some(ChangeTag::Modified),
+                ..entry
+            },
+            FileChange::Copied {
+                entry,
+                source,
+                added,
+            } => BuildManifestRecord {
+                tag: Some(if added {
+                    ChangeTag::Added
+                } else {
+                    ChangeTag::Modified
+                }),
+                copy_source: Some(source),
+                ..entry
+            },
+            FileChange::Removed(entry) => BuildManifestRecord {
+                tag: Some(ChangeTag::Removed),
+                ..entry
+            },
+        };
+        if write_result.is_ok() {
+            write_result = delta_wtr.write(&record);
+        }
+    });
+    if let Some(err) = new_manifest_error {
+        return Err(err.into());
+    }
+    write_result?;
+
+    delta_wtr.into_inner()
+}
+
+/// Names Windows gives to devices, which can't be used as file names with any extension
+#[cfg(windows)]
+const RESERVED_NAMES: [&str; 22] = [
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Turns the paths of a manifest into paths relative to the install directory.
+///
+/// Backslashes are treated as separators, and paths that are absolute, start with a drive letter
+/// or leave the install directory with `..` are rejected, as are names Windows can't create when
+/// installing on Windows. Builds for Windows and macOS expect a case-insensitive file system, so
+/// their paths can be made case-insensitive: every path then takes the case of the first path
+/// seen that only differs from it in case.
+pub struct InstallPaths {
+    /// Case of every path seen, by lowercased path
+    casing: Option<HashMap<String, String>>,
+}
+
+impl InstallPaths {
+    pub fn new(case_insensitive: bool) -> Self {
+        Self {
+            casing: case_insensitive.then(HashMap::new),
+        }
+    }
+
+    /// Returns the path of `name` relative to the install directory, with `/` as separator
+    pub fn resolve(&mut self, name: &str) -> Result<String, ManifestError> {
+        let invalid =
+            |reason: &str| ManifestError::new(None, format!("unsafe path {name:?}: {reason}"));
+
+        if name.starts_with(['/', '\\']) {
+            return Err(invalid("it's absolute"));
+        }
+        let bytes = name.as_bytes();
+        if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+            return Err(invalid("it starts with a drive letter"));
+        }
+
+        let mut path = String::with_capacity(name.len());
+        for component in name.split(['/', '\\']) {
+            match component {
+                "" | "." => continue,
+                ".." => return Err(invalid("it leaves the install directory")),
+                _ => {}
+            }
+            if component.contains('\0') {
+                return Err(invalid("it contains a null character"));
+            }
+            #[cfg(windows)]
+            check_windows_name(component).map_err(invalid)?;
+
+            if !path.is_empty() {
+                path.push('/');
+            }
+            path.push_str(component);
+            if let Some(casing) = &mut self.casing {
+                let cased = casing
+                    .entry(path.to_lowercase())
+                    .or_insert_with(|| path.clone());
+                path.clone_from(cased);
+            }
+        }
+
+        if path.is_empty() {
+            return Err(invalid("it's empty"));
+        }
+
+        Ok(path)
+    }
+}
+
+/// Checks Windows can create a file or directory called `component`
+#[cfg(windows)]
+fn check_windows_name(component: &str) -> Result<(), &'static str> {
+    if component.ends_with(['.', ' ']) {
+        return Err("it has a name ending with a dot or space");
+    }
+    if component
+        .chars()
+        .any(|c| c.is_control() || matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*'))
+    {
+        return Err("it has a name with characters Windows doesn't allow");
+    }
+    let stem = component.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED_NAMES.contains(&stem.to_lowercase().as_str()) {
+        return Err("it has a name Windows reserves for devices");
+    }
+
+    Ok(())
+}
+
+/// A file listed in a build manifest
+#[derive(Debug, Clone, Serialize)]
+pub struct ManifestFile {
+    pub path: String,
+    /// Size in bytes
+    pub size: u64,
+    pub directory: bool,
+    pub empty: bool,
+    /// Number of chunks the file is downloaded in
+    pub chunks: usize,
+    pub sha: String,
+}
+
+/// A chunk listed in a chunk manifest
+#[derive(Debug, Clone, Serialize)]
+pub struct ManifestChunk {
+    /// Index of the chunk in its file
+    pub id: u16,
+    pub path: String,
+    pub sha: String,
+}
+
+/// The build manifest and chunk manifest of a build
+#[derive(Debug, Clone, Serialize)]
+pub struct Manifests {
+    pub files: Vec<ManifestFile>,
+    pub chunks: Vec<ManifestChunk>,
+}
+
+impl Manifests {
+    pub fn parse(manifest: &[u8], manifest_chunks: &[u8]) -> Result<Self, ManifestError> {
+        let mut files = vec![];
+        for record in BuildManifest::new(manifest)? {
+            let record = record?;
+            files.push(ManifestFile {
+                directory: record.is_directory(),
+                empty: record.is_empty(),
+                size: record.size_in_bytes as u64,
+                chunks: record.chunks,
+                path: record.file_name,
+                sha: record.sha,
+            });
+        }
+
+        let mut chunks = vec![];
+        for record in ChunkManifest::new(manifest_chunks)? {
+            let record = record?;
+            chunks.push(ManifestChunk {
+                id: record.id,
+                path: record.file_path,
+                sha: record.sha,
+            });
+        }
+
+        Ok(Self { files, chunks })
+    }
+
+    /// Reads the manifests of `version` stored for the install `install_id`, see
+    /// [`install_id`](crate::models::install_id)
+    pub async fn read_stored(install_id: &str, version: &str) -> Result<Self, Error> {
+        let install_id = install_id.to_owned();
+        let version = version.to_owned();
+        let manifest = read_build_manifest(&version, &install_id, "manifest").await?;
+        let manifest_chunks = read_build_manifest(&version, &install_id, "manifest_chunks").await?;
+        Ok(Self::parse(&manifest, &manifest_chunks)?)
+    }
+
+    /// Keeps the files whose path matches a glob pattern, e.g. `data/**/*.pak`. Chunks of the
+    /// other files are dropped too.
+    pub fn filter(&mut self, pattern: &glob::Pattern) {
+        // Windows builds use backslashes as separators
+        let matches = |path: &str| pattern.matches(&path.replace('\\', "/"));
+        self.files.retain(|file| matches(&file.path));
+        self.chunks.retain(|chunk| matches(&chunk.path));
+    }
+
+    /// Checks the chunk manifest covers every file of the build manifest: every file that isn't
+    /// empty or a directory has as many chunks as the build manifest says, with ids counting up
+    /// from 0, and enough of them for the size of the file. Paths that couldn't be installed are
+    /// reported too, as are paths that only differ in case, which end up as the same file on
+    /// case-insensitive file systems. Returns the problems found.
+    pub fn check(&self) -> Vec<String> {
+        let mut problems = vec![];
+        let mut chunk_ids: HashMap<&str, Vec<u16>> = HashMap::new();
+        for chunk in &self.chunks {
+            chunk_ids.entry(&chunk.path).or_default().push(chunk.id);
+        }
+
+        let mut paths = InstallPaths::new(false);
+        // First path seen for every lowercased path, and for every directory leading to it
+        let mut casing: HashMap<String, String> = HashMap::new();
+        let mut collisions = HashSet::new();
+        for file in &self.files {
+            match paths.resolve(&file.path) {
+                Ok(path) => {
+                    let prefixes = path
+                        .match_indices('/')
+                        .map(|(index, _)| &path[..index])
+                        .chain([path.as_str()]);
+                    for prefix in prefixes {
+                        let first = casing
+                            .entry(prefix.to_lowercase())
+                            .or_insert_with(|| prefix.to_owned());
+                        if first != prefix && collisions.insert(prefix.to_owned()) {
+                            problems.push(format!("{prefix} and {first} only differ in case"));
+                        }
+                    }
+                }
+                Err(err) => problems.push(err.to_string()),
+            }
+            let ids = chunk_ids.remove(file.path.as_str()).unwrap_or_default();
+            if file.directory || file.empty {
+                if !ids.is_empty() {
+                    problems.push(format!(
+                        "{} has {} chunks, but is empty or a directory",
+                        file.path,
+                        ids.len()
+                    ));
+                }
+                continue;
+            }
+
+            if ids.is_empty() {
+                problems.push(format!("{} has no chunks", file.path));
+                continue;
+            }
+            if ids.len() != file.chunks {
+                problems.push(format!(
+                    "{} has {} chunks, but the build manifest lists {}",
+                    file.path,
+                    ids.len(),
+                    file.chunks
+                ));
+            }
+            let mut sorted_ids = ids.clone();
+            sorted_ids.sort_unstable();
+            if sorted_ids
+                .iter()
+                .enumerate()
+                .any(|(i, id)| usize::from(*id) != i)
+            {
+                problems.push(format!(
+                    "{} has chunk ids {:?}, which don't count up from 0",
+                    file.path, ids
+                ));
+            }
+            let needed_chunks = (file.size as usize).div_ceil(*MAX_CHUNK_SIZE);
+            if file.chunks != needed_chunks {
+                problems.push(format!(
+                    "{} needs {} chunks for {} bytes, but the build manifest lists {}",
+                    file.path, needed_chunks, file.size, file.chunks
+                ));
+            }
+        }
+
+        let mut unknown: Vec<&str> = chunk_ids.into_keys().collect();
+        unknown.sort_unstable();
+        for path in unknown {
+            problems.push(format!(
+                "{path} has chunks, but isn't in the build manifest"
+            ));
+        }
+
+        problems
+    }
+}
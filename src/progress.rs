@@ -0,0 +1,95 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use freecarnival::Progress;
+use human_bytes::human_bytes;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use lazy_static::lazy_static;
+
+lazy_static! {
+    /// Every progress bar is drawn here, so messages can be printed above them
+    static ref MULTI_PROGRESS: MultiProgress = MultiProgress::new();
+}
+
+/// Prints a line above the progress bars
+pub(crate) fn println(message: &str) {
+    MULTI_PROGRESS.suspend(|| println!("{message}"));
+}
+
+/// Describes a change of the download rate limit or schedule
+pub(crate) fn throttle_message(progress: &Progress) -> Option<String> {
+    match progress {
+        Progress::Throttled { limit: None } => Some("Downloading at full speed".to_owned()),
+        Progress::Throttled { limit: Some(limit) } => Some(format!(
+            "Limiting downloads to {}/s",
+            human_bytes(*limit as f64)
+        )),
+        Progress::Suspended => {
+            Some("Outside of the download schedule. Pausing downloads...".to_owned())
+        }
+        _ => None,
+    }
+}
+
+/// Shows a download and a disk progress bar for every game being downloaded
+#[derive(Default)]
+pub(crate) struct ProgressBars {
+    bars: Mutex<HashMap<String, (ProgressBar, ProgressBar)>>,
+}
+
+impl ProgressBars {
+    pub(crate) fn update(&self, progress: &Progress) {
+        let mut bars = self.bars.lock().unwrap();
+        match progress {
+            Progress::Started {
+                install_id,
+                name,
+                total_bytes,
+                ..
+            } => {
+                let dl_sty =
+                    ProgressStyle::with_template("{wide_msg} Download: {binary_bytes_per_sec}")
+                        .unwrap();
+                let wr_sty = ProgressStyle::with_template(
+                    "{wide_msg} Disk: {binary_bytes_per_sec}\n[{percent}%] {wide_bar} {bytes:>7}/{total_bytes:7} [{eta_precise}]",
+                )
+                .unwrap()
+                .progress_chars("##-");
+
+                let dl_prog = MULTI_PROGRESS.add(
+                    ProgressBar::new(*total_bytes)
+                        .with_style(dl_sty)
+                        .with_message(name.clone()),
+                );
+                let wrt_prog = MULTI_PROGRESS.insert_after(
+                    &dl_prog,
+                    ProgressBar::new(*total_bytes)
+                        .with_style(wr_sty)
+                        .with_message(name.clone()),
+                );
+                bars.insert(install_id.clone(), (dl_prog, wrt_prog));
+            }
+            Progress::Downloaded { install_id, bytes } => {
+                if let Some((dl_prog, _)) = bars.get(install_id) {
+                    dl_prog.inc(*bytes);
+                }
+            }
+            Progress::Written { install_id, bytes } => {
+                if let Some((_, wrt_prog)) = bars.get(install_id) {
+                    wrt_prog.inc(*bytes);
+                }
+            }
+            Progress::Finished { install_id } => {
+                if let Some((dl_prog, wrt_prog)) = bars.remove(install_id) {
+                    dl_prog.abandon();
+                    wrt_prog.abandon();
+                }
+            }
+            Progress::Throttled { .. } | Progress::Suspended => {
+                if let Some(message) = throttle_message(progress) {
+                    println(&message);
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use freecarnival::{
+    archive::ArchiveIndex,
+    config::{GalaConfig, InstalledConfig, LibraryConfig, QueueConfig},
+    models::{InstallInfo, Product},
+    search::{resolve, Resolution},
+};
+
+use crate::cli::{Commands, ManifestCommands, QueueCommands, SettingsCommands};
+
+/// Replaces the games given to `command` by the slugs they resolve to, so they can be given as
+/// a slug, a product id or the start of a name. Returns `false` if a game couldn't be resolved
+/// and the command shouldn't run.
+pub(crate) fn resolve_command(command: &mut Commands) -> bool {
+    match command {
+        Commands::Install {
+            slugs,
+            from_archive,
+            ..
+        } => {
+            let (products, source) = match from_archive {
+                Some(archive_path) => match ArchiveIndex::load(archive_path) {
+                    Ok(index) => (index.products, "the archive"),
+                    // Reported when installing
+                    Err(_) => return true,
+                },
+                None => (library(), "your library"),
+            };
+            let requested = slugs.len();
+            *slugs = slugs
+                .iter()
+                .filter_map(|slug| resolve_slug(&products, slug, source))
+                .collect();
+            // Installing without any game would process the whole queue instead
+            requested == 0 || !slugs.is_empty()
+        }
+        Commands::Uninstall { slug, .. }
+        | Commands::Verify { slug, .. }
+        | Commands::Hold { slug, .. }
+        | Commands::Unhold { slug, .. } => {
+            let installed = InstalledConfig::load().unwrap_or_default();
+            installed.contains(slug) || resolve_in_library(slug)
+        }
+        Commands::Download { slug, .. }
+        | Commands::Update { slug, .. }
+        | Commands::Launch { slug, .. }
+        | Commands::Info { slug }
+        | Commands::Versions { slug, .. }
+        | Commands::Diff { slug, .. } => resolve_in_library(slug),
+        Commands::Settings {
+            command:
+                SettingsCommands::OsPreference {
+                    game: Some(slug), ..
+                },
+        } => resolve_in_library(slug),
+        Commands::Manifest {
+            command: ManifestCommands::Show { build, .. } | ManifestCommands::Check { build },
+        } => resolve_in_library(&mut build.slug),
+        Commands::Queue {
+            command:
+                QueueCommands::Pause { slug, .. }
+                | QueueCommands::Remove { slug, .. }
+                | QueueCommands::Resume {
+                    slug: Some(slug), ..
+                },
+        } => {
+            let queue = QueueConfig::load().unwrap_or_default();
+            queue.downloads.iter().any(|d| d.slug == *slug) || resolve_in_library(slug)
+        }
+        _ => true,
+    }
+}
+
+fn library() -> Vec<Product> {
+    LibraryConfig::load()
+        .map(|library| library.collection)
+        .unwrap_or_default()
+}
+
+fn resolve_in_library(slug: &mut String) -> bool {
+    match resolve_slug(&library(), slug, "your library") {
+        Some(resolved) => {
+            *slug = resolved;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Finds the slug of the game the user meant. Prints the closest games if nothing matched, and
+/// asks which one was meant if several games match and stdin is a terminal.
+fn resolve_slug(products: &[Product], query: &str, source: &str) -> Option<String> {
+    match resolve(products, query) {
+        Resolution::Found(product) => Some(product.slugged_name.clone()),
+        Resolution::Ambiguous(matches) => {
+            println!("{query} matches several games:");
+            for (i, product) in matches.iter().enumerate() {
+                println!("  {}) {}", i + 1, product);
+            }
+            if !io::stdin().is_terminal() {
+                println!("Use the slug of the game to pick one.");
+                return None;
+            }
+
+            print!("Which one? [1-{}]: ", matches.len());
+            io::stdout().flush().ok()?;
+            let mut answer = String::new();
+            io::stdin().lock().read_line(&mut answer).ok()?;
+            match answer.trim().parse::<usize>() {
+                Ok(choice) if (1..=matches.len()).contains(&choice) => {
+                    Some(matches[choice - 1].slugged_name.clone())
+                }
+                _ => {
+                    println!("No game selected.");
+                    None
+                }
+            }
+        }
+        Resolution::NotFound(suggestions) => {
+            println!("{query} is not in {source}");
+            if !suggestions.is_empty() {
+                println!("Did you mean:");
+                for product in suggestions {
+                    println!("  {}", product);
+                }
+            }
+            None
+        }
+    }
+}
+
+/// Finds the install of `slug` picked by `selector`, see [`InstalledConfig::get`]. Prints why
+/// if there's none.
+pub(crate) fn select_install<'a>(
+    installed: &'a InstalledConfig,
+    slug: &str,
+    selector: Option<&str>,
+) -> Option<&'a InstallInfo> {
+    let install_info = installed.get(slug, selector);
+    if install_info.is_some() {
+        return install_info;
+    }
+
+    let installs = installed.installs(slug);
+    match selector {
+        _ if installs.is_empty() => println!("{slug} is not installed."),
+        Some(selector) => println!("No install of {slug} matches {selector}."),
+        None => println!("{slug} is installed several times. Pick one with --label."),
+    }
+    if !installs.is_empty() {
+        println!("Installs:");
+        for install in installs {
+            println!(
+                "  {} {} ({}) -> {}",
+                install.id(slug),
+                install.version,
+                install.os.name(),
+                install.install_path.display()
+            );
+        }
+    }
+    None
+}
+
+/// Finds the download of `slug` picked by `selector`, see [`QueueConfig::select`]. Prints why
+/// if there's none. Returns the id of the install it creates.
+pub(crate) fn select_download(
+    queue: &QueueConfig,
+    slug: &str,
+    selector: Option<&str>,
+) -> Option<String> {
+    if let Some(download) = queue.select(slug, selector) {
+        return Some(download.id());
+    }
+
+    let downloads: Vec<_> = queue.downloads.iter().filter(|d| d.slug == slug).collect();
+    match selector {
+        _ if downloads.is_empty() => println!("{slug} is not queued."),
+        Some(selector) => println!("No download of {slug} matches {selector}."),
+        None => println!("{slug} is queued several times. Pick one with --label."),
+    }
+    if !downloads.is_empty() {
+        println!("Downloads:");
+        for download in downloads {
+            println!("  {}", download);
+        }
+    }
+    None
+}
@@ -0,0 +1,262 @@
+use std::cmp::Reverse;
+
+use crate::{
+    config::{GalaConfig, InstalledConfig, SettingsConfig},
+    helpers::{build_manifest_size, read_build_manifest},
+    shared::models::api::{BuildOs, Product},
+};
+
+/// Order to list games in
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum LibrarySort {
+    /// Alphabetically by name
+    Name,
+    /// Newest build first
+    Date,
+    /// Biggest install first. Manifests aren't fetched to size games, so games that aren't
+    /// installed are only sized while their manifest is stored, e.g. when they're queued. The
+    /// others are listed last.
+    Size,
+}
+
+/// Which games of the library to list, and in what order
+#[derive(Debug, Clone, Default)]
+pub struct LibraryQuery {
+    /// Fuzzy search on the name and slug. Results are ordered by relevance unless `sort` is set.
+    pub search: Option<String>,
+    /// Only list installed (`true`) or not installed (`false`) games
+    pub installed: Option<bool>,
+    /// Only list games with a build for this OS
+    pub os: Option<BuildOs>,
+    /// Only list installed games with a newer build available
+    pub has_updates: bool,
+    /// Only list games by this developer namespace
+    pub namespace: Option<String>,
+    pub sort: Option<LibrarySort>,
+}
+
+impl LibraryQuery {
+    /// Returns the games of `products` matching the query, in order
+    pub async fn run(&self, products: Vec<Product>, installed: &InstalledConfig) -> Vec<Product> {
+        let mut matches: Vec<(Product, u32)> = products
+            .into_iter()
+            .filter_map(|product| {
+                let score = match &self.search {
+                    Some(search) => fuzzy_score(search, &product.name)
+                        .max(fuzzy_score(search, &product.slugged_name))?,
+                    None => 0,
+                };
+                self.matches(&product, installed)
+                    .then_some((product, score))
+            })
+            .collect();
+
+        match self.sort {
+            Some(LibrarySort::Name) => {
+                matches.sort_by_key(|(product, _)| product.name.to_lowercase())
+            }
+            Some(LibrarySort::Date) => matches
+                .sort_by_key(|(product, _)| Reverse(product.version.iter().map(|v| v.date).max())),
+            Some(LibrarySort::Size) => {
+                let settings = SettingsConfig::load().unwrap_or_default();
+                let mut sized = Vec::with_capacity(matches.len());
+                for (product, score) in matches {
+                    let size = product_size(&product, installed, &settings).await;
+                    sized.push((product, score, size));
+                }
+                sized.sort_by_key(|(_, _, size)| Reverse(*size));
+                matches = sized
+                    .into_iter()
+                    .map(|(product, score, _)| (product, score))
+                    .collect();
+            }
+            // Shorter names match closer when the scores are the same
+            None if self.search.is_some() => {
+                matches.sort_by_key(|(product, score)| (Reverse(*score), product.name.len()))
+            }
+            None => {}
+        }
+
+        matches.into_iter().map(|(product, _)| product).collect()
+    }
+
+    fn matches(&self, product: &Product, installed: &InstalledConfig) -> bool {
+        let installs = installed.installs(&product.slugged_name);
+        if let Some(want_installed) = self.installed {
+            if installs.is_empty() == want_installed {
+                return false;
+            }
+        }
+        if let Some(os) = &self.os {
+            if !product.version.iter().any(|v| v.os == *os) {
+                return false;
+            }
+        }
+        if self.has_updates {
+            let has_update = installs
+                .iter()
+                .any(|info| product.update_for(info).is_some());
+            if !has_update {
+                return false;
+            }
+        }
+        if let Some(namespace) = &self.namespace {
+            if !product.namespace.eq_ignore_ascii_case(namespace) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Scores how well `pattern` matches `text`, ignoring case and whitespace in the pattern.
+/// Every character of the pattern has to appear in `text` in order. Consecutive characters and
+/// matches at the start of a word score higher. Returns `None` if the pattern doesn't match.
+pub fn fuzzy_score(pattern: &str, text: &str) -> Option<u32> {
+    let pattern: Vec<char> = pattern
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    let mut score = 0;
+    let mut matched = 0;
+    let mut last_match = None;
+    for (i, c) in text.iter().enumerate() {
+        if matched == pattern.len() {
+            break;
+        }
+        if *c != pattern[matched] {
+            continue;
+        }
+
+        score += 1;
+        if i > 0 && last_match == Some(i - 1) {
+            score += 4;
+        }
+        if i == 0 || !text[i - 1].is_alphanumeric() {
+            score += 3;
+        }
+        last_match = Some(i);
+        matched += 1;
+    }
+
+    (matched == pattern.len()).then_some(score)
+}
+
+/// Size of every install of a game, or of the build it would be installed with for games that
+/// aren't installed, if the manifests are stored locally
+async fn product_size(
+    product: &Product,
+    installed: &InstalledConfig,
+    settings: &SettingsConfig,
+) -> Option<u64> {
+    let installs = installed.installs(&product.slugged_name);
+    if installs.is_empty() {
+        let preference = settings.os_preference(&product.slugged_name);
+        let os = product.preferred_os(&preference, false)?;
+        let version = &product.get_latest_version(Some(os))?.version;
+        let manifest = read_build_manifest(version, &product.slugged_name, "manifest")
+            .await
+            .ok()?;
+        return build_manifest_size(&manifest[..]).ok();
+    }
+
+    // Installs are sized from the manifests stored for their own install id
+    let mut size = 0;
+    for info in installs {
+        let manifest =
+            read_build_manifest(&info.version, &info.id(&product.slugged_name), "manifest")
+                .await
+                .ok()?;
+        size += build_manifest_size(&manifest[..]).ok()?;
+    }
+    Some(size)
+}
+
+/// Outcome of looking up a game by what the user typed
+#[derive(Debug)]
+pub enum Resolution<'a> {
+    Found(&'a Product),
+    /// Several games start with the given name
+    Ambiguous(Vec<&'a Product>),
+    /// No game matched. Contains the closest games by edit distance, closest first.
+    NotFound(Vec<&'a Product>),
+}
+
+/// How many games are suggested when nothing matched
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Looks up a game by its slug, its numeric product id, or a unique prefix of its name. Slugs
+/// and names are compared ignoring case.
+pub fn resolve<'a>(products: &'a [Product], query: &str) -> Resolution<'a> {
+    let query = query.trim();
+    let lowercase_query = query.to_lowercase();
+
+    if let Some(product) = products
+        .iter()
+        .find(|p| p.slugged_name.eq_ignore_ascii_case(query))
+    {
+        return Resolution::Found(product);
+    }
+    if let Ok(id) = query.parse::<u64>() {
+        if let Some(product) = products.iter().find(|p| p.id == id) {
+            return Resolution::Found(product);
+        }
+    }
+    // A full name wins over the longer names it is a prefix of, e.g. "Syberia" and "Syberia II"
+    if let Some(product) = products
+        .iter()
+        .find(|p| p.name.to_lowercase() == lowercase_query)
+    {
+        return Resolution::Found(product);
+    }
+
+    let mut matches: Vec<&Product> = products
+        .iter()
+        .filter(|p| !query.is_empty() && p.name.to_lowercase().starts_with(&lowercase_query))
+        .collect();
+    match matches.len() {
+        1 => return Resolution::Found(matches.remove(0)),
+        n if n > 1 => return Resolution::Ambiguous(matches),
+        _ => {}
+    }
+
+    let mut suggestions: Vec<(usize, &Product)> = products
+        .iter()
+        .map(|p| {
+            let distance = edit_distance(&lowercase_query, &p.slugged_name)
+                .min(edit_distance(&lowercase_query, &p.name.to_lowercase()));
+            (distance, p)
+        })
+        // Games that share almost nothing with the query aren't worth suggesting
+        .filter(|(distance, _)| *distance <= (query.chars().count() / 2).max(2))
+        .collect();
+    suggestions.sort_by_key(|(distance, _)| *distance);
+    Resolution::NotFound(
+        suggestions
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, p)| p)
+            .collect(),
+    )
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
@@ -0,0 +1,102 @@
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use log::info;
+use tokio_util::io::ReaderStream;
+
+use crate::api::product::CONTENT_ROOT;
+
+/// Serves the manifests and chunks of a local archive at the same paths as the content CDN, so
+/// other machines can install from it by using it as their content URL.
+pub async fn serve(root: PathBuf, addr: SocketAddr) -> hyper::Result<()> {
+    let root = Arc::new(root);
+    let make_service = make_service_fn(move |_| {
+        let root = root.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let root = root.clone();
+                async move { Ok::<_, Infallible>(handle_request(&root, req).await) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    info!("Serving content mirror on http://{}", server.local_addr());
+
+    server
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await
+}
+
+async fn handle_request(root: &Path, req: Request<Body>) -> Response<Body> {
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return status_response(StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    let file_path = match content_path(req.uri().path()) {
+        Some(path) => root.join(path),
+        None => return status_response(StatusCode::NOT_FOUND),
+    };
+    // Chunks and manifests are streamed, so serving them doesn't read whole files into memory
+    let file = match tokio::fs::File::open(&file_path).await {
+        Ok(file) => file,
+        Err(_) => return status_response(StatusCode::NOT_FOUND),
+    };
+    let len = match file.metadata().await {
+        Ok(metadata) if metadata.is_file() => metadata.len(),
+        _ => return status_response(StatusCode::NOT_FOUND),
+    };
+
+    let mut res = Response::builder().header(hyper::header::CONTENT_LENGTH, len);
+    if file_path.extension().is_some_and(|ext| ext == "csv") {
+        res = res.header(hyper::header::CONTENT_TYPE, "text/csv");
+    } else {
+        res = res.header(hyper::header::CONTENT_TYPE, "application/octet-stream");
+    }
+    let body = match req.method() {
+        &Method::HEAD => Body::empty(),
+        _ => Body::wrap_stream(ReaderStream::new(file)),
+    };
+
+    res.body(body).unwrap()
+}
+
+/// Maps a request path to a file in the archive. Only paths under the content root are served,
+/// and path segments that could escape it are rejected.
+fn content_path(uri_path: &str) -> Option<PathBuf> {
+    let segments: Vec<&str> = uri_path.trim_start_matches('/').split('/').collect();
+    if segments.first() != Some(&CONTENT_ROOT) {
+        return None;
+    }
+
+    let valid = segments.iter().all(|segment| {
+        !segment.is_empty()
+            && *segment != "."
+            && *segment != ".."
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+    });
+    if !valid {
+        return None;
+    }
+
+    Some(segments.iter().collect())
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
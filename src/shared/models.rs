@@ -1,107 +1,205 @@
 use std::path::PathBuf;
 
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use chrono::NaiveTime;
+use human_bytes::human_bytes;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
-pub(crate) struct InstallInfo {
+/// A game installed on disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct InstallInfo {
     /// Directory where game was installed to
-    pub(crate) install_path: PathBuf,
+    pub install_path: PathBuf,
     /// Version of the game that is installed
-    pub(crate) version: String,
+    pub version: String,
     /// OS the build is for
     #[serde(default)]
-    pub(crate) os: api::BuildOs,
+    pub os: api::BuildOs,
+    /// Tells this install apart from other installs of the same game, e.g. a beta build or the
+    /// build of another OS. Only the first install of a game can be unlabeled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Version the game is held at. Newer builds aren't offered as updates.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hold: Option<String>,
 }
 
 impl InstallInfo {
-    pub(crate) fn new(install_path: PathBuf, version: String, os: api::BuildOs) -> InstallInfo {
+    pub fn new(install_path: PathBuf, version: String, os: api::BuildOs) -> InstallInfo {
         InstallInfo {
             install_path,
             version,
             os,
+            label: None,
+            hold: None,
         }
     }
+
+    /// Identifies this install of the game with `slug`, see [`install_id`]
+    pub fn id(&self, slug: &str) -> String {
+        install_id(slug, self.label.as_deref())
+    }
+
+    /// Whether `selector` picks this install, either by its label or by its OS
+    pub fn matches(&self, selector: &str) -> bool {
+        self.label.as_deref() == Some(selector)
+            || <api::BuildOs as clap::ValueEnum>::from_str(selector, true)
+                .is_ok_and(|os| os == self.os)
+    }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub(crate) struct BuildManifestRecord {
-    #[serde(rename = "Size in Bytes")]
-    pub(crate) size_in_bytes: usize,
-    #[serde(rename = "Chunks")]
-    pub(crate) chunks: usize,
-    #[serde(rename = "SHA")]
-    pub(crate) sha: String,
-    #[serde(rename = "Flags")]
-    pub(crate) flags: u8,
-    #[serde(
-        rename = "File Name",
-        deserialize_with = "from_latin1_str",
-        serialize_with = "to_latin1_bytes"
-    )]
-    pub(crate) file_name: String,
-    #[serde(rename = "Change Tag")]
-    pub(crate) tag: Option<ChangeTag>,
+/// Identifies an install of a game: its slug, followed by `@label` for labeled installs. Also
+/// used as the name of the directory labeled installs and their manifests are kept in, so they
+/// don't overwrite the game's other installs.
+pub fn install_id(slug: &str, label: Option<&str>) -> String {
+    match label {
+        Some(label) => format!("{slug}@{label}"),
+        None => slug.to_owned(),
+    }
 }
 
-impl BuildManifestRecord {
-    pub(crate) fn is_directory(&self) -> bool {
-        self.flags == 40
+/// A game waiting in the download queue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct QueuedInstall {
+    /// Slug of the game to install
+    pub slug: String,
+    /// Build version to install. The latest build is installed if not set.
+    pub version: Option<String>,
+    /// OS the build is for
+    pub os: Option<api::BuildOs>,
+    /// Directory to install the game to
+    pub install_path: PathBuf,
+    /// Downloads with a higher priority are started first
+    pub priority: i32,
+    /// Skip verifying downloaded chunks
+    pub skip_verify: bool,
+    /// Local archive to install the game from instead of downloading it
+    #[serde(default)]
+    pub archive: Option<PathBuf>,
+    /// Allow installing a build that was disabled or pulled by the developer
+    #[serde(default)]
+    pub include_disabled: bool,
+    /// Install even if there isn't enough free disk space
+    #[serde(default)]
+    pub force: bool,
+    /// Allocate every file to its final size before downloading
+    #[serde(default)]
+    pub preallocate: bool,
+    /// Label of the install, to install the game next to its other installs
+    #[serde(default)]
+    pub label: Option<String>,
+    pub status: QueueStatus,
+}
+
+impl QueuedInstall {
+    /// Queues the latest build of the game with `slug`, to be installed to `install_path`
+    pub fn new(slug: String, install_path: PathBuf) -> QueuedInstall {
+        QueuedInstall {
+            slug,
+            version: None,
+            os: None,
+            install_path,
+            priority: 0,
+            skip_verify: false,
+            archive: None,
+            include_disabled: false,
+            force: false,
+            preallocate: false,
+            label: None,
+            status: QueueStatus::Queued,
+        }
+    }
+
+    /// Identifies the install this download creates, see [`install_id`]
+    pub fn id(&self) -> String {
+        install_id(&self.slug, self.label.as_deref())
     }
 
-    pub(crate) fn is_empty(&self) -> bool {
-        self.size_in_bytes == 0
+    /// Whether `selector` picks this download, either by its label or by its OS
+    pub fn matches(&self, selector: &str) -> bool {
+        self.label.as_deref() == Some(selector)
+            || <api::BuildOs as clap::ValueEnum>::from_str(selector, true)
+                .is_ok_and(|os| self.os.as_ref() == Some(&os))
     }
 }
 
 #[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
-pub(crate) enum ChangeTag {
-    Added,
-    Modified,
-    Removed,
+pub enum QueueStatus {
+    Queued,
+    Paused,
+    Failed,
+}
+
+impl std::fmt::Display for QueuedInstall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}] {} (priority {}) -> {}",
+            self.id(),
+            match self.status {
+                QueueStatus::Queued => "queued",
+                QueueStatus::Paused => "paused",
+                QueueStatus::Failed => "failed",
+            },
+            self.priority,
+            self.install_path.display()
+        )?;
+        if let Some(version) = &self.version {
+            write!(f, " [build {}]", version)?;
+        }
+
+        Ok(())
+    }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub(crate) struct BuildManifestChunksRecord {
-    #[serde(rename = "ID")]
-    pub(crate) id: u16,
-    #[serde(
-        rename = "Filepath",
-        deserialize_with = "from_latin1_str",
-        serialize_with = "to_latin1_bytes"
-    )]
-    pub(crate) file_path: String,
-    #[serde(rename = "Chunk SHA")]
-    pub(crate) sha: String,
+/// Hours during which downloads run at full speed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadSchedule {
+    /// Time of day downloads are allowed to start running at full speed
+    pub start: NaiveTime,
+    /// Time of day downloads stop running at full speed
+    pub end: NaiveTime,
+    /// Rate limit, in bytes per second, to use outside of the schedule. Downloads are paused
+    /// outside of the schedule if not set.
+    pub outside_limit: Option<u64>,
 }
 
-fn from_latin1_str<'de, D>(deserializer: D) -> Result<String, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let s: &[u8] = Deserialize::deserialize(deserializer)?;
-    Ok(s.iter().cloned().map(char::from).collect())
+impl DownloadSchedule {
+    /// Checks if `time` falls within the schedule. Schedules can wrap around midnight, e.g.
+    /// 23:00 - 07:00.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
 }
 
-fn to_latin1_bytes<S>(string: &str, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-{
-    serializer.serialize_bytes(&string.chars().map(|c| c as u8).collect::<Vec<u8>>()[..])
+impl std::fmt::Display for DownloadSchedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} - {} (outside: {})",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M"),
+            match self.outside_limit {
+                Some(limit) => format!("{}/s", human_bytes(limit as f64)),
+                None => "paused".to_owned(),
+            }
+        )
+    }
 }
 
 pub(crate) mod api {
+    use std::cmp::Ordering;
+
     use chrono::NaiveDateTime;
     use serde::{Deserialize, Serialize};
 
     use crate::config::{LibraryConfig, UserConfig};
 
-    #[derive(Debug, Serialize)]
-    pub(crate) struct LatestBuildNumberPayload {
-        dev_id: String,
-        id_key_name: String,
-        os_selected: String,
-    }
-
     #[derive(Debug, Deserialize)]
     pub(crate) struct GameDetailsResponse {
         pub(crate) status: String,
@@ -109,11 +207,14 @@ pub(crate) mod api {
         pub(crate) product_data: GameDetails,
     }
 
+    /// How a game is launched, as set by its developer
     #[derive(Debug, Deserialize, Serialize)]
-    pub(crate) struct GameDetails {
-        pub(crate) exe_path: Option<String>,
-        pub(crate) args: Option<String>,
-        pub(crate) cwd: Option<String>,
+    pub struct GameDetails {
+        /// Path of the executable, relative to the install path
+        pub exe_path: Option<String>,
+        pub args: Option<String>,
+        /// Working directory, relative to the install path
+        pub cwd: Option<String>,
     }
 
     #[derive(Deserialize, Debug)]
@@ -154,60 +255,179 @@ pub(crate) mod api {
         pub(crate) user_collection: Vec<Product>,
     }
 
+    /// A game in the user's library
     #[derive(Deserialize, Serialize, Debug, Clone)]
-    pub(crate) struct Product {
+    pub struct Product {
         #[serde(alias = "prod_dev_namespace")]
-        pub(crate) namespace: String,
+        pub namespace: String,
         #[serde(alias = "prod_slugged_name")]
-        pub(crate) slugged_name: String,
-        pub(crate) id: u64,
+        pub slugged_name: String,
+        pub id: u64,
         #[serde(alias = "prod_name")]
-        pub(crate) name: String,
+        pub name: String,
         #[serde(alias = "prod_id_key_name")]
-        pub(crate) id_key_name: String,
-        pub(crate) version: Vec<ProductVersion>,
+        pub id_key_name: String,
+        pub version: Vec<ProductVersion>,
     }
 
     impl Product {
-        pub(crate) fn get_latest_version(&self, os: Option<&BuildOs>) -> Option<&ProductVersion> {
-            self.version.iter().fold(None, |acc, version| {
-                let valid_os = match os {
-                    Some(build_os) => version.os == *build_os,
-                    #[cfg(target_os = "macos")]
-                    None => version.os == BuildOs::Mac,
-                    #[cfg(not(target_os = "macos"))]
-                    None => version.os == BuildOs::Windows,
-                };
-                if !valid_os {
-                    return acc;
-                }
-
-                match acc {
-                    Some(v) => {
-                        if version.date > v.date {
-                            Some(version)
-                        } else {
-                            acc
-                        }
+        /// Finds the build with `version`. Any OS matches if `os` isn't set.
+        pub fn find_version(&self, version: &str, os: Option<&BuildOs>) -> Option<&ProductVersion> {
+            self.version.iter().find(|v| {
+                v.version == version
+                    && match os {
+                        Some(target) => v.os == *target,
+                        None => true,
                     }
-                    None => Some(version),
-                }
             })
         }
+
+        /// Finds the most recent build for `os`, or for the first OS in
+        /// [`BuildOs::default_preference`] the game has a build for if `os` isn't set. Disabled
+        /// builds are skipped.
+        pub fn get_latest_version(&self, os: Option<&BuildOs>) -> Option<&ProductVersion> {
+            self.latest_version(os, false)
+        }
+
+        /// Finds the most recent build for `os`, or for the first OS in
+        /// [`BuildOs::default_preference`] the game has a build for if `os` isn't set
+        pub fn latest_version(
+            &self,
+            os: Option<&BuildOs>,
+            include_disabled: bool,
+        ) -> Option<&ProductVersion> {
+            let os = match os {
+                Some(os) => os.clone(),
+                None => self
+                    .preferred_os(&BuildOs::default_preference(), include_disabled)?
+                    .clone(),
+            };
+            self.version
+                .iter()
+                .filter(|version| version.os == os)
+                .filter(|version| include_disabled || version.disabled_reason().is_none())
+                .max_by(|a, b| a.cmp_build(b))
+        }
+
+        /// Finds the first OS in `preference` the game has a build for
+        pub fn preferred_os<'a>(
+            &self,
+            preference: &'a [BuildOs],
+            include_disabled: bool,
+        ) -> Option<&'a BuildOs> {
+            preference.iter().find(|os| {
+                self.version.iter().any(|version| {
+                    version.os == **os && (include_disabled || version.disabled_reason().is_none())
+                })
+            })
+        }
+
+        /// Finds the latest build for the OS of an installed game, if it's newer than the
+        /// installed build. Held games are only updated up to the version they're held at.
+        pub fn update_for(&self, install_info: &super::InstallInfo) -> Option<&ProductVersion> {
+            self.newer_builds(install_info)
+                .into_iter()
+                .filter(|build| !self.is_held_back(build, install_info))
+                .max_by(|a, b| a.cmp_build(b))
+        }
+
+        /// Finds the latest build for the OS of an installed game, if its hold keeps the game
+        /// from being updated to it
+        pub fn held_update(&self, install_info: &super::InstallInfo) -> Option<&ProductVersion> {
+            let latest = self
+                .newer_builds(install_info)
+                .into_iter()
+                .max_by(|a, b| a.cmp_build(b))?;
+            self.is_held_back(latest, install_info).then_some(latest)
+        }
+
+        /// Builds for the OS of an installed game that are newer than the installed build.
+        /// Disabled builds are skipped.
+        fn newer_builds(&self, install_info: &super::InstallInfo) -> Vec<&ProductVersion> {
+            self.version
+                .iter()
+                .filter(|build| {
+                    build.os == install_info.os
+                        && build.disabled_reason().is_none()
+                        && self.is_newer(build, &install_info.version)
+                })
+                .collect()
+        }
+
+        fn is_held_back(&self, build: &ProductVersion, install_info: &super::InstallInfo) -> bool {
+            match &install_info.hold {
+                Some(held_version) => self.is_newer(build, held_version),
+                None => false,
+            }
+        }
+
+        /// Whether `build` is newer than the build of its OS with `version`
+        fn is_newer(&self, build: &ProductVersion, version: &str) -> bool {
+            match self.find_version(version, Some(&build.os)) {
+                Some(other) => build.cmp_build(other) == Ordering::Greater,
+                // The build was removed from the library
+                None => compare_versions(&build.version, version) == Ordering::Greater,
+            }
+        }
     }
 
+    /// Compares version numbers part by part, numerically where both parts are numbers, so
+    /// "1.10" is newer than "1.9"
+    pub fn compare_versions(a: &str, b: &str) -> Ordering {
+        let parts = |version: &str| -> Vec<String> {
+            version
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|part| !part.is_empty())
+                .map(|part| part.to_lowercase())
+                .collect()
+        };
+        let (a, b) = (parts(a), parts(b));
+        for (a_part, b_part) in a.iter().zip(&b) {
+            let ordering = match (a_part.parse::<u64>(), b_part.parse::<u64>()) {
+                (Ok(a_number), Ok(b_number)) => a_number.cmp(&b_number),
+                _ => a_part.cmp(b_part),
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        a.len().cmp(&b.len())
+    }
+
+    /// A build of a game
     #[derive(Deserialize, Serialize, Debug, Clone)]
-    pub(crate) struct ProductVersion {
-        pub(crate) status: u16,
-        pub(crate) enabled: u8,
-        pub(crate) version: String,
-        pub(crate) os: BuildOs,
-        pub(crate) date: NaiveDateTime,
-        pub(crate) text: String,
+    pub struct ProductVersion {
+        pub status: u16,
+        pub enabled: u8,
+        pub version: String,
+        pub os: BuildOs,
+        pub date: NaiveDateTime,
+        pub text: String,
+    }
+
+    impl ProductVersion {
+        /// Orders builds by date, and by version number for builds from the same date
+        pub fn cmp_build(&self, other: &ProductVersion) -> Ordering {
+            self.date
+                .cmp(&other.date)
+                .then_with(|| compare_versions(&self.version, &other.version))
+        }
+
+        /// Why the build can't be installed, if it was disabled or pulled by the developer.
+        /// Only `enabled` is checked, as the meaning of `status` isn't known and enabled builds
+        /// with other statuses install fine.
+        pub fn disabled_reason(&self) -> Option<String> {
+            match self.enabled {
+                0 => Some("disabled by the developer".to_owned()),
+                _ => None,
+            }
+        }
     }
 
-    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
-    pub(crate) enum BuildOs {
+    /// OS a build is for
+    #[derive(Debug, Default, Serialize, Deserialize, PartialEq, Clone)]
+    pub enum BuildOs {
+        #[default]
         #[serde(rename = "win")]
         Windows,
         #[serde(rename = "lin")]
@@ -216,9 +436,34 @@ pub(crate) mod api {
         Mac,
     }
 
-    impl Default for BuildOs {
-        fn default() -> Self {
-            Self::Windows
+    impl BuildOs {
+        /// OS this program runs on. Windows is used on platforms IndieGala has no builds for.
+        pub fn host() -> BuildOs {
+            if cfg!(target_os = "linux") {
+                BuildOs::Linux
+            } else if cfg!(target_os = "macos") {
+                BuildOs::Mac
+            } else {
+                BuildOs::Windows
+            }
+        }
+
+        /// Order OSes are picked in when installing a game without selecting one: native builds
+        /// first, then Windows builds
+        pub fn default_preference() -> Vec<BuildOs> {
+            match BuildOs::host() {
+                BuildOs::Windows => vec![BuildOs::Windows],
+                host => vec![host, BuildOs::Windows],
+            }
+        }
+
+        /// Human readable name of the OS
+        pub fn name(&self) -> &'static str {
+            match self {
+                BuildOs::Windows => "Windows",
+                BuildOs::Linux => "Linux",
+                BuildOs::Mac => "macOS",
+            }
         }
     }
 
@@ -246,15 +491,10 @@ pub(crate) mod api {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             writeln!(f, "[{}]", self.version)?;
             writeln!(f, "Build Date: {}", self.date)?;
-            writeln!(
-                f,
-                "Platform: {}",
-                match self.os {
-                    BuildOs::Windows => "Windows",
-                    BuildOs::Linux => "Linux",
-                    BuildOs::Mac => "macOS",
-                }
-            )?;
+            writeln!(f, "Platform: {}", self.os.name())?;
+            if let Some(reason) = self.disabled_reason() {
+                writeln!(f, "Excluded: {}", reason)?;
+            }
             if !self.text.is_empty() {
                 writeln!(f, "About:\n\n{}", self.text)?;
             }
@@ -262,4 +502,33 @@ pub(crate) mod api {
             Ok(())
         }
     }
+
+    impl clap::ValueEnum for BuildOs {
+        fn value_variants<'a>() -> &'a [Self] {
+            &[Self::Windows, Self::Mac, Self::Linux]
+        }
+
+        fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+            match self {
+                Self::Windows => Some(clap::builder::PossibleValue::new("windows").alias("win")),
+                Self::Mac => {
+                    let possible_value = clap::builder::PossibleValue::new("mac");
+                    #[cfg(not(target_os = "macos"))]
+                    let possible_value = possible_value
+                        .help("You can install macOS games, but you won't be able to run them!");
+
+                    Some(possible_value)
+                }
+                Self::Linux => {
+                    let possible_value = clap::builder::PossibleValue::new("linux").alias("lin");
+                    #[cfg(not(target_os = "linux"))]
+                    let possible_value = possible_value.help(
+                        "You can install Linux games, but you probably won't be able to run them!",
+                    );
+
+                    Some(possible_value)
+                }
+            }
+        }
+    }
 }
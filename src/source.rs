@@ -0,0 +1,87 @@
+use std::path::{Path, PathBuf};
+
+use bytes::Bytes;
+
+use crate::{
+    api::{self, ApiClient},
+    archive,
+    error::Error,
+    helpers::is_valid_chunk_sha,
+    shared::models::api::{BuildOs, Product, ProductVersion},
+};
+
+/// Where build manifests and chunks are read from
+#[derive(Clone)]
+pub(crate) enum BuildSource {
+    /// The IndieGala content CDN
+    Cdn(ApiClient),
+    /// A local archive created with the `download` command
+    Archive(PathBuf),
+}
+
+impl BuildSource {
+    pub(crate) async fn build_manifest(
+        &self,
+        product: &Product,
+        build_version: &ProductVersion,
+    ) -> Result<Bytes, Error> {
+        match self {
+            Self::Cdn(client) => {
+                api::product::get_build_manifest(client, product, build_version).await
+            }
+            Self::Archive(root) => {
+                let file_name = api::product::manifest_file_name(build_version, "manifest");
+                read_archived(root, product, &build_version.os, &file_name).await
+            }
+        }
+    }
+
+    pub(crate) async fn build_manifest_chunks(
+        &self,
+        product: &Product,
+        build_version: &ProductVersion,
+    ) -> Result<Bytes, Error> {
+        match self {
+            Self::Cdn(client) => {
+                api::product::get_build_manifest_chunks(client, product, build_version).await
+            }
+            Self::Archive(root) => {
+                let file_name = api::product::manifest_file_name(build_version, "manifest_chunks");
+                read_archived(root, product, &build_version.os, &file_name).await
+            }
+        }
+    }
+
+    pub(crate) async fn chunk(
+        &self,
+        product: &Product,
+        os: &BuildOs,
+        chunk_sha: &String,
+    ) -> Result<Bytes, Error> {
+        match self {
+            Self::Cdn(client) => api::product::download_chunk(client, product, os, chunk_sha).await,
+            Self::Archive(root) => {
+                // Chunk SHAs come from the chunk manifest and end up in paths, so make sure they
+                // can't point outside of the archive
+                if !is_valid_chunk_sha(chunk_sha) {
+                    return Err(Error::Io(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("{} isn't a valid chunk SHA", chunk_sha),
+                    )));
+                }
+                read_archived(root, product, os, chunk_sha).await
+            }
+        }
+    }
+}
+
+async fn read_archived(
+    root: &Path,
+    product: &Product,
+    os: &BuildOs,
+    file_name: &str,
+) -> Result<Bytes, Error> {
+    let path = archive::build_dir(root, product, os).join(file_name);
+
+    Ok(Bytes::from(tokio::fs::read(path).await?))
+}
@@ -0,0 +1,131 @@
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+use tokio::sync::Mutex;
+
+use crate::{
+    config::{GalaConfig, SettingsConfig},
+    installer::Progress,
+};
+
+/// How often settings are reloaded, so limits can be changed while downloading
+const SETTINGS_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(PartialEq, Debug)]
+enum Throttle {
+    Unlimited,
+    Limit(u64),
+    Paused,
+}
+
+struct RateLimiterState {
+    settings: SettingsConfig,
+    settings_loaded_at: Instant,
+    throttle: Throttle,
+    /// Bytes that can be downloaded right away. Goes negative when a download overdraws it.
+    available: f64,
+    last_refill: Instant,
+}
+
+/// Global download rate limiter shared by every chunk download task.
+///
+/// The limit and schedule are read from [`SettingsConfig`] and refreshed periodically, unless
+/// a limit was set for this run with `--limit`.
+pub(crate) struct RateLimiter {
+    limit_override: Option<u64>,
+    state: Mutex<RateLimiterState>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit_override: Option<u64>) -> Self {
+        let now = Instant::now();
+
+        Self {
+            limit_override,
+            state: Mutex::new(RateLimiterState {
+                settings: SettingsConfig::load().unwrap_or_default(),
+                settings_loaded_at: now,
+                throttle: Throttle::Unlimited,
+                available: 0f64,
+                last_refill: now,
+            }),
+        }
+    }
+
+    /// Waits until `bytes` can be downloaded without going over the current rate limit. Call
+    /// it before sending a request, so the response is read at full speed. Throttle changes,
+    /// e.g. when the download schedule starts or ends, are passed to `report`.
+    pub(crate) async fn acquire(&self, bytes: usize, report: impl Fn(Progress)) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().await;
+                let now = Instant::now();
+                if now.duration_since(state.settings_loaded_at) >= SETTINGS_REFRESH_INTERVAL {
+                    if let Ok(settings) = SettingsConfig::load() {
+                        state.settings = settings;
+                    }
+                    state.settings_loaded_at = now;
+                }
+
+                let throttle = self.current_throttle(&state.settings);
+                if throttle != state.throttle {
+                    report(match &throttle {
+                        Throttle::Unlimited => Progress::Throttled { limit: None },
+                        Throttle::Limit(limit) => Progress::Throttled {
+                            limit: Some(*limit),
+                        },
+                        Throttle::Paused => Progress::Suspended,
+                    });
+                    state.throttle = throttle;
+                }
+
+                match state.throttle {
+                    Throttle::Unlimited => return,
+                    Throttle::Paused => SETTINGS_REFRESH_INTERVAL,
+                    Throttle::Limit(limit) => {
+                        let limit = limit as f64;
+                        let elapsed = now.duration_since(state.last_refill).as_secs_f64();
+                        // Allow bursts of up to one second worth of data
+                        state.available = (state.available + elapsed * limit).min(limit);
+                        state.last_refill = now;
+
+                        if state.available >= 0f64 {
+                            state.available -= bytes as f64;
+                            return;
+                        }
+
+                        Duration::from_secs_f64(-state.available / limit)
+                    }
+                }
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Corrects the bytes taken with [`RateLimiter::acquire`] once it's known how many were
+    /// actually downloaded
+    pub(crate) async fn settle(&self, acquired: usize, downloaded: usize) {
+        let mut state = self.state.lock().await;
+        if let Throttle::Limit(limit) = state.throttle {
+            state.available =
+                (state.available + acquired as f64 - downloaded as f64).min(limit as f64);
+        }
+    }
+
+    fn current_throttle(&self, settings: &SettingsConfig) -> Throttle {
+        if let Some(schedule) = &settings.download_schedule {
+            if !schedule.contains(Local::now().time()) {
+                return match schedule.outside_limit {
+                    Some(limit) => Throttle::Limit(limit.max(1)),
+                    None => Throttle::Paused,
+                };
+            }
+        }
+
+        match self.limit_override.or(settings.download_limit) {
+            Some(limit) => Throttle::Limit(limit.max(1)),
+            None => Throttle::Unlimited,
+        }
+    }
+}
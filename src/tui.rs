@@ -0,0 +1,804 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    cursor,
+    event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use freecarnival::{
+    config::{GalaConfig, InstalledConfig, LibraryConfig, QueueConfig},
+    constants::DEFAULT_BASE_INSTALL_PATH,
+    models::{install_id, InstallInfo, Product, QueueStatus, QueuedInstall},
+    prune_install_manifests,
+    search::fuzzy_score,
+    uninstall, Error, GalaClient, InstallOptions, Installer, Launcher, Progress, Verifier,
+};
+use futures::StreamExt;
+use human_bytes::human_bytes;
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Gauge, List, ListItem, ListState, Paragraph, Wrap},
+};
+use tokio::sync::Notify;
+
+use crate::{logger, progress::throttle_message};
+
+/// How many lines of output are kept in the log pane
+const MAX_LOG_LINES: usize = 200;
+
+/// Settings for installs and launches started from the TUI
+pub(crate) struct TuiOptions {
+    pub(crate) install_options: InstallOptions,
+    pub(crate) max_parallel_installs: usize,
+    pub(crate) base_path: Option<PathBuf>,
+    #[cfg(not(target_os = "windows"))]
+    pub(crate) wine_bin: Option<PathBuf>,
+    #[cfg(not(target_os = "windows"))]
+    pub(crate) wine_prefix: Option<PathBuf>,
+}
+
+/// Progress of a game being downloaded
+struct Download {
+    name: String,
+    total_bytes: u64,
+    downloaded: u64,
+    written: u64,
+    total_files: usize,
+    files_written: usize,
+    last_file: Option<String>,
+    started: Instant,
+}
+
+impl Download {
+    fn rate(&self, bytes: u64) -> f64 {
+        bytes as f64 / self.started.elapsed().as_secs_f64().max(1.0)
+    }
+}
+
+/// State updated by background tasks while the UI is running
+#[derive(Default)]
+struct Shared {
+    /// Running downloads by install id
+    downloads: HashMap<String, Download>,
+    log: VecDeque<String>,
+    status: String,
+    /// Set when the installed games changed and need to be reloaded
+    installed_changed: bool,
+}
+
+impl Shared {
+    fn log(&mut self, line: String) {
+        if self.log.len() == MAX_LOG_LINES {
+            self.log.pop_front();
+        }
+        self.log.push_back(line);
+    }
+
+    fn set_status(&mut self, status: String) {
+        self.log(status.clone());
+        self.status = status;
+    }
+
+    fn on_progress(&mut self, progress: &Progress) {
+        match progress {
+            Progress::Started {
+                install_id,
+                name,
+                total_bytes,
+                total_files,
+            } => {
+                self.downloads.insert(
+                    install_id.clone(),
+                    Download {
+                        name: name.clone(),
+                        total_bytes: *total_bytes,
+                        downloaded: 0,
+                        written: 0,
+                        total_files: *total_files,
+                        files_written: 0,
+                        last_file: None,
+                        started: Instant::now(),
+                    },
+                );
+            }
+            Progress::Downloaded { install_id, bytes } => {
+                if let Some(download) = self.downloads.get_mut(install_id) {
+                    download.downloaded += bytes;
+                }
+            }
+            Progress::Written { install_id, bytes } => {
+                if let Some(download) = self.downloads.get_mut(install_id) {
+                    download.written += bytes;
+                }
+            }
+            Progress::FileWritten {
+                install_id,
+                file_name,
+            } => {
+                if let Some(download) = self.downloads.get_mut(install_id) {
+                    download.files_written += 1;
+                    download.last_file = Some(file_name.clone());
+                }
+            }
+            Progress::Finished { install_id } => {
+                self.downloads.remove(install_id);
+            }
+            Progress::Installed {
+                install_id,
+                version,
+            } => {
+                self.set_status(format!("Successfully installed {install_id} ({version})"));
+                self.installed_changed = true;
+            }
+            Progress::Failed { install_id, error } => {
+                self.set_status(format!("Failed to install {install_id}: {error}"));
+            }
+            Progress::Paused { install_id } => {
+                self.set_status(format!("Stopped downloading {install_id}."));
+            }
+            Progress::Throttled { .. } | Progress::Suspended => {
+                if let Some(message) = throttle_message(progress) {
+                    self.set_status(message);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+struct App {
+    client: GalaClient,
+    installer: Installer,
+    options: TuiOptions,
+    queue_changed: Arc<Notify>,
+    shared: Arc<Mutex<Shared>>,
+    library: Vec<Product>,
+    installed: InstalledConfig,
+    queue: QueueConfig,
+    search: String,
+    searching: bool,
+    list_state: ListState,
+    /// Label of the install picked for games installed several times, by slug
+    selected_installs: HashMap<String, Option<String>>,
+    /// Install waiting for the user to confirm it should be uninstalled
+    confirm_uninstall: Option<(String, Option<String>)>,
+}
+
+impl App {
+    fn filtered(&self) -> Vec<&Product> {
+        self.library
+            .iter()
+            .filter(|p| {
+                fuzzy_score(&self.search, &p.name).is_some()
+                    || fuzzy_score(&self.search, &p.slugged_name).is_some()
+            })
+            .collect()
+    }
+
+    fn selected(&self) -> Option<Product> {
+        let index = self.list_state.selected()?;
+        self.filtered().get(index).map(|p| (*p).clone())
+    }
+
+    /// Install of a game that actions apply to. Defaults to its first install.
+    fn selected_install(&self, slug: &str) -> Option<&InstallInfo> {
+        let installs = self.installed.installs(slug);
+        self.selected_installs
+            .get(slug)
+            .and_then(|label| installs.iter().find(|info| info.label == *label))
+            .or(installs.first())
+    }
+
+    /// Picks the next install of a game installed several times
+    fn next_install(&mut self, product: Product) {
+        let slug = product.slugged_name;
+        let installs = self.installed.installs(&slug);
+        let current = self.selected_install(&slug).and_then(|selected| {
+            installs
+                .iter()
+                .position(|info| info.label == selected.label)
+        });
+        let next = match current {
+            Some(index) => &installs[(index + 1) % installs.len()],
+            None => {
+                self.set_status(format!("{slug} is not installed."));
+                return;
+            }
+        };
+        let label = next.label.clone();
+        self.set_status(format!("Selected {}", install_id(&slug, label.as_deref())));
+        self.selected_installs.insert(slug, label);
+    }
+
+    fn move_selection(&mut self, offset: isize) {
+        let len = self.filtered().len();
+        if len == 0 {
+            self.list_state.select(None);
+            return;
+        }
+        let current = self.list_state.selected().unwrap_or(0) as isize;
+        let next = (current + offset).clamp(0, len as isize - 1);
+        self.list_state.select(Some(next as usize));
+    }
+
+    fn reload(&mut self) {
+        let changed = std::mem::take(&mut self.shared.lock().unwrap().installed_changed);
+        if changed {
+            self.installed = InstalledConfig::load().unwrap_or_default();
+        }
+        self.queue = QueueConfig::load().unwrap_or_default();
+    }
+
+    fn set_status(&self, status: String) {
+        self.shared.lock().unwrap().set_status(status);
+    }
+
+    /// Handles a key press. Returns `false` once the user wants to quit.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return false;
+        }
+
+        if let Some((slug, label)) = self.confirm_uninstall.take() {
+            match key.code {
+                KeyCode::Char('y') | KeyCode::Char('Y') => self.uninstall(slug, label),
+                _ => self.set_status("Uninstall cancelled.".to_owned()),
+            }
+            return true;
+        }
+
+        if self.searching {
+            match key.code {
+                KeyCode::Esc => {
+                    self.searching = false;
+                    self.search.clear();
+                }
+                KeyCode::Enter => self.searching = false,
+                KeyCode::Backspace => {
+                    self.search.pop();
+                }
+                KeyCode::Char(c) => self.search.push(c),
+                KeyCode::Up => self.move_selection(-1),
+                KeyCode::Down => self.move_selection(1),
+                _ => {}
+            }
+            // Keep the selection within the filtered games
+            self.move_selection(0);
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Char('q') => return false,
+            KeyCode::Esc if self.search.is_empty() => return false,
+            KeyCode::Esc => {
+                self.search.clear();
+                self.move_selection(0);
+            }
+            KeyCode::Char('/') => self.searching = true,
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::PageUp => self.move_selection(-10),
+            KeyCode::PageDown => self.move_selection(10),
+            KeyCode::Char('i') => self.with_selected(Self::install),
+            KeyCode::Char('u') => self.with_selected(Self::update),
+            KeyCode::Tab => self.with_selected(Self::next_install),
+            KeyCode::Char('x') => self.with_selected(|app, product| {
+                if let Some(info) = app.install_info(&product) {
+                    let slug = product.slugged_name;
+                    app.set_status(format!(
+                        "Uninstall {}? Press y to confirm.",
+                        install_id(&slug, info.label.as_deref())
+                    ));
+                    app.confirm_uninstall = Some((slug, info.label));
+                }
+            }),
+            KeyCode::Char('v') => self.with_selected(Self::verify),
+            KeyCode::Char('l') => self.with_selected(Self::launch),
+            _ => {}
+        }
+
+        true
+    }
+
+    fn with_selected(&mut self, action: impl FnOnce(&mut Self, Product)) {
+        if let Some(product) = self.selected() {
+            action(self, product);
+        }
+    }
+
+    fn install_info(&self, product: &Product) -> Option<InstallInfo> {
+        let install_info = self.selected_install(&product.slugged_name).cloned();
+        if install_info.is_none() {
+            self.set_status(format!("{} is not installed.", product.slugged_name));
+        }
+        install_info
+    }
+
+    fn install(&mut self, product: Product) {
+        let slug = product.slugged_name;
+        if self.installed.contains(&slug) {
+            self.set_status(format!("{slug} already installed."));
+            return;
+        }
+        if self.queue.get(&install_id(&slug, None)).is_some() {
+            self.set_status(format!("{slug} is already queued."));
+            return;
+        }
+
+        let base_path = self
+            .options
+            .base_path
+            .clone()
+            .unwrap_or_else(|| DEFAULT_BASE_INSTALL_PATH.to_owned());
+        let mut download = QueuedInstall::new(slug.clone(), base_path.join(&slug));
+        download.skip_verify = self.options.install_options.skip_verify;
+        download.include_disabled = self.options.install_options.include_disabled;
+        download.force = self.options.install_options.force;
+        download.preallocate = self.options.install_options.preallocate;
+        self.queue.downloads.push(download);
+        match self.queue.store() {
+            Ok(()) => {
+                self.set_status(format!("Adding {slug} to the download queue..."));
+                self.queue_changed.notify_one();
+            }
+            Err(err) => self.set_status(format!("Failed to update download queue: {err}")),
+        }
+    }
+
+    fn update(&mut self, product: Product) {
+        let install_info = match self.install_info(&product) {
+            Some(info) => info,
+            None => return,
+        };
+        let installer = self.installer.clone();
+        let shared = self.shared.clone();
+        let slug = product.slugged_name.clone();
+        self.set_status(format!("Updating {slug}..."));
+
+        tokio::spawn(async move {
+            let status = match installer.update(&product, &install_info, None).await {
+                Ok(install_info) => {
+                    let install_id = install_info.id(&slug);
+                    let mut installed = InstalledConfig::load().unwrap_or_default();
+                    installed.insert(slug.clone(), install_info);
+                    match installed.store() {
+                        Ok(()) => {
+                            let _ = prune_install_manifests(&installed, &install_id).await;
+                            format!("Updated {slug} successfully.")
+                        }
+                        Err(err) => format!("Failed to update installed config: {err}"),
+                    }
+                }
+                Err(Error::AlreadyInstalled(version)) => {
+                    format!("{slug} is up to date ({version})")
+                }
+                Err(err) => format!("Failed to update {slug}: {err}"),
+            };
+            let mut shared = shared.lock().unwrap();
+            shared.set_status(status);
+            shared.installed_changed = true;
+        });
+    }
+
+    fn uninstall(&mut self, slug: String, label: Option<String>) {
+        let install_info = match self
+            .installed
+            .installs(&slug)
+            .iter()
+            .find(|info| info.label == label)
+        {
+            Some(info) => info.clone(),
+            None => return,
+        };
+        let shared = self.shared.clone();
+        let id = install_id(&slug, label.as_deref());
+        self.set_status(format!("Uninstalling {id}..."));
+
+        tokio::spawn(async move {
+            let status = match uninstall(&install_info.install_path).await {
+                Ok(()) => {
+                    let mut installed = InstalledConfig::load().unwrap_or_default();
+                    installed.remove(&slug, install_info.label.as_deref());
+                    match installed.store() {
+                        Ok(()) => {
+                            let _ = prune_install_manifests(&installed, &id).await;
+                            format!("{id} uninstalled successfuly.")
+                        }
+                        Err(err) => format!("Failed to update installed config: {err}"),
+                    }
+                }
+                Err(err) => format!("Failed to uninstall {id}: {err}"),
+            };
+            let mut shared = shared.lock().unwrap();
+            shared.set_status(status);
+            shared.installed_changed = true;
+        });
+    }
+
+    fn verify(&mut self, product: Product) {
+        let install_info = match self.install_info(&product) {
+            Some(info) => info,
+            None => return,
+        };
+        let shared = self.shared.clone();
+        let client = self.client.clone();
+        let slug = product.slugged_name.clone();
+        self.set_status(format!("Verifying {slug}..."));
+
+        tokio::spawn(async move {
+            let verifier = Verifier::new(&slug, &install_info).refetch_manifest(&client, &product);
+            let status = match verifier.verify().await {
+                Ok(true) => format!("{slug} passed verification."),
+                Ok(false) => format!("{slug} is corrupted. Please reinstall."),
+                Err(err) => format!("Failed to verify files: {err}"),
+            };
+            shared.lock().unwrap().set_status(status);
+        });
+    }
+
+    fn launch(&mut self, product: Product) {
+        let install_info = match self.install_info(&product) {
+            Some(info) => info,
+            None => return,
+        };
+        let launcher = Launcher::new(&self.client);
+        #[cfg(not(target_os = "windows"))]
+        let launcher = launcher
+            .wine_bin(self.options.wine_bin.clone())
+            .wine_prefix(self.options.wine_prefix.clone());
+        let shared = self.shared.clone();
+        let slug = product.slugged_name.clone();
+        self.set_status(format!("Launching {slug}..."));
+
+        tokio::spawn(async move {
+            let status = match launcher.launch(&product, &install_info).await {
+                Ok(Some(status)) => format!("{slug} exited with: {status}"),
+                Ok(None) => format!("Failed to launch {slug}"),
+                Err(err) => format!("Failed to launch {slug}: {err}"),
+            };
+            shared.lock().unwrap().set_status(status);
+        });
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let shared = self.shared.clone();
+        let shared = shared.lock().unwrap();
+        let download_height = shared.downloads.len().max(1) as u16 + 2;
+        let [search_area, main_area, downloads_area, log_area, status_area] = *Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Min(8),
+                Constraint::Length(download_height),
+                Constraint::Length(8),
+                Constraint::Length(1),
+            ])
+            .split(frame.size())
+        else {
+            return;
+        };
+        let [list_area, detail_area] = *Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+            .split(main_area)
+        else {
+            return;
+        };
+
+        let search_style = if self.searching {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default()
+        };
+        let search_text = if self.search.is_empty() && !self.searching {
+            "Press / to search".to_owned()
+        } else {
+            self.search.clone()
+        };
+        frame.render_widget(
+            Paragraph::new(search_text).style(search_style).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Search")
+                    .border_style(search_style),
+            ),
+            search_area,
+        );
+
+        let filtered = self.filtered();
+        let items: Vec<ListItem> = filtered
+            .iter()
+            .map(|product| {
+                let slug = &product.slugged_name;
+                let state = match (self.selected_install(slug), self.queue.select(slug, None)) {
+                    (Some(info), _) => match product.update_for(info) {
+                        Some(latest) => format!(" [{} -> {}]", info.version, latest.version),
+                        None => format!(" [{}]", info.version),
+                    },
+                    (None, Some(download)) => match download.status {
+                        QueueStatus::Queued if shared.downloads.contains_key(&download.id()) => {
+                            " [downloading]".to_owned()
+                        }
+                        QueueStatus::Queued => " [queued]".to_owned(),
+                        QueueStatus::Paused => " [paused]".to_owned(),
+                        QueueStatus::Failed => " [failed]".to_owned(),
+                    },
+                    (None, None) => String::new(),
+                };
+                let style = if self.installed.contains(slug) {
+                    Style::default().fg(Color::Green)
+                } else {
+                    Style::default()
+                };
+                ListItem::new(Line::from(vec![
+                    Span::styled(product.name.clone(), style),
+                    Span::styled(state, Style::default().fg(Color::DarkGray)),
+                ]))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(format!(
+                "Library ({}/{})",
+                filtered.len(),
+                self.library.len()
+            )))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("> ");
+        frame.render_stateful_widget(list, list_area, &mut self.list_state);
+
+        let detail = match self.selected() {
+            Some(product) => self.details(&product),
+            None => Text::from("No games found"),
+        };
+        frame.render_widget(
+            Paragraph::new(detail)
+                .wrap(Wrap { trim: false })
+                .block(Block::default().borders(Borders::ALL).title("Details")),
+            detail_area,
+        );
+
+        let block = Block::default().borders(Borders::ALL).title("Downloads");
+        let inner = block.inner(downloads_area);
+        frame.render_widget(block, downloads_area);
+        if shared.downloads.is_empty() {
+            frame.render_widget(Paragraph::new("No downloads running"), inner);
+        }
+        let mut downloads: Vec<_> = shared.downloads.values().collect();
+        downloads.sort_by(|a, b| a.name.cmp(&b.name));
+        for (row, download) in downloads.into_iter().enumerate() {
+            let area = Rect {
+                y: inner.y + row as u16,
+                height: 1,
+                ..inner
+            };
+            if area.y >= inner.bottom() {
+                break;
+            }
+            let ratio = match download.total_bytes {
+                0 => 1.0,
+                total => (download.written as f64 / total as f64).min(1.0),
+            };
+            let mut label = format!(
+                "{} {}/{} Download: {}/s Disk: {}/s",
+                download.name,
+                human_bytes(download.written as f64),
+                human_bytes(download.total_bytes as f64),
+                human_bytes(download.rate(download.downloaded)),
+                human_bytes(download.rate(download.written)),
+            );
+            if download.total_files > 0 {
+                label.push_str(&format!(
+                    " Files: {}/{}",
+                    download.files_written, download.total_files
+                ));
+            }
+            if let Some(file) = &download.last_file {
+                label.push_str(&format!(" {file}"));
+            }
+            frame.render_widget(
+                Gauge::default()
+                    .gauge_style(Style::default().fg(Color::Cyan))
+                    .ratio(ratio)
+                    .label(label),
+                area,
+            );
+        }
+
+        let log_height = log_area.height.saturating_sub(2) as usize;
+        let log: Vec<Line> = shared
+            .log
+            .iter()
+            .skip(shared.log.len().saturating_sub(log_height))
+            .map(|line| Line::from(line.as_str()))
+            .collect();
+        frame.render_widget(
+            Paragraph::new(log).block(Block::default().borders(Borders::ALL).title("Log")),
+            log_area,
+        );
+
+        let help = "/ search  i install  u update  x uninstall  v verify  l launch  tab next install  q quit";
+        let status = if shared.status.is_empty() {
+            help.to_owned()
+        } else {
+            format!("{}  |  {}", shared.status, help)
+        };
+        frame.render_widget(
+            Paragraph::new(status).style(Style::default().add_modifier(Modifier::BOLD)),
+            status_area,
+        );
+    }
+
+    fn details(&self, product: &Product) -> Text<'static> {
+        let mut lines = vec![
+            Line::from(Span::styled(
+                product.name.clone(),
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            Line::from(format!("Slug: {}", product.slugged_name)),
+        ];
+        let installs = self.installed.installs(&product.slugged_name);
+        if installs.is_empty() {
+            lines.push(Line::from("Not installed"));
+        }
+        let selected = self.selected_install(&product.slugged_name);
+        for info in installs {
+            let marker = match selected {
+                Some(selected) if installs.len() > 1 && selected.label == info.label => "> ",
+                _ => "",
+            };
+            let label = match &info.label {
+                Some(label) => format!(" [{label}]"),
+                None => String::new(),
+            };
+            lines.push(Line::from(format!(
+                "{}Installed{}: {} ({}) in {}",
+                marker,
+                label,
+                info.version,
+                info.os,
+                info.install_path.display()
+            )));
+        }
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(
+            "Available Versions",
+            Style::default().add_modifier(Modifier::UNDERLINED),
+        )));
+
+        let mut versions: Vec<_> = product.version.iter().collect();
+        versions.sort_by_key(|v| std::cmp::Reverse(v.date));
+        for version in versions {
+            lines.push(Line::from(""));
+            for line in version.to_string().lines() {
+                lines.push(Line::from(line.to_owned()));
+            }
+        }
+
+        Text::from(lines)
+    }
+}
+
+/// Draws the TUI on the alternate screen. The terminal is restored once the guard is dropped,
+/// or if the TUI panics, and logged messages go to the log pane in the meantime.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn start(shared: Arc<Mutex<Shared>>) -> io::Result<Self> {
+        logger::redirect(move |message| shared.lock().unwrap().log(message));
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            hook(info);
+        }));
+
+        let guard = Self;
+        enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+        // Panics are printed as usual from now on
+        let _ = std::panic::take_hook();
+    }
+}
+
+fn restore_terminal() {
+    logger::reset();
+    let _ = disable_raw_mode();
+    let _ = execute!(io::stdout(), LeaveAlternateScreen, cursor::Show);
+}
+
+/// Runs the TUI until the user quits. Downloads that are still running are left in the queue,
+/// so they can be resumed with `queue resume`.
+pub(crate) async fn run(client: GalaClient, options: TuiOptions) -> io::Result<()> {
+    let shared = Arc::new(Mutex::new(Shared::default()));
+    let progress_shared = shared.clone();
+    let installer = Installer::new(&client, options.install_options.clone())
+        .on_progress(move |progress| progress_shared.lock().unwrap().on_progress(progress));
+
+    let queue_changed = Arc::new(Notify::new());
+    let worker = {
+        let installer = installer.clone();
+        let queue_changed = queue_changed.clone();
+        let shared = shared.clone();
+        let max_parallel_installs = options.max_parallel_installs;
+        tokio::spawn(async move {
+            loop {
+                queue_changed.notified().await;
+                if let Err(err) = installer.process_queue(max_parallel_installs).await {
+                    shared
+                        .lock()
+                        .unwrap()
+                        .set_status(format!("Failed to process the download queue: {}", err));
+                }
+            }
+        })
+    };
+
+    let library = LibraryConfig::load()
+        .map(|library| library.collection)
+        .unwrap_or_default();
+    let mut app = App {
+        client,
+        installer,
+        options,
+        queue_changed,
+        shared: shared.clone(),
+        list_state: ListState::default().with_selected((!library.is_empty()).then_some(0)),
+        library,
+        installed: InstalledConfig::load().unwrap_or_default(),
+        queue: QueueConfig::load().unwrap_or_default(),
+        search: String::new(),
+        searching: false,
+        selected_installs: HashMap::new(),
+        confirm_uninstall: None,
+    };
+
+    let guard = TerminalGuard::start(shared)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+    let result = event_loop(&mut terminal, &mut app).await;
+
+    drop(guard);
+    worker.abort();
+
+    result
+}
+
+async fn event_loop(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    app: &mut App,
+) -> io::Result<()> {
+    let mut events = EventStream::new();
+    let mut redraw = tokio::time::interval(Duration::from_millis(250));
+
+    loop {
+        app.reload();
+        terminal.draw(|frame| app.draw(frame))?;
+
+        tokio::select! {
+            event = events.next() => match event {
+                Some(Ok(Event::Key(key))) if key.kind == KeyEventKind::Press => {
+                    if !app.handle_key(key) {
+                        return Ok(());
+                    }
+                }
+                Some(Ok(_)) => {}
+                Some(Err(err)) => return Err(err),
+                None => return Ok(()),
+            },
+            _ = redraw.tick() => {}
+        }
+    }
+}
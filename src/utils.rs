@@ -1,226 +1,764 @@
-use std::{collections::HashMap, path::PathBuf, process::ExitStatus, sync::Arc};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    sync::Arc,
+};
 
-use human_bytes::human_bytes;
+use async_recursion::async_recursion;
+use futures::{stream::FuturesUnordered, StreamExt};
+use log::{info, warn};
 use os_path::OsPath;
 use regex::Regex;
 use tokio::task::JoinHandle;
 
+#[cfg(target_os = "linux")]
+use crate::helpers::find_linux_exe;
 #[cfg(target_os = "macos")]
 use crate::helpers::mac::{find_app_recursive, find_info_plist, MacAppExecutables};
 use crate::{
-    api,
-    cli::InstallOpts,
-    config::{GalaConfig, InstalledConfig, LibraryConfig},
+    api::{self, ApiClient},
+    archive::{self, ArchiveIndex},
+    config::{GalaConfig, InstalledConfig, LibraryConfig, QueueConfig, SettingsConfig},
+    error::Error,
     helpers::{
-        build_from_manifest, find_exe_recursive, read_build_manifest,
+        archive_chunks, available_space, build_from_manifest, build_manifest_size,
+        copied_files_size, find_exe_recursive, manifests_dir, read_build_manifest,
         read_or_generate_delta_chunks_manifest, read_or_generate_delta_manifest,
-        store_build_manifest, verify_file_hash,
+        store_build_manifest, verify_file_hash, DownloadPool,
     },
+    installer::{BuildDiff, ChangedFile, InstallOptions, InstallSize, Progress, UpdateSize},
+    manifest::{BuildManifest, ChangeTag, InstallPaths},
     shared::models::{
         api::{BuildOs, Product, ProductVersion},
-        BuildManifestRecord, ChangeTag, InstallInfo,
+        install_id, InstallInfo, QueueStatus, QueuedInstall,
     },
+    source::BuildSource,
 };
 
-// TODO: Refactor info printing and chunk downloading to separate functions
-pub(crate) async fn install<'a>(
-    client: reqwest::Client,
-    slug: &String,
-    install_path: &PathBuf,
-    install_opts: InstallOpts,
-    version: Option<&ProductVersion>,
-    os: Option<BuildOs>,
-) -> Result<Result<(String, Option<InstallInfo>), &'a str>, reqwest::Error> {
-    let library = LibraryConfig::load().expect("Failed to load library");
-    let product = match library.collection.iter().find(|p| p.slugged_name == *slug) {
-        Some(product) => product,
+/// Picks the selected build, or the latest build for `os`. Disabled builds are only picked if
+/// `include_disabled` is set.
+fn select_build<'a>(
+    product: &'a Product,
+    version: Option<&'a ProductVersion>,
+    os: Option<&BuildOs>,
+    include_disabled: bool,
+) -> Result<&'a ProductVersion, Error> {
+    match version {
+        Some(selected) => match selected.disabled_reason() {
+            Some(reason) if !include_disabled => Err(Error::BuildDisabled {
+                version: selected.version.to_owned(),
+                reason,
+            }),
+            _ => Ok(selected),
+        },
         None => {
-            return Ok(Err("Could not find game in library"));
+            let os = match os {
+                Some(os) => Some(os.clone()),
+                None => preferred_os(product, include_disabled),
+            };
+            os.and_then(|os| product.latest_version(Some(&os), include_disabled))
+                .ok_or(Error::BuildNotFound)
         }
-    };
+    }
+}
 
-    let build_version = match version {
-        Some(selected) => selected,
-        None => match product.get_latest_version(os.as_ref()) {
-            Some(latest) => latest,
-            None => {
-                return Ok(Err("Failed to fetch latest build number. Cannot install."));
-            }
-        },
-    };
-    println!("Found game. Installing build version {}...", build_version);
+/// Picks the first OS in the user's preference order the game has a build for, and explains
+/// the choice
+fn preferred_os(product: &Product, include_disabled: bool) -> Option<BuildOs> {
+    let preference = SettingsConfig::load()
+        .unwrap_or_default()
+        .os_preference(&product.slugged_name);
+    let os = product.preferred_os(&preference, include_disabled)?.clone();
 
-    println!("Fetching build manifest...");
-    let build_manifest = api::product::get_build_manifest(&client, product, build_version).await?;
+    let skipped: Vec<&str> = preference
+        .iter()
+        .take_while(|preferred| **preferred != os)
+        .map(|preferred| preferred.name())
+        .collect();
+    if skipped.is_empty() {
+        info!(
+            "Picked the {} build, the first OS in your preference order.",
+            os.name()
+        );
+    } else {
+        info!(
+            "No {} build available, picked the {} build instead.",
+            skipped.join(" or "),
+            os.name()
+        );
+    }
+    Some(os)
+}
+
+pub(crate) async fn install_size(
+    source: BuildSource,
+    product: &Product,
+    install_path: &Path,
+    version: Option<&ProductVersion>,
+    os: Option<BuildOs>,
+    include_disabled: bool,
+) -> Result<InstallSize, Error> {
+    let build_version = select_build(product, version, os.as_ref(), include_disabled)?;
+    info!("Found game. Installing build version {}...", build_version);
+
+    info!("Fetching build manifest...");
+    let build_manifest = source.build_manifest(product, build_version).await?;
+    let download_size = build_manifest_size(&build_manifest[..])?;
+
+    Ok(InstallSize {
+        download_size,
+        disk_size: download_size,
+        free_space: available_space(install_path).ok(),
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn install(
+    source: BuildSource,
+    product: &Product,
+    install_path: &Path,
+    install_opts: InstallOptions,
+    version: Option<&ProductVersion>,
+    os: Option<BuildOs>,
+    label: Option<String>,
+    pool: &DownloadPool,
+) -> Result<InstallInfo, Error> {
+    let build_version = select_build(product, version, os.as_ref(), install_opts.include_disabled)?;
+    info!("Found game. Installing build version {}...", build_version);
+
+    let install_id = install_id(&product.slugged_name, label.as_deref());
+    info!("Fetching build manifest...");
+    let build_manifest = source.build_manifest(product, build_version).await?;
     store_build_manifest(
         &build_manifest,
         &build_version.version,
-        &product.slugged_name,
+        &install_id,
         "manifest",
     )
-    .await
-    .expect("Failed to save build manifest");
-
-    if install_opts.info {
-        let mut build_manifest_rdr = csv::Reader::from_reader(&build_manifest[..]);
-        let download_size = build_manifest_rdr
-            .byte_records()
-            .map(|r| {
-                let mut record = r.expect("Failed to get byte record");
-                record.push_field(b"");
-                record.deserialize::<BuildManifestRecord>(None)
-            })
-            .fold(0f64, |acc, record| match record {
-                Ok(record) => acc + record.size_in_bytes as f64,
-                Err(_) => acc,
-            });
+    .await?;
 
-        let mut buf = String::new();
-        buf.push_str(&format!("Download Size: {}", human_bytes(download_size)));
-        buf.push_str(&format!("\nDisk Size: {}", human_bytes(download_size)));
-        return Ok(Ok((buf, None)));
+    if !install_opts.force {
+        check_space(install_path, build_manifest_size(&build_manifest[..])?)?;
     }
 
-    println!("Fetching build manifest chunks...");
-    let build_manifest_chunks =
-        api::product::get_build_manifest_chunks(&client, product, build_version).await?;
+    info!("Fetching build manifest chunks...");
+    let build_manifest_chunks = source.build_manifest_chunks(product, build_version).await?;
     store_build_manifest(
         &build_manifest_chunks,
         &build_version.version,
-        &product.slugged_name,
+        &install_id,
         "manifest_chunks",
     )
-    .await
-    .expect("Failed to save build manifest chunks");
+    .await?;
 
     let product_arc = Arc::new(product.clone());
     let os_arc = Arc::new(build_version.os.to_owned());
 
-    println!("Installing game from manifest...");
+    info!("Installing game from manifest...");
     let result = build_from_manifest(
-        client,
+        source,
         product_arc,
+        &install_id,
         os_arc,
         &build_manifest[..],
         &build_manifest_chunks[..],
-        install_path.into(),
+        None,
+        OsPath::from(install_path.to_path_buf()),
         install_opts,
+        pool,
     )
-    .await
-    .expect("Failed to build from manifest");
-
-    match result {
-        true => {
-            let install_info = InstallInfo::new(
-                install_path.to_owned(),
-                build_version.version.to_owned(),
-                build_version.os.to_owned(),
+    .await?;
+    if !result {
+        return Err(Error::ChunksFailed);
+    }
+
+    Ok(InstallInfo {
+        label,
+        ..InstallInfo::new(
+            install_path.to_owned(),
+            build_version.version.to_owned(),
+            build_version.os.to_owned(),
+        )
+    })
+}
+
+enum QueueOutcome {
+    Installed(InstallInfo),
+    Paused,
+    Failed(String),
+}
+
+/// Installs every queued game in order of priority, running up to `max_parallel_installs`
+/// installs at the same time. Finished games are removed from the queue and added to the
+/// installed config as soon as they're done, so an interrupted queue can be resumed later.
+/// Fails if the queue or installed config can't be read or saved.
+pub(crate) async fn process_queue(
+    client: ApiClient,
+    install_opts: InstallOptions,
+    max_parallel_installs: usize,
+    pool: &DownloadPool,
+) -> Result<(), Error> {
+    let mut started = HashSet::new();
+    let mut running = FuturesUnordered::new();
+    // Downloads can be paused or removed by another process, which only changes the queue
+    // config, so it's checked for changes while games are installing
+    let mut queue_modified = queue_modified_time();
+    let mut check_queue = tokio::time::interval(std::time::Duration::from_secs(2));
+
+    loop {
+        while running.len() < max_parallel_installs.max(1) {
+            let queue = QueueConfig::load()?;
+            let next = queue
+                .ordered()
+                .into_iter()
+                .find(|d| d.status == QueueStatus::Queued && !started.contains(&d.id()))
+                .cloned();
+            match next {
+                Some(download) => {
+                    started.insert(download.id());
+                    let (client, install_opts) = (client.clone(), install_opts.clone());
+                    running.push(async move {
+                        let outcome = install_queued(client, &download, install_opts, pool).await;
+                        (download, outcome)
+                    });
+                }
+                None => break,
+            }
+        }
+
+        let (download, outcome) = tokio::select! {
+            result = running.next() => match result {
+                Some(result) => result,
+                None => break,
+            },
+            _ = check_queue.tick() => {
+                let modified = queue_modified_time();
+                if modified != queue_modified {
+                    queue_modified = modified;
+                    let queue = QueueConfig::load()?;
+                    for install_id in &started {
+                        match queue.get(install_id) {
+                            Some(download) if download.status == QueueStatus::Queued => {}
+                            _ => {
+                                pool.cancel(install_id);
+                            }
+                        }
+                    }
+                }
+                continue;
+            }
+        };
+        let install_id = download.id();
+        let _queue_guard = pool.queue_lock.lock().await;
+        let mut queue = QueueConfig::load()?;
+        match outcome {
+            QueueOutcome::Installed(install_info) => {
+                pool.report(Progress::Installed {
+                    install_id: install_id.clone(),
+                    version: install_info.version.clone(),
+                });
+                info!(
+                    "Successfully installed {} ({})",
+                    install_id, install_info.version
+                );
+                queue.remove(&install_id);
+
+                let mut installed = InstalledConfig::load()?;
+                installed.insert(download.slug, install_info);
+                installed.store()?;
+            }
+            QueueOutcome::Paused => {
+                info!("Stopped downloading {install_id}.");
+                pool.report(Progress::Paused {
+                    install_id: install_id.clone(),
+                });
+            }
+            QueueOutcome::Failed(err) => {
+                warn!("Failed to install {install_id}: {err}");
+                pool.report(Progress::Failed {
+                    install_id: install_id.clone(),
+                    error: err,
+                });
+                if let Some(download) = queue.get_mut(&install_id) {
+                    download.status = QueueStatus::Failed;
+                }
+            }
+        }
+        queue.store()?;
+    }
+
+    let queue = QueueConfig::load()?;
+    if !queue.downloads.is_empty() {
+        info!(
+            "{} download(s) left in the queue. Run `queue resume` to retry them.",
+            queue.downloads.len()
+        );
+    }
+
+    Ok(())
+}
+
+async fn install_queued(
+    client: ApiClient,
+    download: &QueuedInstall,
+    install_opts: InstallOptions,
+    pool: &DownloadPool,
+) -> QueueOutcome {
+    let (source, products) = match &download.archive {
+        Some(archive_path) => match ArchiveIndex::load(archive_path) {
+            Ok(index) => (
+                BuildSource::Archive(archive_path.to_owned()),
+                index.products,
+            ),
+            Err(err) => {
+                return QueueOutcome::Failed(format!("Failed to read archive: {:?}", err));
+            }
+        },
+        None => match LibraryConfig::load() {
+            Ok(library) => (BuildSource::Cdn(client), library.collection),
+            Err(err) => {
+                return QueueOutcome::Failed(format!("Failed to load library: {}", err));
+            }
+        },
+    };
+    let product = match products.iter().find(|p| p.slugged_name == download.slug) {
+        Some(product) => product,
+        None => {
+            return QueueOutcome::Failed(match download.archive {
+                Some(_) => "Could not find game in archive".to_owned(),
+                None => "Could not find game in library".to_owned(),
+            });
+        }
+    };
+    let selected_version = match &download.version {
+        Some(version) => match product.find_version(version, download.os.as_ref()) {
+            Some(version) => Some(version),
+            None => {
+                return QueueOutcome::Failed(format!("Can't find build {version}"));
+            }
+        },
+        None => None,
+    };
+
+    let install_opts = InstallOptions {
+        skip_verify: download.skip_verify,
+        include_disabled: download.include_disabled || install_opts.include_disabled,
+        force: download.force || install_opts.force,
+        preallocate: download.preallocate || install_opts.preallocate,
+        ..install_opts
+    };
+    let install_id = download.id();
+    let running = pool.start(&install_id);
+    tokio::select! {
+        // A paused install may fail before noticing, so pausing is checked first
+        biased;
+        _ = running.token().cancelled() => QueueOutcome::Paused,
+        result = install(
+            source,
+            product,
+            &download.install_path,
+            install_opts,
+            selected_version,
+            download.os.clone(),
+            download.label.clone(),
+            pool,
+        ) => match result {
+            Ok(install_info) => QueueOutcome::Installed(install_info),
+            Err(err) => QueueOutcome::Failed(err.to_string()),
+        },
+    }
+}
+
+/// When the download queue config was last changed, if it can be checked
+fn queue_modified_time() -> Option<std::time::SystemTime> {
+    QueueConfig::path()
+        .ok()
+        .and_then(|path| std::fs::metadata(path).ok())
+        .and_then(|metadata| metadata.modified().ok())
+}
+
+/// Fails if `path` doesn't have `needed_space` bytes free. Continues anyway if free space
+/// can't be checked.
+fn check_space(path: &Path, needed_space: u64) -> Result<(), Error> {
+    match available_space(path) {
+        Ok(free_space) if free_space < needed_space => Err(Error::NotEnoughSpace {
+            needed: needed_space,
+            available: free_space,
+        }),
+        Ok(_) => Ok(()),
+        Err(_) => {
+            warn!(
+                "Couldn't check free space for {}. Continuing anyway...",
+                path.display()
             );
-            Ok(Ok((
-                format!("Successfully installed {} ({})", slug, build_version),
-                Some(install_info),
-            )))
+            Ok(())
         }
-        false => Ok(Err(
-            "Some chunks failed verification. Failed to install game.",
+    }
+}
+
+pub(crate) async fn download_size(
+    client: &ApiClient,
+    product: &Product,
+    archive_path: &Path,
+    version: Option<&ProductVersion>,
+    os: Option<BuildOs>,
+    include_disabled: bool,
+) -> Result<InstallSize, Error> {
+    let build_version = select_build(product, version, os.as_ref(), include_disabled)?;
+    info!("Found game. Downloading build version {}...", build_version);
+
+    info!("Fetching build manifest...");
+    let build_manifest = api::product::get_build_manifest(client, product, build_version).await?;
+    let download_size = build_manifest_size(&build_manifest[..])?;
+
+    Ok(InstallSize {
+        download_size,
+        disk_size: download_size,
+        free_space: available_space(archive_path).ok(),
+    })
+}
+
+/// Downloads a build into a local archive that can be installed from later without an internet
+/// connection
+pub(crate) async fn download(
+    client: ApiClient,
+    product: &Product,
+    archive_path: &Path,
+    install_opts: InstallOptions,
+    version: Option<&ProductVersion>,
+    os: Option<BuildOs>,
+    pool: &DownloadPool,
+) -> Result<(), Error> {
+    let build_version = select_build(product, version, os.as_ref(), install_opts.include_disabled)?;
+    info!("Found game. Downloading build version {}...", build_version);
+
+    info!("Fetching build manifest...");
+    let build_manifest = api::product::get_build_manifest(&client, product, build_version).await?;
+    if !install_opts.force {
+        check_space(archive_path, build_manifest_size(&build_manifest[..])?)?;
+    }
+
+    info!("Fetching build manifest chunks...");
+    let build_manifest_chunks =
+        api::product::get_build_manifest_chunks(&client, product, build_version).await?;
+
+    let build_dir = archive::build_dir(archive_path, product, &build_version.os);
+    tokio::fs::create_dir_all(&build_dir).await?;
+    tokio::fs::write(
+        build_dir.join(api::product::manifest_file_name(build_version, "manifest")),
+        &build_manifest,
+    )
+    .await?;
+    tokio::fs::write(
+        build_dir.join(api::product::manifest_file_name(
+            build_version,
+            "manifest_chunks",
         )),
+        &build_manifest_chunks,
+    )
+    .await?;
+
+    info!("Downloading chunks...");
+    let result = archive_chunks(
+        client,
+        Arc::new(product.clone()),
+        &product.slugged_name,
+        Arc::new(build_version.os.to_owned()),
+        &build_manifest[..],
+        &build_manifest_chunks[..],
+        &build_dir,
+        install_opts,
+        pool,
+    )
+    .await?;
+    if !result {
+        return Err(Error::ChunksFailed);
     }
+
+    // Only list builds once they're complete, so they can't be installed half downloaded
+    let mut index = ArchiveIndex::load(archive_path)?;
+    index.insert(product, build_version);
+    index.store(archive_path)?;
+
+    Ok(())
 }
 
-pub(crate) async fn uninstall(install_path: &PathBuf) -> tokio::io::Result<()> {
+/// Checks how much space a build takes. The manifest stored when `install_info` was installed
+/// is reused for that build, the manifests of other builds are fetched.
+pub(crate) async fn build_size(
+    source: BuildSource,
+    product: &Product,
+    build_version: &ProductVersion,
+    install_info: Option<&InstallInfo>,
+) -> Result<InstallSize, Error> {
+    let stored_manifest = match install_info {
+        Some(info) if info.version == build_version.version && info.os == build_version.os => {
+            read_build_manifest(
+                &build_version.version,
+                &info.id(&product.slugged_name),
+                "manifest",
+            )
+            .await
+            .ok()
+        }
+        _ => None,
+    };
+    let build_manifest = match stored_manifest {
+        Some(manifest) => manifest,
+        None => source
+            .build_manifest(product, build_version)
+            .await?
+            .to_vec(),
+    };
+    let size = build_manifest_size(&build_manifest[..])?;
+
+    Ok(InstallSize {
+        download_size: size,
+        disk_size: size,
+        free_space: None,
+    })
+}
+
+/// Sums the size of every file under `path`
+#[async_recursion]
+pub async fn disk_usage(path: &Path) -> tokio::io::Result<u64> {
+    let mut size = 0;
+    let mut entries = tokio::fs::read_dir(path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        size += if metadata.is_dir() {
+            disk_usage(&entry.path()).await?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+/// Removes the install directory of a game
+pub async fn uninstall(install_path: &PathBuf) -> tokio::io::Result<()> {
     tokio::fs::remove_dir_all(install_path).await
 }
 
-pub(crate) async fn check_updates(
+/// Deletes the stored manifests of builds that aren't installed anymore, and every delta
+/// manifest, which are generated again when they're needed. Manifests of queued installs are
+/// kept, as they may be downloading. Returns how many bytes were freed.
+pub async fn prune_manifests(installed: &InstalledConfig) -> tokio::io::Result<u64> {
+    let mut entries = match tokio::fs::read_dir(manifests_dir()).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+    let queue = QueueConfig::load().unwrap_or_default();
+
+    let mut freed = 0;
+    while let Some(entry) = entries.next_entry().await? {
+        let install_id = entry.file_name().to_string_lossy().into_owned();
+        if queue.get(&install_id).is_none() {
+            freed += prune_install_manifests(installed, &install_id).await?;
+        }
+    }
+
+    Ok(freed)
+}
+
+/// Deletes the stored manifests of the install `install_id` that don't belong to its installed
+/// build, e.g. after it was updated or uninstalled. Other installs aren't touched, so they can
+/// be installed or updated at the same time. Returns how many bytes were freed.
+pub async fn prune_install_manifests(
+    installed: &InstalledConfig,
+    install_id: &str,
+) -> tokio::io::Result<u64> {
+    let path = manifests_dir().join(install_id);
+    let metadata = match tokio::fs::metadata(&path).await {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+    let info = match installed
+        .iter()
+        .find(|(slug, info)| info.id(slug) == install_id)
+    {
+        Some((_, info)) if metadata.is_dir() => info,
+        _ => return remove_path(&path).await,
+    };
+
+    let keep = [
+        format!("{}_manifest.csv", info.version),
+        format!("{}_manifest_chunks.csv", info.version),
+    ];
+    let mut freed = 0;
+    let mut manifests = tokio::fs::read_dir(&path).await?;
+    while let Some(manifest) = manifests.next_entry().await? {
+        if !keep
+            .iter()
+            .any(|name| manifest.file_name() == name.as_str())
+        {
+            freed += remove_path(&manifest.path()).await?;
+        }
+    }
+
+    Ok(freed)
+}
+
+/// Removes a file or directory, returning its size
+async fn remove_path(path: &Path) -> tokio::io::Result<u64> {
+    let metadata = tokio::fs::metadata(path).await?;
+    if metadata.is_dir() {
+        let size = disk_usage(path).await?;
+        tokio::fs::remove_dir_all(path).await?;
+        Ok(size)
+    } else {
+        tokio::fs::remove_file(path).await?;
+        Ok(metadata.len())
+    }
+}
+
+/// Reads the build manifest stored when a game was installed. If it's missing, it's fetched
+/// again with `refetch` and stored.
+async fn read_installed_manifest(
+    slug: &str,
+    install_info: &InstallInfo,
+    refetch: Option<(&ApiClient, &Product)>,
+) -> Result<Vec<u8>, Error> {
+    let install_id = install_info.id(slug);
+    let err = match read_build_manifest(&install_info.version, &install_id, "manifest").await {
+        Ok(manifest) => return Ok(manifest),
+        Err(err) => err,
+    };
+    let (client, product) = match refetch {
+        Some(refetch) if err.kind() == std::io::ErrorKind::NotFound => refetch,
+        _ => return Err(err.into()),
+    };
+
+    warn!(
+        "The {} build manifest of {install_id} is missing. Fetching it again...",
+        install_info.version
+    );
+    let version = product
+        .find_version(&install_info.version, Some(&install_info.os))
+        .ok_or(Error::BuildNotFound)?;
+    let manifest = api::product::get_build_manifest(client, product, version).await?;
+    store_build_manifest(&manifest, &version.version, &install_id, "manifest").await?;
+
+    Ok(manifest.to_vec())
+}
+
+/// A newer build of an installed game
+#[derive(Debug, Clone)]
+pub struct AvailableUpdate {
+    /// Identifies the install, see [`install_id`]
+    pub install_id: String,
+    pub version: String,
+    /// Why the game won't be updated to this build, if it's held
+    pub held: Option<String>,
+}
+
+/// Finds installed games that have a newer build in the library, sorted by install id. Held
+/// games are listed with the latest build they can be updated to, and with the newer build
+/// they're held back from.
+pub async fn check_updates(
     library: LibraryConfig,
     installed: InstalledConfig,
-) -> tokio::io::Result<HashMap<String, String>> {
-    let mut available_updates = HashMap::new();
-    for (slug, info) in installed {
-        println!("Checking if {slug} has updates...");
-        let product = match library.collection.iter().find(|p| p.slugged_name == slug) {
+) -> tokio::io::Result<Vec<AvailableUpdate>> {
+    let mut available_updates = vec![];
+    for (slug, info) in installed.iter() {
+        info!("Checking if {slug} has updates...");
+        let product = match library.collection.iter().find(|p| p.slugged_name == *slug) {
             Some(p) => p,
             None => {
-                println!("Couldn't find {slug} in library. Try running `sync` first.");
-                continue;
-            }
-        };
-        let latest_version = match product.get_latest_version(Some(&info.os)) {
-            Some(v) => v,
-            None => {
-                println!("Couldn't find the latest version of {slug}");
+                warn!("Couldn't find {slug} in library. Try running `sync` first.");
                 continue;
             }
         };
+        if product.get_latest_version(Some(&info.os)).is_none() {
+            warn!("Couldn't find the latest version of {slug}");
+            continue;
+        }
 
-        if info.version != latest_version.version {
-            available_updates.insert(slug, latest_version.version.to_owned());
+        if let Some(latest_version) = product.update_for(info) {
+            available_updates.push(AvailableUpdate {
+                install_id: info.id(slug),
+                version: latest_version.version.to_owned(),
+                held: None,
+            });
+        }
+        if let (Some(held_version), Some(latest_version)) = (&info.hold, product.held_update(info))
+        {
+            available_updates.push(AvailableUpdate {
+                install_id: info.id(slug),
+                version: latest_version.version.to_owned(),
+                held: Some(format!("held at {held_version}")),
+            });
         }
     }
+    available_updates.sort_by(|a, b| a.install_id.cmp(&b.install_id));
     Ok(available_updates)
 }
 
-pub(crate) async fn update(
-    client: reqwest::Client,
-    library: &LibraryConfig,
-    slug: &String,
-    install_opts: InstallOpts,
+/// Manifests needed to update an installed game to another build
+struct UpdateManifests<'a> {
+    version: &'a ProductVersion,
+    old_manifest: Vec<u8>,
+    new_manifest: Vec<u8>,
+    delta_manifest: Vec<u8>,
+    delta_manifest_chunks: Vec<u8>,
+}
+
+async fn fetch_update_manifests<'a>(
+    client: &ApiClient,
+    product: &'a Product,
     install_info: &InstallInfo,
-    selected_version: Option<&ProductVersion>,
-) -> tokio::io::Result<(String, Option<InstallInfo>)> {
-    let product = match library.collection.iter().find(|p| &p.slugged_name == slug) {
-        Some(p) => p,
-        None => {
-            return Ok((format!("Couldn't find {slug} in library"), None));
-        }
-    };
+    selected_version: Option<&'a ProductVersion>,
+    include_disabled: bool,
+) -> Result<UpdateManifests<'a>, Error> {
+    let install_id = install_info.id(&product.slugged_name);
+    if selected_version.is_none() {
+        info!("Fetching latest version...");
+    }
     let version = match selected_version {
-        Some(v) => v,
-        None => {
-            println!("Fetching latest version...");
-            match product.get_latest_version(Some(&install_info.os)) {
-                Some(v) => v,
-                None => {
-                    return Ok((format!("Couldn't find the latest version of {slug}"), None));
-                }
+        Some(_) => select_build(
+            product,
+            selected_version,
+            Some(&install_info.os),
+            include_disabled,
+        )?,
+        // Only builds newer than the installed one are picked, like `list-updates` reports
+        // them, so games are never downgraded. Held games are only updated up to the version
+        // they're held at.
+        None => match (product.update_for(install_info), &install_info.hold) {
+            (Some(version), _) => version,
+            (None, Some(held_version)) if product.held_update(install_info).is_some() => {
+                return Err(Error::UpdateHeld(held_version.to_owned()));
             }
-        }
+            (None, _) => return Err(Error::AlreadyInstalled(install_info.version.to_owned())),
+        },
     };
 
     if install_info.version == version.version {
-        return Ok((format!("Build {version} is already installed"), None));
+        return Err(Error::AlreadyInstalled(version.version.to_owned()));
     }
 
-    let old_manifest = read_build_manifest(&install_info.version, slug, "manifest").await?;
+    let old_manifest =
+        read_installed_manifest(&product.slugged_name, install_info, Some((client, product)))
+            .await?;
 
-    println!("Fetching {} build manifest...", version);
-    let new_manifest = match api::product::get_build_manifest(&client, product, version).await {
-        Ok(m) => m,
-        Err(err) => {
-            return Ok((format!("Failed to fetch build manifest: {:?}", err), None));
-        }
-    };
-    store_build_manifest(&new_manifest, &version.version, slug, "manifest").await?;
+    info!("Fetching {} build manifest...", version);
+    let new_manifest = api::product::get_build_manifest(client, product, version).await?;
+    store_build_manifest(&new_manifest, &version.version, &install_id, "manifest").await?;
     let new_manifest_chunks =
-        match api::product::get_build_manifest_chunks(&client, product, version).await {
-            Ok(m) => m,
-            Err(err) => {
-                return Ok((
-                    format!("Failed to fetch build manifest chunks: {:?}", err),
-                    None,
-                ));
-            }
-        };
+        api::product::get_build_manifest_chunks(client, product, version).await?;
     store_build_manifest(
         &new_manifest_chunks,
         &version.version,
-        slug,
+        &install_id,
         "manifest_chunks",
     )
     .await?;
 
     let delta_manifest = read_or_generate_delta_manifest(
-        slug,
+        &install_id,
         &old_manifest[..],
         &new_manifest[..],
         &install_info.version,
@@ -228,7 +766,7 @@ pub(crate) async fn update(
     )
     .await?;
     let delta_manifest_chunks = read_or_generate_delta_chunks_manifest(
-        slug,
+        &install_id,
         &delta_manifest[..],
         &new_manifest_chunks[..],
         &install_info.version,
@@ -236,84 +774,175 @@ pub(crate) async fn update(
     )
     .await?;
 
-    if install_opts.info {
-        let mut delta_build_manifest_rdr = csv::Reader::from_reader(&delta_manifest[..]);
-        let download_size = delta_build_manifest_rdr
-            .byte_records()
-            .map(|r| {
-                r.expect("Failed to get byte record")
-                    .deserialize::<BuildManifestRecord>(None)
-            })
-            .fold(0f64, |acc, record| match record {
-                Ok(record) => match record.tag {
-                    Some(ChangeTag::Removed) => acc,
-                    _ => acc + record.size_in_bytes as f64,
-                },
-                Err(_) => acc,
-            });
-        let mut new_build_manifest_rdr = csv::Reader::from_reader(&new_manifest[..]);
-        let disk_size = new_build_manifest_rdr
-            .byte_records()
-            .map(|r| {
-                let mut record = r.expect("Failed to get byte record");
-                record.push_field(b"");
-                record.deserialize::<BuildManifestRecord>(None)
-            })
-            .fold(0f64, |acc, record| match record {
-                Ok(record) => acc + record.size_in_bytes as f64,
-                Err(_) => acc,
-            });
+    Ok(UpdateManifests {
+        version,
+        old_manifest,
+        new_manifest: new_manifest.to_vec(),
+        delta_manifest,
+        delta_manifest_chunks,
+    })
+}
 
-        let mut old_manifest_rdr = csv::Reader::from_reader(&old_manifest[..]);
-        let old_disk_size = old_manifest_rdr
-            .byte_records()
-            .map(|r| {
-                let mut record = r.expect("Failed to get byte record");
-                record.push_field(b"");
-                record.deserialize::<BuildManifestRecord>(None)
-            })
-            .fold(0f64, |acc, record| match record {
-                Ok(record) => acc + record.size_in_bytes as f64,
-                Err(_) => acc,
-            });
+pub(crate) async fn update_size(
+    client: &ApiClient,
+    product: &Product,
+    install_info: &InstallInfo,
+    selected_version: Option<&ProductVersion>,
+    include_disabled: bool,
+) -> Result<UpdateSize, Error> {
+    let manifests = fetch_update_manifests(
+        client,
+        product,
+        install_info,
+        selected_version,
+        include_disabled,
+    )
+    .await?;
 
-        let needed_space = disk_size - old_disk_size;
-        println!("{}", needed_space);
-
-        let mut buf = String::new();
-        buf.push_str(&format!("Download Size: {}", human_bytes(download_size)));
-        buf.push_str(&format!(
-            "\nNeeded Space: {}{}",
-            if needed_space < 0f64 { "-" } else { "" },
-            human_bytes(needed_space.abs())
-        ));
-        buf.push_str(&format!("\nTotal Disk Size: {}", human_bytes(disk_size)));
-        return Ok((buf, None));
+    let mut download_size = 0u64;
+    for record in BuildManifest::new(&manifests.delta_manifest[..])? {
+        let record = record?;
+        if record.tag != Some(ChangeTag::Removed) && record.copy_source.is_none() {
+            download_size += record.size_in_bytes as u64;
+        }
+    }
+    let disk_size = build_manifest_size(&manifests.new_manifest[..])?;
+    let old_disk_size = build_manifest_size(&manifests.old_manifest[..])?;
+
+    Ok(UpdateSize {
+        download_size,
+        disk_size,
+        needed_space: disk_size as i64 - old_disk_size as i64,
+    })
+}
+
+/// Compares the files of two builds of a game, without touching its installs. Deltas are kept
+/// per OS, apart from the deltas of installs, which could be for builds of another OS with the
+/// same versions.
+pub(crate) async fn diff_builds(
+    source: BuildSource,
+    product: &Product,
+    from: &ProductVersion,
+    to: &ProductVersion,
+) -> Result<BuildDiff, Error> {
+    info!(
+        "Fetching {} and {} build manifests...",
+        from.version, to.version
+    );
+    let old_manifest = source.build_manifest(product, from).await?;
+    let new_manifest = source.build_manifest(product, to).await?;
+    let os = if from.os == to.os {
+        from.os.to_string()
+    } else {
+        format!("{}-{}", from.os, to.os)
+    };
+    let delta_manifest = read_or_generate_delta_manifest(
+        &format!("{}/{}", product.slugged_name, os),
+        &old_manifest[..],
+        &new_manifest[..],
+        &from.version,
+        &to.version,
+    )
+    .await?;
+
+    let mut diff = BuildDiff {
+        added: vec![],
+        modified: vec![],
+        removed: vec![],
+        download_size: 0,
+        old_size: build_manifest_size(&old_manifest[..])?,
+        new_size: build_manifest_size(&new_manifest[..])?,
+    };
+    for record in BuildManifest::new(&delta_manifest[..])? {
+        let record = record?;
+        if record.is_directory() {
+            continue;
+        }
+
+        let file = ChangedFile {
+            path: record.file_name,
+            size: record.size_in_bytes as u64,
+            copied_from: record.copy_source,
+        };
+        if record.tag != Some(ChangeTag::Removed) && file.copied_from.is_none() {
+            diff.download_size += file.size;
+        }
+        match record.tag {
+            Some(ChangeTag::Added) => diff.added.push(file),
+            Some(ChangeTag::Modified) => diff.modified.push(file),
+            Some(ChangeTag::Removed) => diff.removed.push(file),
+            None => {}
+        }
+    }
+
+    Ok(diff)
+}
+
+pub(crate) async fn update(
+    client: ApiClient,
+    product: &Product,
+    install_opts: InstallOptions,
+    install_info: &InstallInfo,
+    selected_version: Option<&ProductVersion>,
+    pool: &DownloadPool,
+) -> Result<InstallInfo, Error> {
+    let UpdateManifests {
+        version,
+        old_manifest,
+        new_manifest,
+        delta_manifest,
+        delta_manifest_chunks,
+    } = fetch_update_manifests(
+        &client,
+        product,
+        install_info,
+        selected_version,
+        install_opts.include_disabled,
+    )
+    .await?;
+
+    if !install_opts.force {
+        // Files copied from the installed build are copied next to it first. Modified and
+        // removed files are then deleted before anything is written, so after that we only need
+        // room for the difference between the two builds.
+        let needed_space = build_manifest_size(&new_manifest[..])?
+            .saturating_sub(build_manifest_size(&old_manifest[..])?)
+            .max(copied_files_size(&delta_manifest[..])?);
+        check_space(&install_info.install_path, needed_space)?;
     }
 
     let product_arc = Arc::new(product.clone());
     let version_arc = Arc::new(version.os.to_owned());
-    build_from_manifest(
-        client,
+    let result = build_from_manifest(
+        BuildSource::Cdn(client),
         product_arc,
+        &install_info.id(&product.slugged_name),
         version_arc,
         &delta_manifest[..],
         &delta_manifest_chunks[..],
+        Some(&old_manifest[..]),
         OsPath::from(&install_info.install_path),
         install_opts,
+        pool,
     )
     .await?;
+    if !result {
+        return Err(Error::ChunksFailed);
+    }
 
-    let install_info = InstallInfo::new(
-        install_info.install_path.to_owned(),
-        version.version.to_owned(),
-        version.os.to_owned(),
-    );
-    Ok((format!("Updated {slug} successfully."), Some(install_info)))
+    Ok(InstallInfo {
+        label: install_info.label.clone(),
+        hold: install_info.hold.clone(),
+        ..InstallInfo::new(
+            install_info.install_path.to_owned(),
+            version.version.to_owned(),
+            version.os.to_owned(),
+        )
+    })
 }
 
 pub(crate) async fn launch(
-    client: &reqwest::Client,
+    client: &ApiClient,
     product: &Product,
     install_info: &InstallInfo,
     #[cfg(not(target_os = "windows"))] wine_bin: Option<PathBuf>,
@@ -326,7 +955,7 @@ pub(crate) async fn launch(
         BuildOs::Windows => match wine_bin {
             Some(wine_bin) => Some(wine_bin),
             None => {
-                println!("You need to set --wine-bin to run Windows games");
+                warn!("You need to set --wine-bin to run Windows games");
                 return Ok(None);
             }
         },
@@ -336,7 +965,7 @@ pub(crate) async fn launch(
     let game_details = match api::product::get_game_details(client, product).await {
         Ok(details) => details,
         Err(err) => {
-            println!("Failed to fetch game details. Launch might fail: {:?}", err);
+            warn!("Failed to fetch game details. Launch might fail: {:?}", err);
 
             None
         }
@@ -344,6 +973,12 @@ pub(crate) async fn launch(
 
     let exe_path = match game_details {
         Some(details) => match details.exe_path {
+            // The exe path is usually set for the Windows build only
+            Some(exe_path)
+                if *os != BuildOs::Windows && exe_path.to_lowercase().ends_with(".exe") =>
+            {
+                None
+            }
             Some(exe_path) => {
                 // Not too sure about this. At least syberia-ii prepends the slugged name to the
                 // path of the exe. I assume the galaClient always installs in folders with the
@@ -367,7 +1002,7 @@ pub(crate) async fn launch(
             BuildOs::Windows => match find_exe_recursive(&install_info.install_path).await {
                 Some(exe) => exe,
                 None => {
-                    println!("Couldn't find suitable exe...");
+                    warn!("Couldn't find suitable exe...");
                     return Ok(None);
                 }
             },
@@ -380,28 +1015,50 @@ pub(crate) async fn launch(
                     match mac_executables.executable() {
                         Some(exe) => exe,
                         None => {
-                            println!("Couldn't find executable in Info.plist...");
+                            warn!("Couldn't find executable in Info.plist...");
                             return Ok(None);
                         }
                     }
                 }
                 None => {
-                    println!("Couldn't find a suitable app...");
+                    warn!("Couldn't find a suitable app...");
                     return Ok(None);
                 }
             },
             #[cfg(not(target_os = "macos"))]
             BuildOs::Mac => {
-                println!("You can only launch macOS games on macOS");
+                warn!("You can only launch macOS games on macOS");
                 return Ok(None);
             }
+            #[cfg(target_os = "linux")]
+            BuildOs::Linux => match find_linux_exe(&install_info.install_path).await {
+                Some(exe) => exe,
+                None => {
+                    warn!("Couldn't find a suitable executable...");
+                    return Ok(None);
+                }
+            },
+            #[cfg(not(target_os = "linux"))]
             BuildOs::Linux => {
-                println!("We don't support launching Linux games yet...");
+                warn!("You can only launch Linux games on Linux");
                 return Ok(None);
             }
         },
     };
-    println!("{} was selected", exe.display());
+    info!("{} was selected", exe.display());
+
+    // Files are written without their permissions, so native executables need to be marked as
+    // executable before they can run
+    #[cfg(unix)]
+    if *os != BuildOs::Windows {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut permissions = tokio::fs::metadata(&exe).await?.permissions();
+        if permissions.mode() & 0o111 == 0 {
+            permissions.set_mode(permissions.mode() | 0o755);
+            tokio::fs::set_permissions(&exe, permissions).await?;
+        }
+    }
 
     #[cfg(not(target_os = "windows"))]
     let should_use_wine = os == &BuildOs::Windows;
@@ -440,27 +1097,27 @@ pub(crate) async fn launch(
     Ok(Some(status))
 }
 
-pub(crate) async fn verify(slug: &String, install_info: &InstallInfo) -> tokio::io::Result<bool> {
+pub(crate) async fn verify(
+    slug: &str,
+    install_info: &InstallInfo,
+    refetch: Option<(&ApiClient, &Product)>,
+) -> Result<bool, Error> {
     let mut handles: Vec<JoinHandle<bool>> = vec![];
 
-    let build_manifest = read_build_manifest(&install_info.version, slug, "manifest").await?;
-    let mut build_manifest_rdr = csv::Reader::from_reader(&build_manifest[..]);
-    let build_manifest_byte_records = build_manifest_rdr.byte_records();
-
-    for record in build_manifest_byte_records {
-        let mut record = record.expect("Failed to get byte record");
-        record.push_field(b"");
-        let record = record
-            .deserialize::<BuildManifestRecord>(None)
-            .expect("Failed to deserialize build manifest");
+    let build_manifest = read_installed_manifest(slug, install_info, refetch).await?;
+    let mut paths = InstallPaths::new(install_info.os != BuildOs::Linux);
+    for record in BuildManifest::new(&build_manifest[..])? {
+        let record = record?;
+        // Directories are resolved too, as later paths take their case
+        let path = paths.resolve(&record.file_name)?;
 
         if record.is_directory() {
             continue;
         }
 
-        let file_path = OsPath::from(install_info.install_path.join(&record.file_name));
+        let file_path = OsPath::from(install_info.install_path.join(path));
         if !tokio::fs::try_exists(&file_path).await? {
-            println!("{} is missing", record.file_name);
+            warn!("{} is missing", record.file_name);
             return Ok(false);
         }
 
@@ -468,7 +1125,7 @@ pub(crate) async fn verify(slug: &String, install_info: &InstallInfo) -> tokio::
             match verify_file_hash(&file_path, &record.sha) {
                 Ok(result) => result,
                 Err(err) => {
-                    println!("Failed to verify {}: {:?}", record.file_name, err);
+                    warn!("Failed to verify {}: {:?}", record.file_name, err);
 
                     false
                 }
@@ -478,7 +1135,7 @@ pub(crate) async fn verify(slug: &String, install_info: &InstallInfo) -> tokio::
 
     let mut result = true;
     for handle in handles {
-        if !handle.await? {
+        if !handle.await.map_err(std::io::Error::from)? {
             result = false;
             break;
         }
@@ -0,0 +1,40 @@
+use crate::{
+    api::ApiClient,
+    client::GalaClient,
+    error::Error,
+    shared::models::{api::Product, InstallInfo},
+    utils,
+};
+
+/// Checks the files of an installed game against the manifest of its build
+pub struct Verifier<'a> {
+    slug: &'a String,
+    install_info: &'a InstallInfo,
+    refetch: Option<(ApiClient, &'a Product)>,
+}
+
+impl<'a> Verifier<'a> {
+    pub fn new(slug: &'a String, install_info: &'a InstallInfo) -> Self {
+        Self {
+            slug,
+            install_info,
+            refetch: None,
+        }
+    }
+
+    /// Fetches the build manifest of `product` again if the one stored when the game was
+    /// installed is missing
+    pub fn refetch_manifest(mut self, client: &GalaClient, product: &'a Product) -> Self {
+        self.refetch = Some((client.api.clone(), product));
+        self
+    }
+
+    /// Returns `false` if any file is missing or corrupted
+    pub async fn verify(&self) -> Result<bool, Error> {
+        let refetch = self
+            .refetch
+            .as_ref()
+            .map(|(client, product)| (client, *product));
+        utils::verify(self.slug, self.install_info, refetch).await
+    }
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use common::{MockBuild, MockGame, TestEnv, EMAIL, PASSWORD};
+
+fn games() -> Vec<MockGame> {
+    vec![
+        MockGame::new(
+            "syberia-ii",
+            vec![MockBuild::new("1.0", 1, &[("game.exe", vec![1; 16])])],
+        ),
+        MockGame::new(
+            "blasphemous",
+            vec![MockBuild::new("2.1", 2, &[("game.exe", vec![2; 16])])],
+        ),
+    ]
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn login_syncs_library() {
+    let env = TestEnv::new(games()).await;
+
+    let output = env.run(&["login", EMAIL, PASSWORD]).await;
+    assert!(output.success, "{}", output.stdout);
+
+    let output = env.run(&["library"]).await;
+    assert!(output.stdout.contains("[syberia-ii]"), "{}", output.stdout);
+    assert!(output.stdout.contains("[blasphemous]"), "{}", output.stdout);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn login_with_wrong_password_fails() {
+    let env = TestEnv::new(games()).await;
+
+    let output = env.run(&["login", EMAIL, "wrong"]).await;
+    assert!(
+        output
+            .stdout
+            .contains("Login failed: Wrong email or password"),
+        "{}",
+        output.stdout
+    );
+
+    let output = env.run(&["library"]).await;
+    assert!(!output.stdout.contains("[syberia-ii]"), "{}", output.stdout);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn logout_clears_library() {
+    let env = TestEnv::logged_in(games()).await;
+
+    env.run(&["logout"]).await;
+
+    let output = env.run(&["library"]).await;
+    assert!(!output.stdout.contains("[syberia-ii]"), "{}", output.stdout);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn info_lists_available_versions() {
+    let env = TestEnv::logged_in(games()).await;
+
+    let output = env.run(&["info", "syberia-ii"]).await;
+    assert!(output.stdout.contains("[1.0]"), "{}", output.stdout);
+    assert!(
+        output.stdout.contains("Platform: Windows"),
+        "{}",
+        output.stdout
+    );
+}
@@ -0,0 +1,512 @@
+//! In-process mock of the IndieGala website, developer API and content CDN, serving synthetic
+//! games to the `freecarnival` binary.
+
+#![allow(dead_code)]
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    convert::Infallible,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::Output,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use sha2::{Digest, Sha256};
+use tempfile::TempDir;
+
+pub const EMAIL: &str = "player@example.com";
+pub const PASSWORD: &str = "hunter2";
+
+const SESSION_COOKIE: &str = "session=mock-session";
+/// Chunk size used by the real CDN. Every chunk but the last one of a file is this big.
+const CHUNK_SIZE: usize = 1048576;
+
+#[derive(Clone)]
+pub struct MockGame {
+    pub slug: String,
+    pub name: String,
+    pub namespace: String,
+    pub id_key_name: String,
+    pub exe_path: Option<String>,
+    pub builds: Vec<MockBuild>,
+}
+
+impl MockGame {
+    pub fn new(slug: &str, builds: Vec<MockBuild>) -> Self {
+        Self {
+            slug: slug.to_owned(),
+            name: slug.replace('-', " "),
+            namespace: format!("{}-dev", slug),
+            id_key_name: slug.replace('-', "_"),
+            exe_path: Some(format!("{}\\game.exe", slug)),
+            builds,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MockBuild {
+    pub version: String,
+    pub date: String,
+    pub os: String,
+    /// `enabled` and `status` fields reported by the library API
+    pub enabled: u8,
+    pub status: u16,
+    pub files: Vec<(String, Vec<u8>)>,
+    /// Chunks listed in the chunk manifest for paths that aren't files of the build manifest
+    pub stray_chunks: Vec<(String, Vec<u8>)>,
+    /// Files listed in the build manifest with their size but without chunks, e.g. to make a
+    /// build that doesn't fit on the disk
+    pub unchunked_files: Vec<(String, u64)>,
+}
+
+impl MockBuild {
+    /// Creates a Windows build. Later dates make newer builds.
+    pub fn new(version: &str, day: u32, files: &[(&str, Vec<u8>)]) -> Self {
+        Self {
+            version: version.to_owned(),
+            date: format!("2023-01-{:02}T12:00:00", day),
+            os: "win".to_owned(),
+            enabled: 1,
+            status: 1,
+            files: files
+                .iter()
+                .map(|(name, contents)| (name.to_string(), contents.clone()))
+                .collect(),
+            stray_chunks: vec![],
+            unchunked_files: vec![],
+        }
+    }
+}
+
+/// Deterministic file contents that don't repeat across files with different seeds
+pub fn contents(seed: u8, size: usize) -> Vec<u8> {
+    (0..size)
+        .map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed) ^ (i >> 8) as u8)
+        .collect()
+}
+
+/// Failures the server should simulate
+#[derive(Default, Clone)]
+pub struct Faults {
+    /// Serve chunks with a flipped byte
+    pub corrupt_chunks: bool,
+    /// Respond with 404 to every chunk request
+    pub missing_chunks: bool,
+    /// Respond with 404 to every manifest request
+    pub missing_manifests: bool,
+    /// Serve chunk manifests whose chunk SHAs point outside of the build
+    pub invalid_chunk_shas: bool,
+    /// Wait this long before responding to chunk requests
+    pub stall_chunks: Option<Duration>,
+    /// Wait this long before responding to requests for the first chunk of every file, so the
+    /// other chunks arrive before it
+    pub stall_first_chunks: Option<Duration>,
+}
+
+struct MockState {
+    games: Vec<MockGame>,
+    /// Manifests and chunks by path, relative to the root of the CDN
+    content: HashMap<String, Vec<u8>>,
+    faults: Mutex<Faults>,
+    chunk_requests: AtomicUsize,
+}
+
+pub struct MockServer {
+    pub url: String,
+    state: Arc<MockState>,
+}
+
+impl MockServer {
+    pub async fn start(games: Vec<MockGame>) -> Self {
+        let content = games.iter().flat_map(build_content).collect();
+        let state = Arc::new(MockState {
+            games,
+            content,
+            faults: Mutex::new(Faults::default()),
+            chunk_requests: AtomicUsize::new(0),
+        });
+
+        let service_state = state.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let state = state.clone();
+                    async move { Ok::<_, Infallible>(handle(&state, req).await) }
+                }))
+            }
+        });
+        let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let server = Server::bind(&addr).serve(make_service);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+
+        Self { url, state }
+    }
+
+    pub fn set_faults(&self, faults: Faults) {
+        *self.state.faults.lock().unwrap() = faults;
+    }
+
+    pub fn chunk_requests(&self) -> usize {
+        self.state.chunk_requests.load(Ordering::SeqCst)
+    }
+}
+
+/// Generates the manifests and chunks of every build of a game
+fn build_content(game: &MockGame) -> Vec<(String, Vec<u8>)> {
+    let mut content = vec![];
+    for build in &game.builds {
+        let build_path = format!(
+            "DevShowCaseSourceVolume/dev_fold_{}/{}/{}",
+            game.namespace, game.id_key_name, build.os
+        );
+        let mut manifest = String::from("Size in Bytes,Chunks,SHA,Flags,File Name\n");
+        let mut manifest_chunks = String::from("ID,Filepath,Chunk SHA\n");
+
+        // Directories have to be listed before the files in them
+        let directories: BTreeSet<String> = build
+            .files
+            .iter()
+            .flat_map(|(name, _)| {
+                let parts: Vec<&str> = name.split('/').collect();
+                (1..parts.len())
+                    .map(|i| parts[..i].join("/"))
+                    .collect::<Vec<String>>()
+            })
+            .collect();
+        for directory in directories {
+            manifest.push_str(&format!("0,0,,40,{}\n", directory));
+        }
+
+        for (name, data) in &build.files {
+            let chunks: Vec<&[u8]> = data.chunks(CHUNK_SIZE).collect();
+            manifest.push_str(&format!(
+                "{},{},{},0,{}\n",
+                data.len(),
+                chunks.len(),
+                sha256(data),
+                name
+            ));
+            for (id, chunk) in chunks.iter().enumerate() {
+                let chunk_sha = format!("{}_{}", id, sha256(chunk));
+                manifest_chunks.push_str(&format!("{},{},{}\n", id, name, chunk_sha));
+                content.push((format!("{}/{}", build_path, chunk_sha), chunk.to_vec()));
+            }
+        }
+        for (name, size) in &build.unchunked_files {
+            manifest.push_str(&format!("{},0,{:064},0,{}\n", size, 0, name));
+        }
+        for (name, chunk) in &build.stray_chunks {
+            let chunk_sha = format!("0_{}", sha256(chunk));
+            manifest_chunks.push_str(&format!("0,{},{}\n", name, chunk_sha));
+            content.push((format!("{}/{}", build_path, chunk_sha), chunk.clone()));
+        }
+
+        content.push((
+            format!("{}/{}_manifest.csv", build_path, build.version),
+            manifest.into_bytes(),
+        ));
+        content.push((
+            format!("{}/{}_manifest_chunks.csv", build_path, build.version),
+            manifest_chunks.into_bytes(),
+        ));
+    }
+
+    content
+}
+
+async fn handle(state: &MockState, req: Request<Body>) -> Response<Body> {
+    let path = req.uri().path().to_owned();
+    match (req.method(), path.as_str()) {
+        (&Method::POST, "/login_new/gcl") => login(req).await,
+        (&Method::GET, "/login_new/user_info") => user_info(state, &req),
+        (&Method::GET, "/get_product_info") => product_info(state, &req),
+        (&Method::GET, path) => content(state, path.trim_start_matches('/')).await,
+        _ => status(StatusCode::NOT_FOUND),
+    }
+}
+
+async fn login(req: Request<Body>) -> Response<Body> {
+    let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+    let form: HashMap<String, String> = url::form_urlencoded::parse(&body).into_owned().collect();
+
+    let logged_in = form.get("usre").map(String::as_str) == Some(EMAIL)
+        && form.get("usrp").map(String::as_str) == Some(PASSWORD);
+    let res = Response::builder().header(header::CONTENT_TYPE, "application/json");
+    if !logged_in {
+        return res
+            .body(Body::from(
+                r#"{"status":"error","message":"Wrong email or password"}"#,
+            ))
+            .unwrap();
+    }
+
+    res.header(
+        header::SET_COOKIE,
+        format!("{}; Path=/; Max-Age=86400", SESSION_COOKIE),
+    )
+    .body(Body::from(r#"{"status":"success","message":"Logged in"}"#))
+    .unwrap()
+}
+
+fn user_info(state: &MockState, req: &Request<Body>) -> Response<Body> {
+    let logged_in = req
+        .headers()
+        .get_all(header::COOKIE)
+        .iter()
+        .any(|cookie| cookie.to_str().unwrap_or_default().contains(SESSION_COOKIE));
+    if !logged_in {
+        return json(serde_json::json!({ "status": "success", "user_found": "false" }));
+    }
+
+    let collection: Vec<serde_json::Value> = state
+        .games
+        .iter()
+        .enumerate()
+        .map(|(id, game)| {
+            serde_json::json!({
+                "prod_dev_namespace": game.namespace,
+                "prod_slugged_name": game.slug,
+                "id": id,
+                "prod_name": game.name,
+                "prod_id_key_name": game.id_key_name,
+                "version": game.builds.iter().map(|build| serde_json::json!({
+                    "status": build.status,
+                    "enabled": build.enabled,
+                    "version": build.version,
+                    "os": build.os,
+                    "date": build.date,
+                    "text": "",
+                })).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+
+    json(serde_json::json!({
+        "status": "success",
+        "user_found": "true",
+        "_indiegala_user_email": EMAIL,
+        "_indiegala_username": "player",
+        "_indiegala_user_id": 1,
+        "showcase_content": { "content": { "user_collection": collection } },
+    }))
+}
+
+fn product_info(state: &MockState, req: &Request<Body>) -> Response<Body> {
+    let query: HashMap<String, String> =
+        url::form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+            .into_owned()
+            .collect();
+    let game = state.games.iter().find(|game| {
+        query.get("prod_name") == Some(&game.slug) && query.get("dev_id") == Some(&game.namespace)
+    });
+
+    match game {
+        Some(game) => json(serde_json::json!({
+            "status": "success",
+            "message": "",
+            "product_data": { "exe_path": game.exe_path, "args": null, "cwd": null },
+        })),
+        None => json(serde_json::json!({
+            "status": "error",
+            "message": "Product not found",
+            "product_data": { "exe_path": null, "args": null, "cwd": null },
+        })),
+    }
+}
+
+async fn content(state: &MockState, path: &str) -> Response<Body> {
+    let faults = state.faults.lock().unwrap().clone();
+    let is_manifest = path.ends_with(".csv");
+
+    if !is_manifest {
+        state.chunk_requests.fetch_add(1, Ordering::SeqCst);
+        if let Some(stall) = faults.stall_chunks {
+            tokio::time::sleep(stall).await;
+        }
+        if let Some(stall) = faults.stall_first_chunks {
+            if path
+                .rsplit('/')
+                .next()
+                .is_some_and(|sha| sha.starts_with("0_"))
+            {
+                tokio::time::sleep(stall).await;
+            }
+        }
+    }
+    if (is_manifest && faults.missing_manifests) || (!is_manifest && faults.missing_chunks) {
+        return status(StatusCode::NOT_FOUND);
+    }
+
+    let mut data = match state.content.get(path) {
+        Some(data) => data.clone(),
+        None => return status(StatusCode::NOT_FOUND),
+    };
+    if !is_manifest && faults.corrupt_chunks {
+        data[0] ^= 0xff;
+    }
+    if path.ends_with("_manifest_chunks.csv") && faults.invalid_chunk_shas {
+        data = String::from_utf8(data)
+            .unwrap()
+            .replace(",0_", ",../0_")
+            .into_bytes();
+    }
+
+    Response::builder()
+        .header(header::CONTENT_LENGTH, data.len())
+        .body(Body::from(data))
+        .unwrap()
+}
+
+fn json(value: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(value.to_string()))
+        .unwrap()
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
+
+fn sha256(data: &[u8]) -> String {
+    base16ct::lower::encode_string(&Sha256::digest(data))
+}
+
+/// Isolated config and install directories pointing the binary at a mock server
+pub struct TestEnv {
+    pub server: MockServer,
+    dir: TempDir,
+}
+
+impl TestEnv {
+    pub async fn new(games: Vec<MockGame>) -> Self {
+        Self {
+            server: MockServer::start(games).await,
+            dir: TempDir::new().unwrap(),
+        }
+    }
+
+    /// Starts a mock server with the given games and logs into it
+    pub async fn logged_in(games: Vec<MockGame>) -> Self {
+        let env = Self::new(games).await;
+        let output = env.run(&["login", EMAIL, PASSWORD]).await;
+        assert!(output.success, "Failed to login: {}", output.stdout);
+        env
+    }
+
+    pub fn path(&self, path: &str) -> PathBuf {
+        self.dir.path().join(path)
+    }
+
+    pub fn install_path(&self, slug: &str) -> PathBuf {
+        self.path("games").join(slug)
+    }
+
+    pub async fn run(&self, args: &[&str]) -> RunOutput {
+        self.run_with_env(args, &[]).await
+    }
+
+    pub async fn run_with_env(&self, args: &[&str], env: &[(&str, &str)]) -> RunOutput {
+        let output = self
+            .command(args)
+            .envs(env.iter().copied())
+            .output()
+            .await
+            .expect("Failed to run freecarnival");
+
+        RunOutput::from(output)
+    }
+
+    /// Command running the binary with this environment
+    pub fn command(&self, args: &[&str]) -> tokio::process::Command {
+        let mut command = tokio::process::Command::new(env!("CARGO_BIN_EXE_freecarnival"));
+        command
+            .args(args)
+            .env("HOME", self.path("home"))
+            .env("XDG_CONFIG_HOME", self.path("config"))
+            .env("XDG_CACHE_HOME", self.path("cache"))
+            .env("XDG_DATA_HOME", self.path("data"))
+            .env("FREECARNIVAL_BASE_URL", &self.server.url)
+            .env("FREECARNIVAL_CONTENT_URL", &self.server.url)
+            .env("FREECARNIVAL_DEV_URL", &self.server.url)
+            .env("FREECARNIVAL_TIMEOUT", "10")
+            .env("FREECARNIVAL_SOCKET", self.socket_path());
+        command
+    }
+
+    /// Control socket of a daemon started in this environment
+    pub fn socket_path(&self) -> PathBuf {
+        self.path("daemon.sock")
+    }
+
+    /// Installs the latest build of a game into [`TestEnv::install_path`]
+    pub async fn install(&self, slug: &str) -> RunOutput {
+        let install_path = self.install_path(slug);
+        self.run(&["install", slug, "--path", install_path.to_str().unwrap()])
+            .await
+    }
+}
+
+pub struct RunOutput {
+    pub success: bool,
+    pub stdout: String,
+}
+
+impl From<Output> for RunOutput {
+    fn from(output: Output) -> Self {
+        Self {
+            success: output.status.success(),
+            stdout: format!(
+                "{}{}",
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            ),
+        }
+    }
+}
+
+/// Asserts that `dir` contains exactly the given files
+pub fn assert_files(dir: &Path, files: &[(&str, Vec<u8>)]) {
+    for (name, data) in files {
+        let path = dir.join(name);
+        let actual = std::fs::read(&path)
+            .unwrap_or_else(|err| panic!("Failed to read {}: {:?}", path.display(), err));
+        assert!(actual == *data, "{} has the wrong contents", path.display());
+    }
+
+    let mut actual_files = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else {
+                actual_files.push(path);
+            }
+        }
+    }
+    assert_eq!(
+        actual_files.len(),
+        files.len(),
+        "Unexpected files in {}: {:?}",
+        dir.display(),
+        actual_files
+    );
+}
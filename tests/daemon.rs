@@ -0,0 +1,212 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::{process::Stdio, time::Duration};
+
+use common::{assert_files, contents, MockBuild, MockGame, TestEnv};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+    process::Child,
+};
+
+fn files() -> Vec<(&'static str, Vec<u8>)> {
+    vec![
+        ("game.exe", contents(1, 4096)),
+        ("data/level1.pak", contents(2, 1048576 + 77)),
+    ]
+}
+
+fn game() -> MockGame {
+    MockGame::new("syberia-ii", vec![MockBuild::new("1.0", 1, &files())])
+}
+
+/// Starts a daemon and waits until it's listening
+async fn start_daemon(env: &TestEnv) -> Child {
+    let daemon = env
+        .command(&["daemon"])
+        .kill_on_drop(true)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to start daemon");
+
+    for _ in 0..100 {
+        if UnixStream::connect(env.socket_path()).await.is_ok() {
+            return daemon;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Daemon didn't start listening");
+}
+
+async fn call(stream: &mut BufReader<UnixStream>, method: &str, params: Value) -> Value {
+    let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+    let mut line = request.to_string();
+    line.push('\n');
+    stream.get_mut().write_all(line.as_bytes()).await.unwrap();
+
+    let mut response = String::new();
+    stream.read_line(&mut response).await.unwrap();
+    serde_json::from_str(&response).unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn commands_are_forwarded_to_daemon() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    let _daemon = start_daemon(&env).await;
+
+    let output = env.run(&["library"]).await;
+    assert!(output.stdout.contains("[syberia-ii]"), "{}", output.stdout);
+    assert!(!output.stdout.contains("Syncing"), "{}", output.stdout);
+
+    let output = env.install("syberia-ii").await;
+    assert!(
+        output
+            .stdout
+            .contains("Successfully installed syberia-ii (1.0)"),
+        "{}",
+        output.stdout
+    );
+    assert_files(&env.install_path("syberia-ii"), &files());
+
+    let output = env.run(&["queue", "list"]).await;
+    assert!(
+        output.stdout.contains("The download queue is empty"),
+        "{}",
+        output.stdout
+    );
+
+    // The daemon keeps track of what it installed
+    let output = env.run(&["verify", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("syberia-ii passed verification."),
+        "{}",
+        output.stdout
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn daemon_reports_rpc_errors() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    let _daemon = start_daemon(&env).await;
+    let mut stream = BufReader::new(UnixStream::connect(env.socket_path()).await.unwrap());
+
+    let response = call(&mut stream, "library.list", json!(null)).await;
+    assert_eq!(response["result"][0]["slugged_name"], "syberia-ii");
+
+    let response = call(&mut stream, "nonexistent", json!(null)).await;
+    assert_eq!(response["error"]["code"], -32601, "{response}");
+
+    let response = call(&mut stream, "install.start", json!({})).await;
+    assert_eq!(response["error"]["code"], -32602, "{response}");
+
+    let response = call(&mut stream, "install.start", json!({"slug": "unknown"})).await;
+    assert_eq!(response["error"]["code"], -32000, "{response}");
+
+    let response = call(&mut stream, "install.pause", json!({"slug": "unknown"})).await;
+    assert_eq!(response["error"]["code"], -32000, "{response}");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn daemon_syncs_library() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    // The game was added to the library after the last sync
+    let library_path = env.path("config").join("freecarnival").join("library.yml");
+    std::fs::write(&library_path, "collection: []").unwrap();
+    let _daemon = start_daemon(&env).await;
+    let mut stream = BufReader::new(UnixStream::connect(env.socket_path()).await.unwrap());
+
+    let response = call(&mut stream, "install.start", json!({"slug": "syberia-ii"})).await;
+    assert_eq!(response["result"]["slug"], "syberia-ii", "{response}");
+
+    std::fs::write(&library_path, "collection: []").unwrap();
+    let response = call(&mut stream, "library.list", json!(null)).await;
+    assert_eq!(response["result"][0]["slugged_name"], "syberia-ii");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn second_daemon_fails_to_start() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    let _daemon = start_daemon(&env).await;
+
+    let output = env.run(&["daemon"]).await;
+    assert!(
+        output.stdout.contains("already running"),
+        "{}",
+        output.stdout
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn daemon_socket_and_launches_are_not_shared() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let env = TestEnv::logged_in(vec![game()]).await;
+    let _daemon = start_daemon(&env).await;
+
+    let mode = std::fs::metadata(env.socket_path())
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    // Games are launched with the daemon's own Wine settings
+    let output = env
+        .run(&["launch", "syberia-ii", "--wine-bin", "/bin/sh"])
+        .await;
+    assert!(
+        output
+            .stdout
+            .contains("--wine-bin and --wine-prefix can't be used while the daemon is running"),
+        "{}",
+        output.stdout
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn daemon_limits_are_set_when_it_starts() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    let _daemon = start_daemon(&env).await;
+
+    for args in [
+        ["--limit", "1MiB/s"],
+        ["--max-download-workers", "1"],
+        ["--max-memory-usage", "1048576"],
+    ] {
+        let output = env
+            .run(&[&["install", "syberia-ii"][..], &args[..]].concat())
+            .await;
+        assert!(
+            output
+                .stdout
+                .contains("can't be used while the daemon is running"),
+            "{}",
+            output.stdout
+        );
+    }
+    assert!(!env.install_path("syberia-ii").exists());
+
+    // Options of a single install are passed on to the daemon
+    let install_path = env.install_path("syberia-ii");
+    let output = env
+        .run(&[
+            "install",
+            "syberia-ii",
+            "--path",
+            install_path.to_str().unwrap(),
+            "--force",
+            "--preallocate",
+        ])
+        .await;
+    assert!(
+        output
+            .stdout
+            .contains("Successfully installed syberia-ii (1.0)"),
+        "{}",
+        output.stdout
+    );
+    assert_files(&env.install_path("syberia-ii"), &files());
+}
@@ -0,0 +1,501 @@
+mod common;
+
+use std::{
+    process::Stdio,
+    time::{Duration, Instant},
+};
+
+use common::{contents, Faults, MockBuild, MockGame, RunOutput, TestEnv};
+
+fn game() -> MockGame {
+    MockGame::new(
+        "syberia-ii",
+        vec![MockBuild::new(
+            "1.0",
+            1,
+            &[
+                ("game.exe", contents(1, 4096)),
+                ("data.pak", contents(2, 1048576 + 100)),
+            ],
+        )],
+    )
+}
+
+async fn assert_not_installed(env: &TestEnv) {
+    let output = env.run(&["verify", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("syberia-ii is not installed."),
+        "{}",
+        output.stdout
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn corrupt_chunks_fail_install() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    env.server.set_faults(Faults {
+        corrupt_chunks: true,
+        ..Default::default()
+    });
+
+    let output = env.install("syberia-ii").await;
+    assert!(
+        output.stdout.contains("failed verification"),
+        "{}",
+        output.stdout
+    );
+    assert!(
+        output.stdout.contains("Failed to install syberia-ii"),
+        "{}",
+        output.stdout
+    );
+    assert_not_installed(&env).await;
+
+    let output = env.run(&["queue", "list"]).await;
+    assert!(output.stdout.contains("failed"), "{}", output.stdout);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_install_can_be_resumed() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    env.server.set_faults(Faults {
+        corrupt_chunks: true,
+        ..Default::default()
+    });
+    env.install("syberia-ii").await;
+    assert_not_installed(&env).await;
+
+    env.server.set_faults(Faults::default());
+    let output = env.run(&["queue", "resume"]).await;
+    assert!(
+        output.stdout.contains("Successfully installed syberia-ii"),
+        "{}",
+        output.stdout
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn labeled_install_is_queued_next_to_failed_install() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    env.server.set_faults(Faults {
+        corrupt_chunks: true,
+        ..Default::default()
+    });
+    env.install("syberia-ii").await;
+
+    env.server.set_faults(Faults::default());
+    let install_path = env.install_path("syberia-ii@beta");
+    let output = env
+        .run(&[
+            "install",
+            "syberia-ii",
+            "--label",
+            "beta",
+            "--path",
+            install_path.to_str().unwrap(),
+        ])
+        .await;
+    assert!(
+        output
+            .stdout
+            .contains("Successfully installed syberia-ii@beta"),
+        "{}",
+        output.stdout
+    );
+
+    let output = env
+        .run(&["queue", "pause", "syberia-ii", "--label", "beta"])
+        .await;
+    assert!(
+        output
+            .stdout
+            .contains("No download of syberia-ii matches beta."),
+        "{}",
+        output.stdout
+    );
+    let output = env.run(&["queue", "list"]).await;
+    assert!(
+        output.stdout.contains("[syberia-ii] failed"),
+        "{}",
+        output.stdout
+    );
+    let output = env.run(&["queue", "remove", "syberia-ii"]).await;
+    assert!(
+        output
+            .stdout
+            .contains("syberia-ii removed from the download queue."),
+        "{}",
+        output.stdout
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn skip_verify_installs_corrupt_chunks() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    env.server.set_faults(Faults {
+        corrupt_chunks: true,
+        ..Default::default()
+    });
+    let install_path = env.install_path("syberia-ii");
+
+    let output = env
+        .run(&[
+            "install",
+            "syberia-ii",
+            "--path",
+            install_path.to_str().unwrap(),
+            "--skip-verify",
+        ])
+        .await;
+    assert!(
+        output.stdout.contains("Successfully installed syberia-ii"),
+        "{}",
+        output.stdout
+    );
+
+    let output = env.run(&["verify", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("syberia-ii is corrupted."),
+        "{}",
+        output.stdout
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn missing_chunks_fail_install() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    env.server.set_faults(Faults {
+        missing_chunks: true,
+        ..Default::default()
+    });
+
+    let output = env.install("syberia-ii").await;
+    assert!(output.stdout.contains("404 Not Found"), "{}", output.stdout);
+    assert!(
+        output.stdout.contains("Failed to install syberia-ii"),
+        "{}",
+        output.stdout
+    );
+    assert_not_installed(&env).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn missing_manifest_fails_install() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    env.server.set_faults(Faults {
+        missing_manifests: true,
+        ..Default::default()
+    });
+
+    let output = env.install("syberia-ii").await;
+    assert!(output.stdout.contains("404 Not Found"), "{}", output.stdout);
+    assert!(
+        output.stdout.contains("Failed to install syberia-ii"),
+        "{}",
+        output.stdout
+    );
+    assert_eq!(env.server.chunk_requests(), 0);
+    assert_not_installed(&env).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stalled_downloads_time_out() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    env.server.set_faults(Faults {
+        stall_chunks: Some(Duration::from_secs(30)),
+        ..Default::default()
+    });
+    let install_path = env.install_path("syberia-ii");
+
+    let start = Instant::now();
+    let output = env
+        .run_with_env(
+            &[
+                "install",
+                "syberia-ii",
+                "--path",
+                install_path.to_str().unwrap(),
+            ],
+            &[("FREECARNIVAL_TIMEOUT", "1")],
+        )
+        .await;
+    assert!(start.elapsed() < Duration::from_secs(20));
+    assert!(
+        output.stdout.contains("Timed out waiting for the server"),
+        "{}",
+        output.stdout
+    );
+    assert!(
+        output.stdout.contains("Failed to install syberia-ii"),
+        "{}",
+        output.stdout
+    );
+    assert_not_installed(&env).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unreachable_server_fails_sync() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+
+    let output = env
+        .run_with_env(
+            &["install", "syberia-ii"],
+            &[("FREECARNIVAL_BASE_URL", "http://127.0.0.1:1")],
+        )
+        .await;
+    assert!(
+        output.stdout.contains("Failed to sync"),
+        "{}",
+        output.stdout
+    );
+    assert_not_installed(&env).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unsafe_manifest_paths_fail_install() {
+    let game = MockGame::new(
+        "syberia-ii",
+        vec![MockBuild::new(
+            "1.0",
+            1,
+            &[
+                ("game.exe", contents(1, 100)),
+                ("../escape.txt", contents(2, 100)),
+            ],
+        )],
+    );
+    let env = TestEnv::logged_in(vec![game]).await;
+
+    let output = env.install("syberia-ii").await;
+    assert!(
+        output.stdout.contains("it leaves the install directory"),
+        "{}",
+        output.stdout
+    );
+    assert!(!env.path("games").join("escape.txt").exists());
+    // Nothing is written before the paths are checked
+    assert!(!env.install_path("syberia-ii").join("game.exe").exists());
+    assert_not_installed(&env).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn chunks_of_unknown_files_fail_install() {
+    // A chunk for a file missing from the build manifest, and one for a directory
+    for stray_path in ["missing.pak", "data"] {
+        let mut build = MockBuild::new(
+            "1.0",
+            1,
+            &[
+                ("game.exe", contents(1, 100)),
+                ("data/level1.pak", contents(2, 100)),
+            ],
+        );
+        build.stray_chunks = vec![(stray_path.to_owned(), contents(3, 100))];
+        let env = TestEnv::logged_in(vec![MockGame::new("syberia-ii", vec![build])]).await;
+
+        let output = env.install("syberia-ii").await;
+        assert!(
+            output.stdout.contains(&format!(
+                "is a chunk of {stray_path}, which isn't a file downloaded by the build manifest"
+            )),
+            "{}",
+            output.stdout
+        );
+        assert!(!env.install_path("syberia-ii").join("game.exe").exists());
+        assert_not_installed(&env).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn installs_that_dont_fit_need_force() {
+    let mut build = MockBuild::new("1.0", 1, &[("game.exe", contents(1, 4096))]);
+    // Sparse, so it's only created when the space check is skipped
+    build.unchunked_files = vec![("huge.pak".to_owned(), 8 << 40)];
+    let env = TestEnv::logged_in(vec![MockGame::new("syberia-ii", vec![build])]).await;
+
+    let output = env.install("syberia-ii").await;
+    assert!(
+        output.stdout.contains("Not enough free disk space"),
+        "{}",
+        output.stdout
+    );
+    assert_eq!(env.server.chunk_requests(), 0);
+    assert!(!env.install_path("syberia-ii").exists());
+    assert_not_installed(&env).await;
+
+    let output = env.run(&["queue", "resume", "--force"]).await;
+    assert!(
+        output.stdout.contains("Successfully installed syberia-ii"),
+        "{}",
+        output.stdout
+    );
+    assert_eq!(
+        std::fs::read(env.install_path("syberia-ii").join("game.exe")).unwrap(),
+        contents(1, 4096)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn invalid_chunk_shas_fail_download() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    env.server.set_faults(Faults {
+        invalid_chunk_shas: true,
+        ..Default::default()
+    });
+    let archive_path = env.path("archive");
+    let archive = archive_path.to_str().unwrap();
+    let output = env.run(&["download", "syberia-ii", "--to", archive]).await;
+    assert!(
+        output.stdout.contains("isn't a valid chunk SHA"),
+        "{}",
+        output.stdout
+    );
+    assert_eq!(env.server.chunk_requests(), 0);
+
+    // The build isn't listed in the archive, so it can't be installed from it
+    let output = env
+        .run(&[
+            "install",
+            "syberia-ii",
+            "--path",
+            env.install_path("syberia-ii").to_str().unwrap(),
+            "--from-archive",
+            archive,
+        ])
+        .await;
+    assert!(
+        !output.stdout.contains("Successfully installed"),
+        "{}",
+        output.stdout
+    );
+    assert_not_installed(&env).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn archived_chunks_outside_the_archive_fail_install() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    let archive_path = env.path("archive");
+    let archive = archive_path.to_str().unwrap();
+    env.run(&["download", "syberia-ii", "--to", archive]).await;
+
+    // Point a chunk of the archived chunk manifest outside of the archive
+    let mut dirs = vec![archive_path.clone()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.to_str().unwrap().ends_with("_manifest_chunks.csv") {
+                let manifest = std::fs::read_to_string(&path).unwrap();
+                std::fs::write(&path, manifest.replace(",0_", ",../0_")).unwrap();
+            }
+        }
+    }
+
+    let install_path = env.install_path("syberia-ii");
+    let output = env
+        .run(&[
+            "install",
+            "syberia-ii",
+            "--path",
+            install_path.to_str().unwrap(),
+            "--from-archive",
+            archive,
+        ])
+        .await;
+    assert!(
+        output.stdout.contains("isn't a valid chunk SHA"),
+        "{}",
+        output.stdout
+    );
+    assert_not_installed(&env).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn write_errors_fail_install() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    env.server.set_faults(Faults {
+        stall_chunks: Some(Duration::from_secs(1)),
+        ..Default::default()
+    });
+
+    let install = env
+        .command(&[
+            "install",
+            "syberia-ii",
+            "--path",
+            env.install_path("syberia-ii").to_str().unwrap(),
+        ])
+        .kill_on_drop(true)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start install");
+    for _ in 0..100 {
+        if env.server.chunk_requests() > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    // Files are created before chunks are downloaded, so this one can't be written anymore
+    let data_path = env.install_path("syberia-ii").join("data.pak");
+    std::fs::remove_file(&data_path).unwrap();
+    std::fs::create_dir(&data_path).unwrap();
+
+    let output = RunOutput::from(install.wait_with_output().await.unwrap());
+    assert!(
+        output.stdout.contains("Failed to open"),
+        "{}",
+        output.stdout
+    );
+    assert!(
+        output.stdout.contains("Failed to install syberia-ii"),
+        "{}",
+        output.stdout
+    );
+    assert!(!output.stdout.contains("panicked"), "{}", output.stdout);
+    assert_not_installed(&env).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unreadable_installed_config_fails_queue() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    env.server.set_faults(Faults {
+        stall_chunks: Some(Duration::from_secs(1)),
+        ..Default::default()
+    });
+
+    let install = env
+        .command(&[
+            "install",
+            "syberia-ii",
+            "--path",
+            env.install_path("syberia-ii").to_str().unwrap(),
+        ])
+        .kill_on_drop(true)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start install");
+    for _ in 0..100 {
+        if env.server.chunk_requests() > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let installed_path = env
+        .path("config")
+        .join("freecarnival")
+        .join("installed.yml");
+    std::fs::write(&installed_path, "games: [").unwrap();
+
+    let output = RunOutput::from(install.wait_with_output().await.unwrap());
+    assert!(
+        output
+            .stdout
+            .contains("Failed to process the download queue"),
+        "{}",
+        output.stdout
+    );
+    assert!(!output.stdout.contains("panicked"), "{}", output.stdout);
+}
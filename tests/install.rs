@@ -0,0 +1,947 @@
+mod common;
+
+use std::{process::Stdio, time::Duration};
+
+use common::{assert_files, contents, Faults, MockBuild, MockGame, MockServer, RunOutput, TestEnv};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+fn files() -> Vec<(&'static str, Vec<u8>)> {
+    vec![
+        ("game.exe", contents(1, 4096)),
+        ("data/level1.pak", contents(2, 1048576 * 2 + 1234)),
+        ("data/sub/empty.txt", vec![]),
+        ("readme.txt", contents(3, 10)),
+    ]
+}
+
+fn game() -> MockGame {
+    MockGame::new("syberia-ii", vec![MockBuild::new("1.0", 1, &files())])
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn install_verify_uninstall() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    let install_path = env.install_path("syberia-ii");
+
+    let output = env.install("syberia-ii").await;
+    assert!(
+        output.stdout.contains("Successfully installed syberia-ii"),
+        "{}",
+        output.stdout
+    );
+    assert_files(&install_path, &files());
+
+    let output = env.run(&["verify", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("syberia-ii passed verification."),
+        "{}",
+        output.stdout
+    );
+
+    let output = env.run(&["uninstall", "syberia-ii"]).await;
+    assert!(output.stdout.contains("uninstalled"), "{}", output.stdout);
+    assert!(!install_path.exists());
+
+    let output = env.run(&["verify", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("syberia-ii is not installed."),
+        "{}",
+        output.stdout
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn verify_detects_modified_files() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    env.install("syberia-ii").await;
+
+    std::fs::write(
+        env.install_path("syberia-ii").join("readme.txt"),
+        "tampered",
+    )
+    .unwrap();
+
+    let output = env.run(&["verify", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("syberia-ii is corrupted."),
+        "{}",
+        output.stdout
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn install_info_does_not_download() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    let install_path = env.install_path("syberia-ii");
+
+    let output = env
+        .run(&[
+            "install",
+            "syberia-ii",
+            "--path",
+            install_path.to_str().unwrap(),
+            "--info",
+        ])
+        .await;
+    assert!(
+        output.stdout.contains("Download Size: 2"),
+        "{}",
+        output.stdout
+    );
+    assert!(!install_path.exists());
+    assert_eq!(env.server.chunk_requests(), 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn info_shows_install_state_and_sizes() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+
+    let output = env.run(&["info", "syberia-ii"]).await;
+    assert!(output.stdout.contains("Not installed"), "{}", output.stdout);
+    assert!(
+        output.stdout.contains("Download Size: 2"),
+        "{}",
+        output.stdout
+    );
+
+    env.install("syberia-ii").await;
+    let output = env.run(&["info", "syberia-ii"]).await;
+    for expected in [
+        "[syberia-ii] syberia ii",
+        "Installed Version: 1.0 (Windows)",
+        "Size on Disk: 2",
+        "Up to date",
+        "Executable: syberia-ii\\game.exe",
+        "Install Size: 2",
+        "Build Date: 2023-01-01 12:00:00",
+    ] {
+        assert!(output.stdout.contains(expected), "{}", output.stdout);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn install_specific_version() {
+    let old_files = vec![("game.exe", contents(9, 100))];
+    let game = MockGame::new(
+        "syberia-ii",
+        vec![
+            MockBuild::new("1.0", 1, &old_files),
+            MockBuild::new("1.1", 2, &files()),
+        ],
+    );
+    let env = TestEnv::logged_in(vec![game]).await;
+    let install_path = env.install_path("syberia-ii");
+
+    let output = env
+        .run(&[
+            "install",
+            "syberia-ii",
+            "--path",
+            install_path.to_str().unwrap(),
+            "--version",
+            "1.0",
+        ])
+        .await;
+    assert!(output.stdout.contains("[1.0]"), "{}", output.stdout);
+    assert_files(&install_path, &old_files);
+
+    let output = env.run(&["list-updates"]).await;
+    assert!(
+        output.stdout.contains("syberia-ii has an update -> 1.1"),
+        "{}",
+        output.stdout
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread")]
+async fn native_builds_are_preferred() {
+    let windows_files = vec![("game.exe", contents(1, 100))];
+    let linux_files = vec![("game.x86_64", contents(2, 100))];
+    let mut linux_build = MockBuild::new("1.0", 2, &linux_files);
+    linux_build.os = "lin".to_owned();
+    let games = vec![
+        MockGame::new(
+            "syberia-ii",
+            vec![MockBuild::new("1.0", 1, &windows_files), linux_build],
+        ),
+        MockGame::new("syberia", vec![MockBuild::new("1.0", 3, &windows_files)]),
+    ];
+    let env = TestEnv::logged_in(games).await;
+
+    let output = env.install("syberia-ii").await;
+    assert!(
+        output.stdout.contains("Picked the Linux build"),
+        "{}",
+        output.stdout
+    );
+    assert_files(&env.install_path("syberia-ii"), &linux_files);
+
+    let output = env.install("syberia").await;
+    assert!(
+        output
+            .stdout
+            .contains("No Linux build available, picked the Windows build instead."),
+        "{}",
+        output.stdout
+    );
+    assert_files(&env.install_path("syberia"), &windows_files);
+
+    let output = env
+        .run(&[
+            "settings",
+            "os-preference",
+            "windows",
+            "--game",
+            "syberia-ii",
+        ])
+        .await;
+    assert!(output.success, "{}", output.stdout);
+    let output = env.run(&["settings", "show"]).await;
+    assert!(
+        output
+            .stdout
+            .contains("OS preference of syberia-ii: Windows"),
+        "{}",
+        output.stdout
+    );
+
+    env.run(&["uninstall", "syberia-ii"]).await;
+    let output = env.install("syberia-ii").await;
+    assert!(
+        output.stdout.contains("Picked the Windows build"),
+        "{}",
+        output.stdout
+    );
+    assert_files(&env.install_path("syberia-ii"), &windows_files);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread")]
+async fn builds_install_side_by_side() {
+    let windows_files = vec![("game.exe", contents(1, 100))];
+    let linux_files = vec![("game.x86_64", contents(2, 100))];
+    let mut linux_build = MockBuild::new("1.0", 2, &linux_files);
+    linux_build.os = "lin".to_owned();
+    let game = MockGame::new(
+        "syberia-ii",
+        vec![MockBuild::new("1.0", 1, &windows_files), linux_build],
+    );
+    let env = TestEnv::logged_in(vec![game]).await;
+    let base_path = env.path("games");
+    let base_path = base_path.to_str().unwrap();
+
+    env.install("syberia-ii").await;
+    let output = env
+        .run(&[
+            "install",
+            "syberia-ii",
+            "--base-path",
+            base_path,
+            "--os",
+            "windows",
+        ])
+        .await;
+    assert!(
+        output
+            .stdout
+            .contains("Use --label to install another build"),
+        "{}",
+        output.stdout
+    );
+
+    let output = env
+        .run(&[
+            "install",
+            "syberia-ii",
+            "--base-path",
+            base_path,
+            "--os",
+            "windows",
+            "--label",
+            "win",
+        ])
+        .await;
+    assert!(
+        output
+            .stdout
+            .contains("Successfully installed syberia-ii@win"),
+        "{}",
+        output.stdout
+    );
+    assert_files(&env.install_path("syberia-ii"), &linux_files);
+    assert_files(&env.install_path("syberia-ii@win"), &windows_files);
+
+    // Both builds are version 1.0, but each install keeps its own manifest
+    let output = env.run(&["verify", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("syberia-ii passed verification."),
+        "{}",
+        output.stdout
+    );
+    let output = env.run(&["verify", "syberia-ii", "--label", "win"]).await;
+    assert!(
+        output
+            .stdout
+            .contains("syberia-ii@win passed verification."),
+        "{}",
+        output.stdout
+    );
+    let output = env
+        .run(&["verify", "syberia-ii", "--label", "windows"])
+        .await;
+    assert!(
+        output
+            .stdout
+            .contains("syberia-ii@win passed verification."),
+        "{}",
+        output.stdout
+    );
+    let output = env.run(&["verify", "syberia-ii", "--label", "beta"]).await;
+    assert!(
+        output
+            .stdout
+            .contains("No install of syberia-ii matches beta."),
+        "{}",
+        output.stdout
+    );
+
+    let output = env.run(&["info", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("Installed Version: 1.0 (Linux)"),
+        "{}",
+        output.stdout
+    );
+    assert!(
+        output
+            .stdout
+            .contains("Installed Version: 1.0 (Windows, win)"),
+        "{}",
+        output.stdout
+    );
+
+    let output = env
+        .run(&["uninstall", "syberia-ii", "--label", "win"])
+        .await;
+    assert!(output.stdout.contains("uninstalled"), "{}", output.stdout);
+    assert!(!env.install_path("syberia-ii@win").exists());
+    assert_files(&env.install_path("syberia-ii"), &linux_files);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn installed_config_without_labels_is_migrated() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    let install_path = env.install_path("syberia-ii");
+    std::fs::create_dir_all(&install_path).unwrap();
+    std::fs::write(
+        env.path("config")
+            .join("freecarnival")
+            .join("installed.yml"),
+        format!(
+            "syberia-ii:\n  install_path: {}\n  version: '1.0'\n  os: win\n",
+            install_path.display()
+        ),
+    )
+    .unwrap();
+
+    let output = env.run(&["info", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("Installed Version: 1.0 (Windows)"),
+        "{}",
+        output.stdout
+    );
+
+    let output = env.run(&["uninstall", "syberia-ii"]).await;
+    assert!(output.stdout.contains("uninstalled"), "{}", output.stdout);
+    assert!(!install_path.exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn manifest_show_and_check() {
+    let files = vec![
+        ("game.exe", contents(1, 4096)),
+        ("data/level1.pak", contents(2, 1048576 + 10)),
+        ("data/level2.pak", contents(3, 2048)),
+    ];
+    let game = MockGame::new("syberia-ii", vec![MockBuild::new("1.0", 1, &files)]);
+    let env = TestEnv::logged_in(vec![game]).await;
+
+    // Not installed, the manifests are fetched
+    let output = env.run(&["manifest", "show", "syberia-ii"]).await;
+    assert!(output.stdout.contains("game.exe"), "{}", output.stdout);
+    assert!(
+        output.stdout.contains("4 files, 4 chunks"),
+        "{}",
+        output.stdout
+    );
+
+    env.install("syberia-ii").await;
+    let output = env
+        .run(&["manifest", "show", "syberia-ii", "--glob", "data/*.pak"])
+        .await;
+    assert!(
+        output.stdout.contains("data/level1.pak"),
+        "{}",
+        output.stdout
+    );
+    assert!(!output.stdout.contains("game.exe"), "{}", output.stdout);
+
+    let output = env
+        .run(&[
+            "manifest",
+            "show",
+            "syberia-ii",
+            "--glob",
+            "*.exe",
+            "--json",
+        ])
+        .await;
+    let json: serde_json::Value = serde_json::from_str(&output.stdout).unwrap();
+    assert_eq!(json["files"][0]["path"], "game.exe");
+    assert_eq!(json["files"][0]["size"], 4096);
+    assert_eq!(json["chunks"].as_array().unwrap().len(), 1);
+
+    let output = env.run(&["manifest", "check", "syberia-ii"]).await;
+    assert!(
+        output
+            .stdout
+            .contains("The manifests of syberia-ii are consistent"),
+        "{}",
+        output.stdout
+    );
+
+    // Drop the second chunk of data/level1.pak from the stored chunk manifest
+    let chunks_path = env
+        .path("data")
+        .join("freecarnival")
+        .join("manifests")
+        .join("syberia-ii")
+        .join("1.0_manifest_chunks.csv");
+    let chunks = std::fs::read_to_string(&chunks_path).unwrap();
+    let chunks: String = chunks
+        .lines()
+        .filter(|line| !line.starts_with("1,"))
+        .map(|line| format!("{line}\n"))
+        .collect();
+    std::fs::write(&chunks_path, chunks).unwrap();
+
+    let output = env.run(&["manifest", "check", "syberia-ii"]).await;
+    assert!(
+        output
+            .stdout
+            .contains("data/level1.pak has 1 chunks, but the build manifest lists 2"),
+        "{}",
+        output.stdout
+    );
+    assert!(
+        output.stdout.contains("Found 1 problem(s)"),
+        "{}",
+        output.stdout
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread")]
+async fn windows_builds_are_installed_case_insensitively() {
+    let files = vec![
+        ("Data/level1.pak", contents(1, 100)),
+        ("data/level2.pak", contents(2, 100)),
+        ("DATA/Sub/a.txt", contents(3, 10)),
+    ];
+    let game = MockGame::new("syberia-ii", vec![MockBuild::new("1.0", 1, &files)]);
+    let env = TestEnv::logged_in(vec![game]).await;
+    let install_path = env.install_path("syberia-ii");
+
+    env.install("syberia-ii").await;
+    // Directories are listed sorted in the manifest, so they all take the case of `DATA`
+    assert_files(
+        &install_path,
+        &[
+            ("DATA/level1.pak", contents(1, 100)),
+            ("DATA/level2.pak", contents(2, 100)),
+            ("DATA/Sub/a.txt", contents(3, 10)),
+        ],
+    );
+    assert!(!install_path.join("Data").exists());
+    assert!(!install_path.join("data").exists());
+
+    let output = env.run(&["verify", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("syberia-ii passed verification."),
+        "{}",
+        output.stdout
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn downloaded_builds_are_served_to_other_machines() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    let archive_path = env.path("archive");
+    let archive = archive_path.to_str().unwrap();
+
+    let output = env.run(&["download", "syberia-ii", "--to", archive]).await;
+    assert!(output.success, "{}", output.stdout);
+
+    let mut mirror = env
+        .command(&["serve", "--root", archive, "--bind", "127.0.0.1:0"])
+        .kill_on_drop(true)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to start mirror");
+    let mut lines = BufReader::new(mirror.stdout.take().unwrap()).lines();
+    let line = tokio::time::timeout(Duration::from_secs(10), lines.next_line())
+        .await
+        .expect("Mirror didn't start listening")
+        .unwrap()
+        .unwrap();
+    let mirror_url = line
+        .strip_prefix("Serving content mirror on ")
+        .unwrap_or_else(|| panic!("Unexpected output: {}", line));
+
+    // Everything has to come from the mirror
+    env.server.set_faults(Faults {
+        missing_chunks: true,
+        missing_manifests: true,
+        ..Default::default()
+    });
+    let chunk_requests = env.server.chunk_requests();
+    let install_path = env.install_path("syberia-ii");
+    let output = env
+        .run(&[
+            "install",
+            "syberia-ii",
+            "--path",
+            install_path.to_str().unwrap(),
+            "--content-url",
+            mirror_url,
+        ])
+        .await;
+    assert!(
+        output.stdout.contains("Successfully installed syberia-ii"),
+        "{}",
+        output.stdout
+    );
+    assert_files(&install_path, &files());
+    assert_eq!(env.server.chunk_requests(), chunk_requests);
+}
+
+/// Install ids of `queue list` in the order they're listed
+fn queued(stdout: &str) -> Vec<&str> {
+    stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix('[')?.split(']').next())
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn queue_installs_by_priority_and_can_be_paused() {
+    let small_files = vec![("game.exe", contents(4, 100))];
+    let env = TestEnv::logged_in(vec![
+        game(),
+        MockGame::new("syberia", vec![MockBuild::new("1.0", 1, &small_files)]),
+        MockGame::new("blasphemous", vec![MockBuild::new("1.0", 1, &small_files)]),
+    ])
+    .await;
+    let base_path = env.path("games");
+    let base_path = base_path.to_str().unwrap();
+
+    // Failed installs stay in the queue, so it can be filled without installing anything
+    env.server.set_faults(Faults {
+        missing_chunks: true,
+        ..Default::default()
+    });
+    for (slug, priority) in [("syberia", "0"), ("syberia-ii", "5"), ("blasphemous", "0")] {
+        env.run(&[
+            "install",
+            slug,
+            "--base-path",
+            base_path,
+            "--priority",
+            priority,
+        ])
+        .await;
+    }
+    let output = env.run(&["queue", "list"]).await;
+    assert_eq!(
+        queued(&output.stdout),
+        ["syberia-ii", "syberia", "blasphemous"]
+    );
+
+    // Pausing a download stops the process downloading it
+    env.server.set_faults(Faults {
+        stall_chunks: Some(Duration::from_secs(60)),
+        ..Default::default()
+    });
+    let chunk_requests = env.server.chunk_requests();
+    let resume = env
+        .command(&["queue", "resume", "syberia-ii"])
+        .kill_on_drop(true)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to resume queue");
+    for _ in 0..100 {
+        if env.server.chunk_requests() > chunk_requests {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let output = env.run(&["queue", "pause", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("syberia-ii paused."),
+        "{}",
+        output.stdout
+    );
+    let output = tokio::time::timeout(Duration::from_secs(20), resume.wait_with_output())
+        .await
+        .expect("Paused download kept running")
+        .unwrap();
+    let stdout = RunOutput::from(output).stdout;
+    assert!(
+        stdout.contains("Stopped downloading syberia-ii."),
+        "{}",
+        stdout
+    );
+    // Chunks that arrive after the install stopped are dropped quietly
+    assert!(!stdout.contains("panicked"), "{}", stdout);
+    let output = env.run(&["queue", "list"]).await;
+    assert!(
+        output.stdout.contains("[syberia-ii] paused"),
+        "{}",
+        output.stdout
+    );
+
+    // Resuming installs everything left, highest priority first
+    env.server.set_faults(Faults::default());
+    let output = env.run(&["queue", "resume"]).await;
+    let installed: Vec<usize> = ["syberia-ii", "syberia", "blasphemous"]
+        .iter()
+        .map(|slug| {
+            output
+                .stdout
+                .find(&format!("Successfully installed {} (1.0)", slug))
+                .unwrap_or_else(|| panic!("{} wasn't installed: {}", slug, output.stdout))
+        })
+        .collect();
+    assert!(
+        installed.windows(2).all(|pair| pair[0] < pair[1]),
+        "{}",
+        output.stdout
+    );
+    assert_files(&env.install_path("syberia-ii"), &files());
+    assert_files(&env.install_path("syberia"), &small_files);
+    assert_files(&env.install_path("blasphemous"), &small_files);
+    let output = env.run(&["queue", "list"]).await;
+    assert!(
+        output.stdout.contains("The download queue is empty"),
+        "{}",
+        output.stdout
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn preallocated_files_take_their_space_before_downloading() {
+    use std::os::unix::fs::MetadataExt;
+
+    let env = TestEnv::logged_in(vec![game()]).await;
+    let install_path = env.install_path("syberia-ii");
+    env.server.set_faults(Faults {
+        stall_chunks: Some(Duration::from_secs(2)),
+        ..Default::default()
+    });
+
+    let install = env
+        .command(&[
+            "install",
+            "syberia-ii",
+            "--path",
+            install_path.to_str().unwrap(),
+            "--preallocate",
+        ])
+        .kill_on_drop(true)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start install");
+    for _ in 0..100 {
+        if env.server.chunk_requests() > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    // Chunks are still being downloaded, but the files already take their final size on disk
+    let metadata = std::fs::metadata(install_path.join("data/level1.pak")).unwrap();
+    assert_eq!(metadata.len(), 1048576 * 2 + 1234);
+    assert!(metadata.blocks() * 512 >= metadata.len());
+
+    let output = install.wait_with_output().await.unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Successfully installed syberia-ii"),
+        "{}",
+        stdout
+    );
+    assert_files(&install_path, &files());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn downloads_are_limited_to_the_download_limit() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    let install_path = env.install_path("syberia-ii");
+
+    // The game downloads a bit over 2MiB, most of it after the first chunk was taken
+    let start = std::time::Instant::now();
+    let output = env
+        .run(&[
+            "install",
+            "syberia-ii",
+            "--path",
+            install_path.to_str().unwrap(),
+            "--limit",
+            "512KiB/s",
+        ])
+        .await;
+    assert!(
+        start.elapsed() >= Duration::from_secs(2),
+        "{:?}",
+        start.elapsed()
+    );
+    assert!(
+        output.stdout.contains("Limiting downloads to 524.3 KB/s"),
+        "{}",
+        output.stdout
+    );
+    assert!(
+        output.stdout.contains("Successfully installed syberia-ii"),
+        "{}",
+        output.stdout
+    );
+    assert_files(&install_path, &files());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn chunks_are_written_in_the_order_they_arrive() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    let install_path = env.install_path("syberia-ii");
+    env.server.set_faults(Faults {
+        stall_first_chunks: Some(Duration::from_secs(1)),
+        ..Default::default()
+    });
+
+    let output = env
+        .run(&[
+            "install",
+            "syberia-ii",
+            "--path",
+            install_path.to_str().unwrap(),
+            "--max-download-workers",
+            "4",
+        ])
+        .await;
+    assert!(
+        output.stdout.contains("Successfully installed syberia-ii"),
+        "{}",
+        output.stdout
+    );
+    assert_files(&install_path, &files());
+
+    let output = env.run(&["verify", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("syberia-ii passed verification."),
+        "{}",
+        output.stdout
+    );
+}
+
+/// Value of a `Name: value` line printed by a command
+fn field<'a>(stdout: &'a str, name: &str) -> &'a str {
+    stdout
+        .lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(": "))
+        .unwrap_or_else(|| panic!("No {} in: {}", name, stdout))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cached_chunks_are_not_downloaded_again() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    let output = env.run(&["settings", "chunk-cache", "on"]).await;
+    assert!(output.success, "{}", output.stdout);
+
+    env.install("syberia-ii").await;
+    let chunk_requests = env.server.chunk_requests();
+    assert_eq!(chunk_requests, 5);
+    let output = env.run(&["cache", "stats"]).await;
+    assert_eq!(field(&output.stdout, "Chunks"), "5");
+    let cache_path = std::path::PathBuf::from(field(&output.stdout, "Location"));
+
+    // Reinstalls only download chunks that aren't cached, or that got corrupted
+    env.run(&["uninstall", "syberia-ii"]).await;
+    let corrupted = std::fs::read_dir(&cache_path)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| std::fs::metadata(path).unwrap().len() == 4096)
+        .unwrap();
+    std::fs::write(&corrupted, contents(9, 4096)).unwrap();
+    let output = env.install("syberia-ii").await;
+    assert!(
+        output.stdout.contains("Successfully installed syberia-ii"),
+        "{}",
+        output.stdout
+    );
+    assert_files(&env.install_path("syberia-ii"), &files());
+    assert_eq!(env.server.chunk_requests(), chunk_requests + 1);
+
+    let output = env.run(&["cache", "prune", "--max-size", "1MiB"]).await;
+    assert!(
+        output.stdout.contains("Chunk cache pruned to"),
+        "{}",
+        output.stdout
+    );
+    let output = env.run(&["cache", "stats"]).await;
+    let chunks: u64 = field(&output.stdout, "Chunks").parse().unwrap();
+    assert!(chunks > 0 && chunks < 5, "{}", output.stdout);
+
+    let output = env.run(&["cache", "clear"]).await;
+    assert!(
+        output.stdout.contains("Chunk cache cleared."),
+        "{}",
+        output.stdout
+    );
+    let output = env.run(&["cache", "stats"]).await;
+    assert_eq!(field(&output.stdout, "Chunks"), "0");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn downloaded_builds_install_offline() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    let archive_path = env.path("archive");
+    let archive = archive_path.to_str().unwrap();
+
+    let output = env.run(&["download", "syberia-ii", "--to", archive]).await;
+    assert!(
+        output.stdout.contains("Successfully downloaded syberia-ii"),
+        "{}",
+        output.stdout
+    );
+    let chunk_requests = env.server.chunk_requests();
+    // Chunks that are already archived aren't downloaded again
+    let output = env.run(&["download", "syberia-ii", "--to", archive]).await;
+    assert!(output.success, "{}", output.stdout);
+    assert_eq!(env.server.chunk_requests(), chunk_requests);
+
+    env.server.set_faults(Faults {
+        missing_chunks: true,
+        missing_manifests: true,
+        ..Default::default()
+    });
+    let install_path = env.install_path("syberia-ii");
+    let output = env
+        .run(&[
+            "install",
+            "syberia-ii",
+            "--path",
+            install_path.to_str().unwrap(),
+            "--from-archive",
+            archive,
+        ])
+        .await;
+    assert!(
+        output.stdout.contains("Successfully installed syberia-ii"),
+        "{}",
+        output.stdout
+    );
+    assert_files(&install_path, &files());
+    assert_eq!(env.server.chunk_requests(), chunk_requests);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn content_url_is_picked_from_flags_env_and_settings() {
+    let env = TestEnv::logged_in(vec![game()]).await;
+    let mirror = MockServer::start(vec![game()]).await;
+    // Runs without the content URL the test environment sets
+    let run = |args: &[&str]| {
+        let mut command = env.command(args);
+        command.env_remove("FREECARNIVAL_CONTENT_URL");
+        async move { RunOutput::from(command.output().await.unwrap()) }
+    };
+
+    let output = run(&["settings", "show"]).await;
+    assert!(
+        output
+            .stdout
+            .contains("Content URL: https://content.indiegalacdn.com"),
+        "{}",
+        output.stdout
+    );
+    let output = run(&["settings", "endpoint", "content", "ftp://mirror"]).await;
+    assert!(
+        output.stdout.contains("is not an http or https URL"),
+        "{}",
+        output.stdout
+    );
+    let output = run(&[
+        "settings",
+        "endpoint",
+        "content",
+        &format!("{}/", mirror.url),
+    ])
+    .await;
+    assert!(output.success, "{}", output.stdout);
+    let output = run(&["settings", "show"]).await;
+    assert!(
+        output
+            .stdout
+            .contains(&format!("Content URL: {}\n", mirror.url)),
+        "{}",
+        output.stdout
+    );
+
+    // The env var overrides settings, and the flag overrides both
+    let output = env.run(&["settings", "show"]).await;
+    assert!(
+        output
+            .stdout
+            .contains(&format!("Content URL: {}\n", env.server.url)),
+        "{}",
+        output.stdout
+    );
+    let output = env
+        .run(&["settings", "show", "--content-url", "http://flag.test"])
+        .await;
+    assert!(
+        output.stdout.contains("Content URL: http://flag.test\n"),
+        "{}",
+        output.stdout
+    );
+
+    let install_path = env.install_path("syberia-ii");
+    let output = run(&[
+        "install",
+        "syberia-ii",
+        "--path",
+        install_path.to_str().unwrap(),
+    ])
+    .await;
+    assert!(
+        output.stdout.contains("Successfully installed syberia-ii"),
+        "{}",
+        output.stdout
+    );
+    assert_files(&install_path, &files());
+    assert_eq!(env.server.chunk_requests(), 0);
+    assert_eq!(mirror.chunk_requests(), 5);
+
+    let output = run(&["settings", "endpoint", "content", "default"]).await;
+    assert!(output.success, "{}", output.stdout);
+    let output = run(&["settings", "show"]).await;
+    assert!(
+        output
+            .stdout
+            .contains("Content URL: https://content.indiegalacdn.com"),
+        "{}",
+        output.stdout
+    );
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use common::{contents, MockBuild, MockGame, TestEnv};
+
+fn games() -> Vec<MockGame> {
+    let files = vec![("game.exe", contents(1, 4096))];
+    let big_files = vec![("game.exe", contents(2, 1048576 + 10))];
+
+    let mut linux_build = MockBuild::new("2.0", 5, &files);
+    linux_build.os = "lin".to_owned();
+    let mut blasphemous = MockGame::new("blasphemous", vec![linux_build]);
+    blasphemous.namespace = "the-game-kitchen".to_owned();
+
+    vec![
+        MockGame::new(
+            "syberia-ii",
+            vec![
+                MockBuild::new("1.0", 1, &files),
+                MockBuild::new("1.1", 3, &files),
+            ],
+        ),
+        blasphemous,
+        MockGame::new("syberia", vec![MockBuild::new("1.0", 2, &big_files)]),
+    ]
+}
+
+/// Slugs in the order they were listed
+fn listed(stdout: &str) -> Vec<&str> {
+    stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix('[')?.split(']').next())
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn library_search_filter_and_sort() {
+    let env = TestEnv::logged_in(games()).await;
+
+    let output = env.run(&["library"]).await;
+    assert_eq!(
+        listed(&output.stdout),
+        ["syberia-ii", "blasphemous", "syberia"]
+    );
+
+    // Typos that keep the characters in order still match, best match first
+    let output = env.run(&["library", "--search", "sybria"]).await;
+    assert_eq!(listed(&output.stdout), ["syberia", "syberia-ii"]);
+    let output = env.run(&["library", "--search", "BLSPH"]).await;
+    assert_eq!(listed(&output.stdout), ["blasphemous"]);
+
+    let output = env.run(&["library", "--os", "lin"]).await;
+    assert_eq!(listed(&output.stdout), ["blasphemous"]);
+    let output = env
+        .run(&["library", "--developer", "the-game-kitchen"])
+        .await;
+    assert_eq!(listed(&output.stdout), ["blasphemous"]);
+
+    let output = env.run(&["library", "--sort", "name"]).await;
+    assert_eq!(
+        listed(&output.stdout),
+        ["blasphemous", "syberia", "syberia-ii"]
+    );
+    let output = env.run(&["library", "--sort", "date"]).await;
+    assert_eq!(
+        listed(&output.stdout),
+        ["blasphemous", "syberia-ii", "syberia"]
+    );
+
+    let install_path = env.install_path("syberia-ii");
+    let output = env
+        .run(&[
+            "install",
+            "syberia-ii",
+            "--path",
+            install_path.to_str().unwrap(),
+            "--version",
+            "1.0",
+        ])
+        .await;
+    assert!(output.success, "{}", output.stdout);
+    let output = env.install("syberia").await;
+    assert!(output.success, "{}", output.stdout);
+
+    let output = env.run(&["library", "--installed"]).await;
+    assert_eq!(listed(&output.stdout), ["syberia-ii", "syberia"]);
+    let output = env.run(&["library", "--not-installed"]).await;
+    assert_eq!(listed(&output.stdout), ["blasphemous"]);
+    let output = env.run(&["library", "--has-updates"]).await;
+    assert_eq!(listed(&output.stdout), ["syberia-ii"]);
+
+    // Games that were never installed have no known size, so they are listed last
+    let output = env.run(&["library", "--sort", "size"]).await;
+    assert_eq!(
+        listed(&output.stdout),
+        ["syberia", "syberia-ii", "blasphemous"]
+    );
+
+    // Games installed several times take the space of all their installs
+    for label in ["a", "b"] {
+        let install_path = env.install_path(&format!("blasphemous@{}", label));
+        let output = env
+            .run(&[
+                "install",
+                "blasphemous",
+                "--path",
+                install_path.to_str().unwrap(),
+                "--label",
+                label,
+            ])
+            .await;
+        assert!(output.success, "{}", output.stdout);
+    }
+    let output = env.run(&["library", "--sort", "size"]).await;
+    assert_eq!(
+        listed(&output.stdout),
+        ["syberia", "blasphemous", "syberia-ii"]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn games_resolve_by_id_name_and_typo() {
+    let env = TestEnv::logged_in(games()).await;
+
+    // Unique name prefix, product id and slug in another case
+    let output = env.run(&["info", "Blasph"]).await;
+    assert!(output.stdout.contains("[2.0]"), "{}", output.stdout);
+    let output = env.run(&["info", "1"]).await;
+    assert!(output.stdout.contains("[2.0]"), "{}", output.stdout);
+    let output = env.run(&["info", "SYBERIA"]).await;
+    assert!(output.stdout.contains("[1.0]"), "{}", output.stdout);
+    assert!(!output.stdout.contains("[1.1]"), "{}", output.stdout);
+
+    // Several matches can't be picked from without a terminal
+    let output = env.run(&["info", "syb"]).await;
+    assert!(
+        output.stdout.contains("syb matches several games"),
+        "{}",
+        output.stdout
+    );
+    assert!(
+        !output.stdout.contains("Available Versions"),
+        "{}",
+        output.stdout
+    );
+
+    let output = env.run(&["info", "blasfemous"]).await;
+    assert!(
+        output.stdout.contains("blasfemous is not in your library"),
+        "{}",
+        output.stdout
+    );
+    assert!(output.stdout.contains("Did you mean:"), "{}", output.stdout);
+    assert!(output.stdout.contains("[blasphemous]"), "{}", output.stdout);
+
+    let output = env.install("blasfemous").await;
+    assert!(!output.stdout.contains("Installing"), "{}", output.stdout);
+    let output = env.run(&["queue", "list"]).await;
+    assert!(
+        output.stdout.contains("The download queue is empty"),
+        "{}",
+        output.stdout
+    );
+}
@@ -0,0 +1,291 @@
+use freecarnival::manifest::{
+    delta_manifest, BuildManifest, BuildManifestChunksRecord, BuildManifestRecord,
+    BuildManifestWriter, ChangeTag, ChunkManifest, ChunkManifestWriter, InstallPaths, Manifests,
+};
+use proptest::prelude::*;
+
+fn build_records(bytes: &[u8]) -> Vec<BuildManifestRecord> {
+    BuildManifest::new(bytes)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+/// Changes of a delta manifest, as (file name, tag, copy source)
+fn delta_changes(old: &[u8], new: &[u8]) -> Vec<(String, ChangeTag, Option<String>)> {
+    build_records(&delta_manifest(old, new).unwrap())
+        .into_iter()
+        .map(|record| (record.file_name, record.tag.unwrap(), record.copy_source))
+        .collect()
+}
+
+fn file_name() -> impl Strategy<Value = String> {
+    // Any characters, including separators, quotes and line breaks that have to be escaped
+    "[^\u{0}]{1,40}|[a-z\\\\/ ,\"\r\n]{1,20}"
+}
+
+fn build_record() -> impl Strategy<Value = BuildManifestRecord> {
+    (
+        any::<usize>(),
+        any::<usize>(),
+        "[0-9a-f]{0,64}",
+        any::<u8>(),
+        file_name(),
+        prop::option::of(prop_oneof![
+            Just(ChangeTag::Added),
+            Just(ChangeTag::Modified),
+            Just(ChangeTag::Removed),
+        ]),
+        prop::option::of(file_name()),
+    )
+        .prop_map(
+            |(size_in_bytes, chunks, sha, flags, file_name, tag, copy_source)| {
+                BuildManifestRecord {
+                    size_in_bytes,
+                    chunks,
+                    sha,
+                    flags,
+                    file_name,
+                    tag,
+                    copy_source,
+                }
+            },
+        )
+}
+
+fn chunk_record() -> impl Strategy<Value = BuildManifestChunksRecord> {
+    (any::<u16>(), file_name(), "[0-9a-f_]{1,70}")
+        .prop_map(|(id, file_path, sha)| BuildManifestChunksRecord { id, file_path, sha })
+}
+
+proptest! {
+    #[test]
+    fn build_manifests_round_trip(records in prop::collection::vec(build_record(), 0..20)) {
+        let mut writer = BuildManifestWriter::new(vec![]).unwrap();
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        let bytes = writer.into_inner().unwrap();
+
+        prop_assert_eq!(build_records(&bytes), records);
+    }
+
+    #[test]
+    fn chunk_manifests_round_trip(records in prop::collection::vec(chunk_record(), 0..20)) {
+        let mut writer = ChunkManifestWriter::new(vec![]).unwrap();
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        let bytes = writer.into_inner().unwrap();
+
+        let read: Vec<BuildManifestChunksRecord> = ChunkManifest::new(&bytes[..])
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        prop_assert_eq!(read, records);
+    }
+
+    #[test]
+    fn file_names_are_utf8_or_latin1(name in prop::collection::vec(1u8.., 1..40)) {
+        let mut bytes = b"Size in Bytes,Chunks,SHA,Flags,File Name\n1,1,abc,0,\"".to_vec();
+        bytes.extend(name.iter().flat_map(|&b| if b == b'"' { vec![b'"', b'"'] } else { vec![b] }));
+        bytes.extend(b"\"\n");
+
+        let expected = match String::from_utf8(name.clone()) {
+            Ok(name) => name,
+            Err(_) => name.iter().copied().map(char::from).collect(),
+        };
+        prop_assert_eq!(&build_records(&bytes)[0].file_name, &expected);
+    }
+}
+
+#[test]
+fn header_variants_are_read() {
+    let expected = BuildManifestRecord {
+        size_in_bytes: 10,
+        chunks: 1,
+        sha: "abc".to_owned(),
+        flags: 0,
+        file_name: "data\\level1.pak".to_owned(),
+        tag: None,
+        copy_source: None,
+    };
+
+    // Build manifest from the server
+    let manifest = b"Size in Bytes,Chunks,SHA,Flags,File Name\n10,1,abc,0,data\\level1.pak\n";
+    assert_eq!(build_records(manifest), vec![expected.clone()]);
+    // Byte order mark, other column names and order, and an unknown column
+    let manifest =
+        b"\xef\xbb\xbffile_name,flags,sha,chunks,size,extra\ndata\\level1.pak,0,abc,1,10,x\n";
+    assert_eq!(build_records(manifest), vec![expected.clone()]);
+    // Delta manifest, with rows leaving out the optional columns
+    let manifest = b"Size in Bytes,Chunks,SHA,Flags,File Name,Change Tag,Copy Source\n\
+        10,1,abc,0,data\\level1.pak\n\
+        10,1,abc,0,data\\level1.pak,Modified,data\\old.pak\n";
+    assert_eq!(
+        build_records(manifest),
+        [
+            expected.clone(),
+            BuildManifestRecord {
+                tag: Some(ChangeTag::Modified),
+                copy_source: Some("data\\old.pak".to_owned()),
+                ..expected
+            }
+        ]
+    );
+    // Empty manifests, like chunk deltas of builds without changes
+    assert_eq!(build_records(b""), []);
+    assert_eq!(ChunkManifest::new(&b""[..]).unwrap().count(), 0);
+}
+
+#[test]
+fn invalid_rows_are_reported_with_their_row() {
+    let manifest = b"Size in Bytes,Chunks,SHA,Flags,File Name\n\
+        10,1,abc,0,a.pak\n\
+        ten,1,abc,0,b.pak\n";
+    let err = BuildManifest::new(&manifest[..])
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_err();
+    assert_eq!(err.row(), Some(3));
+    assert_eq!(err.to_string(), "row 3: invalid Size in Bytes \"ten\"");
+
+    let manifest = b"ID,Filepath,Chunk SHA\n0,a.pak,abc\n70000,a.pak,def\n";
+    let err = ChunkManifest::new(&manifest[..])
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_err();
+    assert_eq!(err.to_string(), "row 3: invalid ID \"70000\"");
+
+    let err = BuildManifest::new(&b"Size in Bytes,Chunks,SHA,File Name\n"[..])
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), "row 1: missing the Flags column");
+}
+
+#[test]
+fn manifest_paths_stay_in_the_install_directory() {
+    let mut paths = InstallPaths::new(false);
+    assert_eq!(
+        paths.resolve("data\\level1.pak").unwrap(),
+        "data/level1.pak"
+    );
+    assert_eq!(paths.resolve("./data//sub/").unwrap(), "data/sub");
+    assert_eq!(paths.resolve("Data/level2.pak").unwrap(), "Data/level2.pak");
+
+    for (path, reason) in [
+        ("../escape.txt", "it leaves the install directory"),
+        (
+            "data\\..\\..\\escape.txt",
+            "it leaves the install directory",
+        ),
+        ("/etc/passwd", "it's absolute"),
+        ("\\\\server\\share", "it's absolute"),
+        ("C:\\Windows\\system.ini", "it starts with a drive letter"),
+        ("./", "it's empty"),
+    ] {
+        let err = paths.resolve(path).unwrap_err();
+        assert_eq!(err.to_string(), format!("unsafe path {path:?}: {reason}"));
+    }
+}
+
+#[test]
+fn case_insensitive_paths_take_the_first_case() {
+    let mut paths = InstallPaths::new(true);
+    assert_eq!(paths.resolve("Data").unwrap(), "Data");
+    assert_eq!(
+        paths.resolve("data\\Level1.pak").unwrap(),
+        "Data/Level1.pak"
+    );
+    assert_eq!(paths.resolve("DATA/level1.PAK").unwrap(), "Data/Level1.pak");
+    assert_eq!(paths.resolve("data/sub/a.txt").unwrap(), "Data/sub/a.txt");
+}
+
+#[test]
+fn check_reports_paths_that_only_differ_in_case() {
+    let manifest = b"Size in Bytes,Chunks,SHA,Flags,File Name\n\
+        0,0,,40,Data\n\
+        0,0,,40,data\n\
+        10,1,abc,0,Data/level1.pak\n\
+        10,1,def,0,data/level2.pak\n\
+        10,1,ghi,0,data/LEVEL1.pak\n";
+    let manifest_chunks = b"ID,Filepath,Chunk SHA\n\
+        0,Data/level1.pak,0_abc\n\
+        0,data/level2.pak,0_def\n\
+        0,data/LEVEL1.pak,0_ghi\n";
+    let manifests = Manifests::parse(&manifest[..], &manifest_chunks[..]).unwrap();
+
+    assert_eq!(
+        manifests.check(),
+        vec![
+            "data and Data only differ in case",
+            "data/LEVEL1.pak and Data/level1.pak only differ in case",
+        ]
+    );
+}
+
+#[test]
+fn moved_files_are_copied() {
+    let old = b"Size in Bytes,Chunks,SHA,Flags,File Name\n\
+        0,0,,40,data\n\
+        10,1,abc,0,data\\level1.pak\n\
+        10,1,def,0,data\\level2.pak\n\
+        0,0,e3b0,0,data\\empty.txt\n";
+    let new = b"Size in Bytes,Chunks,SHA,Flags,File Name\n\
+        0,0,,40,content\n\
+        10,1,abc,0,content\\level1.pak\n\
+        10,1,ghi,0,data\\level2.pak\n\
+        0,0,e3b0,0,content\\empty.txt\n";
+
+    assert_eq!(
+        delta_changes(old, new),
+        vec![
+            ("content".to_owned(), ChangeTag::Added, None),
+            (
+                "content\\level1.pak".to_owned(),
+                ChangeTag::Added,
+                Some("data\\level1.pak".to_owned())
+            ),
+            ("data\\level2.pak".to_owned(), ChangeTag::Modified, None),
+            // Empty files are created, not copied
+            ("content\\empty.txt".to_owned(), ChangeTag::Added, None),
+            ("data".to_owned(), ChangeTag::Removed, None),
+            ("data\\level1.pak".to_owned(), ChangeTag::Removed, None),
+            ("data\\empty.txt".to_owned(), ChangeTag::Removed, None),
+        ]
+    );
+}
+
+#[test]
+fn files_with_duplicate_shas_are_copied_from_the_first_one() {
+    let old = b"Size in Bytes,Chunks,SHA,Flags,File Name\n\
+        10,1,abc,0,level1.pak\n\
+        10,1,abc,0,level1_copy.pak\n\
+        10,1,def,0,level2.pak\n";
+    let new = b"Size in Bytes,Chunks,SHA,Flags,File Name\n\
+        10,1,abc,0,level1.pak\n\
+        10,1,abc,0,level1_copy.pak\n\
+        10,1,abc,0,level2.pak\n\
+        10,1,abc,0,level3.pak\n\
+        10,1,ghi,0,level4.pak\n\
+        10,1,ghi,0,level5.pak\n";
+
+    assert_eq!(
+        delta_changes(old, new),
+        vec![
+            (
+                "level2.pak".to_owned(),
+                ChangeTag::Modified,
+                Some("level1.pak".to_owned())
+            ),
+            (
+                "level3.pak".to_owned(),
+                ChangeTag::Added,
+                Some("level1.pak".to_owned())
+            ),
+            // Files that are new in this build are downloaded, even if they share their SHA
+            ("level4.pak".to_owned(), ChangeTag::Added, None),
+            ("level5.pak".to_owned(), ChangeTag::Added, None),
+        ]
+    );
+}
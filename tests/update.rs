@@ -0,0 +1,500 @@
+mod common;
+
+use common::{assert_files, contents, Faults, MockBuild, MockGame, TestEnv};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn update_adds_modifies_moves_and_removes_files() {
+    let old_files = vec![
+        ("game.exe", contents(1, 4096)),
+        ("data/level1.pak", contents(2, 1048576 + 10)),
+        ("data/level2.pak", contents(3, 2048)),
+        ("data/old.pak", contents(4, 100)),
+        ("readme.txt", contents(5, 10)),
+    ];
+    let new_files = vec![
+        // Modified
+        ("game.exe", contents(11, 5000)),
+        // Unchanged
+        ("data/level1.pak", contents(2, 1048576 + 10)),
+        // Moved
+        ("data/levels/level2.pak", contents(3, 2048)),
+        // Added
+        ("data/level3.pak", contents(6, 1048576 * 2)),
+        ("readme.txt", contents(5, 10)),
+    ];
+    let game = MockGame::new(
+        "syberia-ii",
+        vec![
+            MockBuild::new("1.0", 1, &old_files),
+            MockBuild::new("1.1", 2, &new_files),
+        ],
+    );
+    let env = TestEnv::logged_in(vec![game]).await;
+    let install_path = env.install_path("syberia-ii");
+    env.run(&[
+        "install",
+        "syberia-ii",
+        "--path",
+        install_path.to_str().unwrap(),
+        "--version",
+        "1.0",
+    ])
+    .await;
+    assert_files(&install_path, &old_files);
+    let install_requests = env.server.chunk_requests();
+
+    let output = env.run(&["update", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("Updated syberia-ii successfully."),
+        "{}",
+        output.stdout
+    );
+    assert_files(&install_path, &new_files);
+    // Only the modified and added files are downloaded
+    assert_eq!(env.server.chunk_requests() - install_requests, 1 + 2);
+
+    let output = env.run(&["verify", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("syberia-ii passed verification."),
+        "{}",
+        output.stdout
+    );
+    let output = env.run(&["list-updates"]).await;
+    assert!(
+        output.stdout.contains("No available updates"),
+        "{}",
+        output.stdout
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread")]
+async fn updated_windows_files_take_the_case_of_the_installed_build() {
+    let old_files = vec![("Data/level1.pak", contents(1, 100))];
+    let new_files = vec![
+        ("Data/level1.pak", contents(1, 100)),
+        ("data/level2.pak", contents(2, 100)),
+    ];
+    let game = MockGame::new(
+        "syberia-ii",
+        vec![
+            MockBuild::new("1.0", 1, &old_files),
+            MockBuild::new("1.1", 2, &new_files),
+        ],
+    );
+    let env = TestEnv::logged_in(vec![game]).await;
+    let install_path = env.install_path("syberia-ii");
+    env.run(&[
+        "install",
+        "syberia-ii",
+        "--path",
+        install_path.to_str().unwrap(),
+        "--version",
+        "1.0",
+    ])
+    .await;
+
+    let output = env.run(&["update", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("Updated syberia-ii successfully."),
+        "{}",
+        output.stdout
+    );
+    // The update only lists the added file, but it's written next to the installed one
+    assert_files(
+        &install_path,
+        &[
+            ("Data/level1.pak", contents(1, 100)),
+            ("Data/level2.pak", contents(2, 100)),
+        ],
+    );
+    assert!(!install_path.join("data").exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn downgrade_to_previous_version() {
+    let old_files = vec![("game.exe", contents(1, 100)), ("a.txt", contents(2, 10))];
+    let new_files = vec![("game.exe", contents(3, 200)), ("b.txt", contents(4, 10))];
+    let game = MockGame::new(
+        "syberia-ii",
+        vec![
+            MockBuild::new("1.0", 1, &old_files),
+            MockBuild::new("1.1", 2, &new_files),
+        ],
+    );
+    let env = TestEnv::logged_in(vec![game]).await;
+    let install_path = env.install_path("syberia-ii");
+    env.install("syberia-ii").await;
+    assert_files(&install_path, &new_files);
+
+    let output = env.run(&["update", "syberia-ii", "--version", "1.0"]).await;
+    assert!(
+        output.stdout.contains("Updated syberia-ii successfully."),
+        "{}",
+        output.stdout
+    );
+    assert_files(&install_path, &old_files);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn disabled_builds_are_skipped() {
+    let old_files = vec![("game.exe", contents(1, 100))];
+    let new_files = vec![("game.exe", contents(2, 200))];
+    // Only `enabled` decides whether a build is skipped, whatever its status
+    let mut old = MockBuild::new("1.0", 1, &old_files);
+    old.status = 2;
+    let mut pulled = MockBuild::new("1.1", 2, &new_files);
+    pulled.enabled = 0;
+    let game = MockGame::new("syberia-ii", vec![old, pulled]);
+    let env = TestEnv::logged_in(vec![game]).await;
+    let install_path = env.install_path("syberia-ii");
+
+    let output = env.install("syberia-ii").await;
+    assert!(output.stdout.contains("[1.0]"), "{}", output.stdout);
+    assert_files(&install_path, &old_files);
+
+    let output = env.run(&["list-updates"]).await;
+    assert!(
+        output.stdout.contains("No available updates"),
+        "{}",
+        output.stdout
+    );
+    let output = env.run(&["info", "syberia-ii"]).await;
+    assert!(
+        output
+            .stdout
+            .contains("Excluded: disabled by the developer"),
+        "{}",
+        output.stdout
+    );
+
+    let output = env.run(&["update", "syberia-ii", "--version", "1.1"]).await;
+    assert!(
+        output.stdout.contains("Use --include-disabled"),
+        "{}",
+        output.stdout
+    );
+    assert_files(&install_path, &old_files);
+
+    let output = env
+        .run(&[
+            "update",
+            "syberia-ii",
+            "--version",
+            "1.1",
+            "--include-disabled",
+        ])
+        .await;
+    assert!(
+        output.stdout.contains("Updated syberia-ii successfully."),
+        "{}",
+        output.stdout
+    );
+    assert_files(&install_path, &new_files);
+
+    // The latest enabled build is older than the installed one
+    let output = env.run(&["update", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("Build 1.1 is already installed"),
+        "{}",
+        output.stdout
+    );
+    assert_files(&install_path, &new_files);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn held_games_are_not_updated_past_their_version() {
+    let files = |seed| vec![("game.exe", contents(seed, 100))];
+    let game = MockGame::new(
+        "syberia-ii",
+        vec![
+            MockBuild::new("1.0", 1, &files(1)),
+            MockBuild::new("1.1", 2, &files(2)),
+            MockBuild::new("1.2", 3, &files(3)),
+        ],
+    );
+    let env = TestEnv::logged_in(vec![game]).await;
+    let install_path = env.install_path("syberia-ii");
+    env.run(&[
+        "install",
+        "syberia-ii",
+        "--path",
+        install_path.to_str().unwrap(),
+        "--version",
+        "1.0",
+    ])
+    .await;
+
+    let output = env.run(&["hold", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("syberia-ii is held at 1.0"),
+        "{}",
+        output.stdout
+    );
+    let output = env.run(&["list-updates"]).await;
+    assert!(
+        output
+            .stdout
+            .contains("syberia-ii has an update -> 1.2 (held at 1.0)"),
+        "{}",
+        output.stdout
+    );
+    let output = env.run(&["update", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("The game is held at 1.0"),
+        "{}",
+        output.stdout
+    );
+    assert_files(&install_path, &files(1));
+
+    // Updates up to a pinned version are still offered
+    env.run(&["hold", "syberia-ii", "--version", "1.1"]).await;
+    let output = env.run(&["list-updates"]).await;
+    assert!(
+        output.stdout.contains("syberia-ii has an update -> 1.1\n"),
+        "{}",
+        output.stdout
+    );
+    let output = env.run(&["update", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("Updated syberia-ii successfully."),
+        "{}",
+        output.stdout
+    );
+    assert_files(&install_path, &files(2));
+    let output = env.run(&["info", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("Held at 1.1, 1.2 is available"),
+        "{}",
+        output.stdout
+    );
+
+    let output = env.run(&["unhold", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("syberia-ii is no longer held."),
+        "{}",
+        output.stdout
+    );
+    let output = env.run(&["update", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("Updated syberia-ii successfully."),
+        "{}",
+        output.stdout
+    );
+    assert_files(&install_path, &files(3));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn versions_and_diff_between_builds() {
+    let old_files = vec![
+        ("game.exe", contents(1, 4096)),
+        ("data/level1.pak", contents(2, 2048)),
+        ("data/old.pak", contents(3, 100)),
+    ];
+    let new_files = vec![
+        ("game.exe", contents(4, 5000)),
+        ("data/levels/level1.pak", contents(2, 2048)),
+        ("data/level2.pak", contents(5, 1000)),
+    ];
+    let mut pulled = MockBuild::new("1.2", 3, &new_files);
+    pulled.enabled = 0;
+    let game = MockGame::new(
+        "syberia-ii",
+        vec![
+            MockBuild::new("1.0", 1, &old_files),
+            MockBuild::new("1.1", 2, &new_files),
+            pulled,
+        ],
+    );
+    let env = TestEnv::logged_in(vec![game]).await;
+    let install_path = env.install_path("syberia-ii");
+    env.run(&[
+        "install",
+        "syberia-ii",
+        "--path",
+        install_path.to_str().unwrap(),
+        "--version",
+        "1.0",
+    ])
+    .await;
+
+    let output = env.run(&["versions", "syberia-ii"]).await;
+    let lines: Vec<&str> = output
+        .stdout
+        .lines()
+        .filter(|l| l.starts_with('['))
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            "[1.2] 2023-01-03 12:00:00 Windows (disabled by the developer)",
+            "[1.1] 2023-01-02 12:00:00 Windows",
+            "[1.0] 2023-01-01 12:00:00 Windows (installed)",
+        ],
+        "{}",
+        output.stdout
+    );
+
+    let install_requests = env.server.chunk_requests();
+    let output = env.run(&["diff", "syberia-ii", "1.0", "1.1"]).await;
+    for expected in [
+        "Added: data/level2.pak (1 KB)",
+        "Added: data/levels/level1.pak (2 KB, copied from data/level1.pak)",
+        "Modified: game.exe (5 KB)",
+        "Removed: data/old.pak (100 B)",
+        "2 added, 1 modified, 2 removed",
+        // The moved file is copied from the install instead of being downloaded
+        "Download Size: 6 KB",
+    ] {
+        assert!(output.stdout.contains(expected), "{}", output.stdout);
+    }
+    // Nothing is downloaded or changed in the install
+    assert_eq!(env.server.chunk_requests(), install_requests);
+    assert_files(&install_path, &old_files);
+}
+
+fn manifests_path(env: &TestEnv) -> std::path::PathBuf {
+    env.path("data").join("freecarnival").join("manifests")
+}
+
+fn stored_manifests(env: &TestEnv, install_id: &str) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(manifests_path(env).join(install_id))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn manifests_of_old_builds_are_pruned() {
+    let files = |seed| vec![("game.exe", contents(seed, 100))];
+    let game = MockGame::new(
+        "syberia-ii",
+        vec![
+            MockBuild::new("1.0", 1, &files(1)),
+            MockBuild::new("1.1", 2, &files(2)),
+        ],
+    );
+    let env = TestEnv::logged_in(vec![game]).await;
+    let install_path = env.install_path("syberia-ii");
+    env.run(&[
+        "install",
+        "syberia-ii",
+        "--path",
+        install_path.to_str().unwrap(),
+        "--version",
+        "1.0",
+    ])
+    .await;
+    env.run(&["diff", "syberia-ii", "1.0", "1.1"]).await;
+
+    env.run(&["update", "syberia-ii"]).await;
+    assert_eq!(
+        stored_manifests(&env, "syberia-ii"),
+        ["1.1_manifest.csv", "1.1_manifest_chunks.csv"]
+    );
+
+    env.run(&["uninstall", "syberia-ii"]).await;
+    assert!(!manifests_path(&env).join("syberia-ii").exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn manifests_of_queued_installs_are_kept() {
+    let game = MockGame::new(
+        "syberia-ii",
+        vec![MockBuild::new("1.0", 1, &[("game.exe", contents(1, 100))])],
+    );
+    let env = TestEnv::logged_in(vec![game]).await;
+    env.server.set_faults(Faults {
+        corrupt_chunks: true,
+        ..Default::default()
+    });
+
+    // The failed install stays in the queue, so it can be resumed with its manifests
+    env.install("syberia-ii").await;
+    env.run(&["manifest", "prune"]).await;
+    assert_eq!(
+        stored_manifests(&env, "syberia-ii"),
+        ["1.0_manifest.csv", "1.0_manifest_chunks.csv"]
+    );
+
+    env.run(&["queue", "remove", "syberia-ii"]).await;
+    env.run(&["manifest", "prune"]).await;
+    assert!(!manifests_path(&env).join("syberia-ii").exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn missing_manifests_are_fetched_again() {
+    let old_files = vec![("game.exe", contents(1, 100))];
+    let new_files = vec![("game.exe", contents(2, 200))];
+    let game = MockGame::new(
+        "syberia-ii",
+        vec![
+            MockBuild::new("1.0", 1, &old_files),
+            MockBuild::new("1.1", 2, &new_files),
+        ],
+    );
+    let env = TestEnv::logged_in(vec![game]).await;
+    let install_path = env.install_path("syberia-ii");
+    env.run(&[
+        "install",
+        "syberia-ii",
+        "--path",
+        install_path.to_str().unwrap(),
+        "--version",
+        "1.0",
+    ])
+    .await;
+
+    std::fs::remove_dir_all(manifests_path(&env)).unwrap();
+    let output = env.run(&["verify", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("Fetching it again"),
+        "{}",
+        output.stdout
+    );
+    assert!(
+        output.stdout.contains("syberia-ii passed verification."),
+        "{}",
+        output.stdout
+    );
+
+    std::fs::remove_dir_all(manifests_path(&env)).unwrap();
+    let output = env.run(&["update", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("Updated syberia-ii successfully."),
+        "{}",
+        output.stdout
+    );
+    assert_files(&install_path, &new_files);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn manifests_in_config_dir_are_moved() {
+    let files = vec![("game.exe", contents(1, 100))];
+    let game = MockGame::new("syberia-ii", vec![MockBuild::new("1.0", 1, &files)]);
+    let env = TestEnv::logged_in(vec![game]).await;
+    env.install("syberia-ii").await;
+
+    // Older versions stored manifests in the config directory
+    let old_path = env.path("config").join("freecarnival").join("manifests");
+    std::fs::rename(manifests_path(&env), &old_path).unwrap();
+
+    let output = env.run(&["verify", "syberia-ii"]).await;
+    assert!(
+        output.stdout.contains("syberia-ii passed verification."),
+        "{}",
+        output.stdout
+    );
+    assert!(
+        !output.stdout.contains("Fetching it again"),
+        "{}",
+        output.stdout
+    );
+    assert!(!old_path.exists());
+    assert_eq!(
+        stored_manifests(&env, "syberia-ii"),
+        ["1.0_manifest.csv", "1.0_manifest_chunks.csv"]
+    );
+}